@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::images::ImageProcessor;
+
+/// Fingerprinted files are served under this path of the site, like
+/// `files/images/cat.3f9a1c0b2d.png`
+pub const FINGERPRINTED_PREFIX: &str = "files/";
+
+/// How many hex digits of the content hash go into a fingerprinted file name
+const HASH_LENGTH: usize = 10;
+
+/// Content hashes of the files from `files_directory`. A hash is recomputed when the file's
+/// modification time changes, or when the file is forgotten (after the watcher noticed a change)
+pub struct Assets {
+    hashes: HashMap<PathBuf, (SystemTime, String)>,
+    pub images: ImageProcessor,
+}
+
+impl Assets {
+    pub fn new(images: ImageProcessor) -> Self {
+        Self {
+            hashes: HashMap::new(),
+            images,
+        }
+    }
+
+    /// Returns `None` if the file cannot be read
+    pub fn hash_of(&mut self, path: &Path) -> Option<String> {
+        let modification_time = fs::metadata(path).and_then(|metadata| metadata.modified());
+        let Ok(modification_time) = modification_time else {
+            self.hashes.remove(path);
+            return None;
+        };
+        if let Some((known_modification_time, hash)) = self.hashes.get(path) {
+            if *known_modification_time == modification_time {
+                return Some(hash.clone());
+            }
+        }
+        let contents = fs::read(path).ok()?;
+        let mut hash = String::with_capacity(HASH_LENGTH);
+        for byte in &Sha256::digest(contents)[..HASH_LENGTH / 2] {
+            write!(hash, "{byte:02x}").unwrap();
+        }
+        self.hashes
+            .insert(path.to_owned(), (modification_time, hash.clone()));
+        Some(hash)
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.hashes.remove(path);
+    }
+}
+
+/// Splits the file name into the stem and the extension (with the dot). Leading dots are not
+/// extensions
+fn split_extension(file_name: &str) -> (&str, &str) {
+    match file_name.rfind('.') {
+        Some(dot_index) if dot_index > 0 => file_name.split_at(dot_index),
+        _ => (file_name, ""),
+    }
+}
+
+/// `images/cat.png` with hash `3f9a1c0b2d` -> `files/images/cat.3f9a1c0b2d.png`
+pub fn fingerprinted_path(relative_path: &str, hash: &str) -> String {
+    let (directory, file_name) = match relative_path.rsplit_once('/') {
+        Some((directory, file_name)) => (format!("{directory}/"), file_name),
+        None => (String::new(), relative_path),
+    };
+    let (stem, extension) = split_extension(file_name);
+    format!("{FINGERPRINTED_PREFIX}{directory}{stem}.{hash}{extension}")
+}
+
+pub fn is_hash(candidate: &str) -> bool {
+    candidate.len() == HASH_LENGTH && candidate.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// `files/images/cat.3f9a1c0b2d.png` -> (`images/cat.png`, `3f9a1c0b2d`). Returns `None` if the
+/// path is not a fingerprinted one
+pub fn parse_fingerprinted_path(path: &str) -> Option<(String, &str)> {
+    let path = path.strip_prefix(FINGERPRINTED_PREFIX)?;
+    let (directory, file_name) = match path.rsplit_once('/') {
+        Some((directory, file_name)) => (format!("{directory}/"), file_name),
+        None => (String::new(), path),
+    };
+    let (stem, extension) = split_extension(file_name);
+    // `Makefile.3f9a1c0b2d`, there was no extension
+    if let Some(hash) = extension.strip_prefix('.').filter(|hash| is_hash(hash)) {
+        return Some((format!("{directory}{stem}"), hash));
+    }
+    let (stem, hash) = split_extension(stem);
+    let hash = hash.strip_prefix('.').filter(|hash| is_hash(hash))?;
+    Some((format!("{directory}{stem}{extension}"), hash))
+}
@@ -0,0 +1,198 @@
+use std::fmt::Write;
+
+use actix_web::http::Uri;
+use serde::{Deserialize, Serialize};
+
+/// Where a site is reachable from the outside: either a full URL (`https://example.com/blog/`) or
+/// just a path (`/blog/`). Every internal link is built from it, so the site can be served under a
+/// subpath behind a proxy.
+#[derive(Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct BaseUrl {
+    full: String,
+    origin: Option<String>,
+    /// Always starts and ends with `/`
+    path_prefix: String,
+}
+
+impl TryFrom<String> for BaseUrl {
+    type Error = String;
+
+    fn try_from(full: String) -> Result<Self, Self::Error> {
+        let uri: Uri = full
+            .parse()
+            .map_err(|error| format!("`{full}` is not a valid base URL: {error}"))?;
+        let origin = match (uri.scheme_str(), uri.authority()) {
+            (Some(scheme), Some(authority)) => Some(format!("{scheme}://{authority}")),
+            (None, None) => None,
+            _ => {
+                return Err(format!(
+                    "`{full}` is not a valid base URL: it should be either a full URL or a path"
+                ))
+            }
+        };
+        if uri.query().is_some() {
+            return Err(format!(
+                "`{full}` is not a valid base URL: it shouldn't have a query"
+            ));
+        }
+        let path_prefix = normalize_path_prefix(uri.path()).ok_or_else(|| {
+            format!("`{full}` is not a valid base URL: its path shouldn't have `.` or `..`")
+        })?;
+        Ok(Self {
+            full,
+            origin,
+            path_prefix,
+        })
+    }
+}
+
+/// `a//b` -> `/a/b/`, and `""` -> `/`. The paths with `.` or `..` are rejected, so the links
+/// cannot lead outside of the prefix
+fn normalize_path_prefix(path: &str) -> Option<String> {
+    let mut path_prefix = "/".to_owned();
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        if segment == "." || segment == ".." {
+            return None;
+        }
+        path_prefix.push_str(segment);
+        path_prefix.push('/');
+    }
+    Some(path_prefix)
+}
+
+impl From<BaseUrl> for String {
+    fn from(base_url: BaseUrl) -> Self {
+        base_url.full
+    }
+}
+
+impl Default for BaseUrl {
+    fn default() -> Self {
+        Self::try_from("/".to_owned()).unwrap()
+    }
+}
+
+impl BaseUrl {
+    /// `/blog/` for `https://example.com/blog`
+    pub fn path_prefix(&self) -> &str {
+        &self.path_prefix
+    }
+
+    /// Link to a page of the site, `path` being relative to the site's root (`""` is the index).
+    /// Every internal link should be made by this method
+    pub fn link(&self, path: &str) -> String {
+        let mut link = self.path_prefix.clone();
+        encode_path(path, &mut link);
+        link
+    }
+
+    /// Same as [`BaseUrl::link`], but with the scheme and the host, if they are known
+    pub fn absolute(&self, path: &str) -> Option<String> {
+        self.origin
+            .as_ref()
+            .map(|origin| format!("{}{}", origin, self.link(path)))
+    }
+
+    /// The same site behind a proxy that serves it under `prefix` (from `X-Forwarded-Prefix`),
+    /// which replaces the path of the URL
+    pub fn with_forwarded_prefix(&self, prefix: &str) -> Result<Self, String> {
+        let path_prefix = normalize_path_prefix(prefix).ok_or_else(|| {
+            format!("forwarded prefix `{prefix}` shouldn't have `.` or `..` in it")
+        })?;
+        Ok(Self {
+            full: format!(
+                "{}{path_prefix}",
+                self.origin.as_deref().unwrap_or_default()
+            ),
+            origin: self.origin.clone(),
+            path_prefix,
+        })
+    }
+
+    /// Turns a request path into a path relative to the site's root, or returns `None` if the
+    /// request is not for this site
+    pub fn strip_prefix<'path>(&self, request_path: &'path str) -> Option<&'path str> {
+        request_path.strip_prefix(&self.path_prefix[..])
+    }
+}
+
+/// Percent-encodes the characters that would break a link (or end up being treated as a part of
+/// the query or the fragment), leaving the slashes as they are
+fn encode_path(path: &str, output: &mut String) {
+    for character in path.chars() {
+        if character.is_ascii_alphanumeric() || "/-._~!$&'()*+,;=:@".contains(character) {
+            output.push(character);
+        } else {
+            let mut buffer = [0; 4];
+            for byte in character.encode_utf8(&mut buffer).bytes() {
+                write!(output, "%{byte:02X}").unwrap();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BaseUrl;
+
+    fn base_url(full: &str) -> BaseUrl {
+        BaseUrl::try_from(full.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn normalizes_prefixes() {
+        for (full, path_prefix) in [
+            ("/", "/"),
+            ("https://example.com", "/"),
+            ("/blog", "/blog/"),
+            ("/blog/", "/blog/"),
+            ("//my//blog//", "/my/blog/"),
+            ("https://example.com/my/blog", "/my/blog/"),
+        ] {
+            assert_eq!(base_url(full).path_prefix(), path_prefix, "{full}");
+        }
+        assert_eq!(base_url("/blog").link("a b/c?"), "/blog/a%20b/c%3F");
+        assert_eq!(base_url("/blog").strip_prefix("/blog/post"), Some("post"));
+        assert_eq!(base_url("/blog").strip_prefix("/other/post"), None);
+        assert_eq!(
+            base_url("https://example.com/blog")
+                .absolute("post")
+                .as_deref(),
+            Some("https://example.com/blog/post")
+        );
+        assert_eq!(base_url("/blog").absolute("post"), None);
+    }
+
+    #[test]
+    fn rejects_dots_and_queries() {
+        for full in [
+            "/blog/../admin",
+            "/./blog",
+            "https://example.com/..",
+            "/blog?a=b",
+        ] {
+            assert!(BaseUrl::try_from(full.to_owned()).is_err(), "{full}");
+        }
+    }
+
+    #[test]
+    fn forwarded_prefixes_replace_the_path() {
+        let configured = base_url("https://example.com/blog/");
+        let forwarded = configured.with_forwarded_prefix("/proxied/blog").unwrap();
+        assert_eq!(forwarded.path_prefix(), "/proxied/blog/");
+        assert_eq!(forwarded.link("post"), "/proxied/blog/post");
+        assert_eq!(
+            forwarded.absolute("post").as_deref(),
+            Some("https://example.com/proxied/blog/post")
+        );
+        assert_eq!(String::from(forwarded), "https://example.com/proxied/blog/");
+        for (prefix, path_prefix) in [("", "/"), ("/", "/"), ("notes/", "/notes/")] {
+            let forwarded = base_url("/blog/").with_forwarded_prefix(prefix).unwrap();
+            assert_eq!(forwarded.path_prefix(), path_prefix, "{prefix}");
+            assert_eq!(String::from(forwarded), path_prefix, "{prefix}");
+        }
+        assert!(configured.with_forwarded_prefix("/blog/../admin").is_err());
+        assert!(configured.with_forwarded_prefix("..").is_err());
+    }
+}
@@ -0,0 +1,471 @@
+use std::{collections::HashMap, iter::Peekable, str::CharIndices};
+
+/// A work from a BibTeX file. The field names are in lowercase, and the values are still LaTeX
+pub struct Entry {
+    /// `article`, `book` and so on
+    pub kind: String,
+    pub fields: HashMap<String, String>,
+}
+
+/// A person from an `author` or an `editor` field
+pub struct Name {
+    pub first: String,
+    pub last: String,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+struct Parser<'text> {
+    text: &'text str,
+    characters: Peekable<CharIndices<'text>>,
+    /// `@string{name = "value"}`
+    macros: HashMap<String, String>,
+}
+
+impl Parser<'_> {
+    fn line(&mut self) -> usize {
+        let index = self
+            .characters
+            .peek()
+            .map_or(self.text.len(), |(index, _character)| *index);
+        self.text[..index].matches('\n').count() + 1
+    }
+
+    fn error(&mut self, message: &str) -> String {
+        format!("line {}: {}", self.line(), message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .characters
+            .next_if(|(_index, character)| character.is_whitespace())
+            .is_some()
+        {}
+    }
+
+    fn identifier(&mut self) -> String {
+        let mut identifier = String::new();
+        while let Some((_index, character)) = self.characters.next_if(|(_index, character)| {
+            character.is_alphanumeric() || "_-:.+/".contains(*character)
+        }) {
+            identifier.push(character);
+        }
+        identifier
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.characters.next() {
+            Some((_index, character)) if character == expected => Ok(()),
+            _ => Err(self.error(&format!("expected `{expected}`"))),
+        }
+    }
+
+    /// The contents of `{...}` (after the opening brace), with the nested braces kept
+    fn braced(&mut self) -> Result<String, String> {
+        let mut depth = 0;
+        let mut value = String::new();
+        for (_index, character) in self.characters.by_ref() {
+            match character {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(value),
+                '}' => depth -= 1,
+                _ => (),
+            }
+            value.push(character);
+        }
+        Err(self.error("a `{` is not closed"))
+    }
+
+    /// The contents of `"..."` (after the opening quote). The quotes in braces don't end it
+    fn quoted(&mut self) -> Result<String, String> {
+        let mut depth = 0;
+        let mut value = String::new();
+        for (_index, character) in self.characters.by_ref() {
+            match character {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '"' if depth == 0 => return Ok(value),
+                _ => (),
+            }
+            value.push(character);
+        }
+        Err(self.error("a `\"` is not closed"))
+    }
+
+    /// `{braced}`, `"quoted"`, `1984` or `macro`, joined with `#`
+    fn value(&mut self) -> Result<String, String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.characters.peek() {
+                Some((_index, '{')) => {
+                    self.characters.next();
+                    value.push_str(&self.braced()?);
+                }
+                Some((_index, '"')) => {
+                    self.characters.next();
+                    value.push_str(&self.quoted()?);
+                }
+                _ => {
+                    let name = self.identifier();
+                    if name.is_empty() {
+                        return Err(self.error("expected a value"));
+                    }
+                    let month = MONTHS.iter().position(|month| name == *month);
+                    match (self.macros.get(&name.to_lowercase()), month) {
+                        (Some(expansion), _) => value.push_str(expansion),
+                        (None, Some(month)) => value.push_str(&(month + 1).to_string()),
+                        (None, None) => value.push_str(&name),
+                    }
+                }
+            }
+            self.skip_whitespace();
+            if self
+                .characters
+                .next_if(|(_index, character)| *character == '#')
+                .is_none()
+            {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// `name = value` pairs until the closing character
+    fn fields(&mut self, closing: char) -> Result<HashMap<String, String>, String> {
+        let mut fields = HashMap::new();
+        loop {
+            self.skip_whitespace();
+            if self
+                .characters
+                .next_if(|(_index, character)| *character == closing)
+                .is_some()
+            {
+                return Ok(fields);
+            }
+            let name = self.identifier().to_lowercase();
+            if name.is_empty() {
+                return Err(self.error("expected a field name"));
+            }
+            self.expect('=')?;
+            let value = self.value()?;
+            fields.insert(name, value);
+            self.skip_whitespace();
+            match self.characters.next() {
+                Some((_index, ',')) => (),
+                Some((_index, character)) if character == closing => return Ok(fields),
+                _ => return Err(self.error("expected `,` after a field")),
+            }
+        }
+    }
+
+    fn entries(mut self) -> Result<HashMap<String, Entry>, String> {
+        let mut entries = HashMap::new();
+        // Everything outside of the entries is a comment
+        while self.characters.any(|(_index, character)| character == '@') {
+            let kind = self.identifier().to_lowercase();
+            self.skip_whitespace();
+            let closing = match self.characters.next() {
+                Some((_index, '{')) => '}',
+                Some((_index, '(')) => ')',
+                _ => return Err(self.error(&format!("`@{kind}` is not followed by `{{`"))),
+            };
+            match kind.as_str() {
+                "comment" => {
+                    self.braced()?;
+                }
+                "preamble" => {
+                    self.value()?;
+                    self.expect(closing)?;
+                }
+                "string" => {
+                    let fields = self.fields(closing)?;
+                    self.macros.extend(fields);
+                }
+                _ => {
+                    self.skip_whitespace();
+                    let mut key = String::new();
+                    while let Some((_index, character)) = self
+                        .characters
+                        .next_if(|(_index, character)| *character != ',' && *character != closing)
+                    {
+                        key.push(character);
+                    }
+                    let key = key.trim().to_owned();
+                    if key.is_empty() {
+                        return Err(self.error(&format!("`@{kind}` has no key")));
+                    }
+                    let fields = if self
+                        .characters
+                        .next_if(|(_index, character)| *character == ',')
+                        .is_some()
+                    {
+                        self.fields(closing)?
+                    } else {
+                        self.characters.next();
+                        HashMap::new()
+                    };
+                    entries.insert(key, Entry { kind, fields });
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Entries of a BibTeX file by their keys
+pub fn parse(text: &str) -> Result<HashMap<String, Entry>, String> {
+    Parser {
+        text,
+        characters: text.char_indices().peekable(),
+        macros: HashMap::new(),
+    }
+    .entries()
+}
+
+/// Combining characters of the LaTeX accents (`\'e` -> `é`)
+const fn accent(command: char) -> Option<char> {
+    match command {
+        '\'' => Some('\u{301}'),
+        '`' => Some('\u{300}'),
+        '^' => Some('\u{302}'),
+        '"' => Some('\u{308}'),
+        '~' => Some('\u{303}'),
+        '=' => Some('\u{304}'),
+        '.' => Some('\u{307}'),
+        'c' => Some('\u{327}'),
+        'v' => Some('\u{30C}'),
+        'u' => Some('\u{306}'),
+        'H' => Some('\u{30B}'),
+        _ => None,
+    }
+}
+
+/// LaTeX -> text: the braces are removed, and the accents, the escapes, the dashes and the
+/// non-breaking spaces become characters
+pub fn plain(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut characters = value.chars().peekable();
+    // The combining character of an accent waits for its letter
+    let mut pending_accent = None;
+    while let Some(character) = characters.next() {
+        let mut output = None;
+        match character {
+            '{' | '}' => (),
+            '\\' => match characters.next() {
+                Some(command) if accent(command).is_some() => {
+                    pending_accent = accent(command);
+                    // `\c c` and `\v{s}`
+                    while characters.next_if(|next| *next == ' ').is_some() {}
+                    continue;
+                }
+                Some(command) if command.is_ascii_alphabetic() => {
+                    let mut name = String::from(command);
+                    while let Some(next) = characters.next_if(char::is_ascii_alphabetic) {
+                        name.push(next);
+                    }
+                    while characters.next_if(|next| *next == ' ').is_some() {}
+                    let replacement = match name.as_str() {
+                        "ss" => "ß",
+                        "ae" => "æ",
+                        "AE" => "Æ",
+                        "oe" => "œ",
+                        "OE" => "Œ",
+                        "o" => "ø",
+                        "O" => "Ø",
+                        "aa" => "å",
+                        "AA" => "Å",
+                        "l" => "ł",
+                        "L" => "Ł",
+                        "i" => "ı",
+                        "TeX" => "TeX",
+                        "LaTeX" => "LaTeX",
+                        "BibTeX" => "BibTeX",
+                        // `\emph`, `\textbf` and the like only format their arguments
+                        _ => "",
+                    };
+                    text.push_str(replacement);
+                }
+                Some(escaped) => output = Some(escaped),
+                None => (),
+            },
+            '~' => output = Some('\u{A0}'),
+            '-' if characters.next_if_eq(&'-').is_some() => {
+                output = Some(if characters.next_if_eq(&'-').is_some() {
+                    '—'
+                } else {
+                    '–'
+                });
+            }
+            character if character.is_whitespace() => {
+                if !text.ends_with(' ') {
+                    output = Some(' ');
+                }
+            }
+            character => output = Some(character),
+        }
+        if let Some(output) = output {
+            text.push(output);
+            if let Some(accent) = pending_accent.take() {
+                text.push(accent);
+            }
+        }
+    }
+    text.trim().to_owned()
+}
+
+/// Splits at `separator` outside of the braces
+fn split_outside_braces<'value>(value: &'value str, separator: &str) -> Vec<&'value str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (index, character) in value.char_indices() {
+        let is_separator = || {
+            value[index..]
+                .get(..separator.len())
+                .is_some_and(|part| part.eq_ignore_ascii_case(separator))
+        };
+        match character {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ if depth == 0 && index >= start && is_separator() => {
+                parts.push(&value[start..index]);
+                start = index + separator.len();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// `Knuth, Donald E. and Leslie Lamport and others`. The `others` become `None`
+pub fn names(value: &str) -> Vec<Option<Name>> {
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    split_outside_braces(&value, " and ")
+        .into_iter()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            if name == "others" {
+                return None;
+            }
+            let parts = split_outside_braces(name, ",");
+            if parts.len() > 1 {
+                // `Last, First` or `Last, Jr., First`
+                return Some(Name {
+                    first: plain(parts[parts.len() - 1]),
+                    last: plain(parts[0]),
+                });
+            }
+            let words: Vec<_> = split_outside_braces(name, " ")
+                .into_iter()
+                .filter(|word| !word.is_empty())
+                .collect();
+            let (last, first) = words.split_last().unwrap();
+            Some(Name {
+                first: plain(&first.join(" ")),
+                last: plain(last),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{names, parse, plain};
+
+    #[test]
+    fn expands_strings_and_months() {
+        let entries = parse(
+            r#"Comments are ignored.
+            @string{acm = "Communications of the {ACM}"}
+            @STRING(vol = {21})
+            @comment{@article{ignored, title = {Not here}}}
+            @Article{lamport78,
+                Title = {Time, Clocks, and the Ordering of Events},
+                journal = acm # ", " # "vol. " # vol,
+                month = jul,
+                year = 1978
+            }
+            @book{knuth84, month = "sep" # { } # dec}"#,
+        )
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        let lamport = &entries["lamport78"];
+        assert_eq!(lamport.kind, "article");
+        assert_eq!(
+            lamport.fields["title"],
+            "Time, Clocks, and the Ordering of Events"
+        );
+        assert_eq!(
+            lamport.fields["journal"],
+            "Communications of the {ACM}, vol. 21"
+        );
+        assert_eq!(lamport.fields["month"], "7");
+        assert_eq!(lamport.fields["year"], "1978");
+        // The months in quotes are text
+        assert_eq!(entries["knuth84"].fields["month"], "sep 12");
+    }
+
+    #[test]
+    fn reports_the_lines_of_errors() {
+        assert_eq!(
+            parse("@book{knuth84,\n  title = {The \\TeX book}\n  year = 1984}")
+                .err()
+                .as_deref(),
+            Some("line 3: expected `,` after a field")
+        );
+        assert_eq!(
+            parse("@book{knuth84, title = {The \\TeX book")
+                .err()
+                .as_deref(),
+            Some("line 1: a `{` is not closed")
+        );
+        assert_eq!(
+            parse("\n@book{, title = x}").err().as_deref(),
+            Some("line 2: `@book` has no key")
+        );
+    }
+
+    #[test]
+    fn turns_latex_into_text() {
+        // The accents are combining characters after their letters
+        assert_eq!(
+            plain(r"Erd{\H o}s and Ch{\'e}b{\v{s}}ev"),
+            "Erdo\u{30B}s and Che\u{301}bs\u{30C}ev"
+        );
+        assert_eq!(plain(r#"Gau\ss{} and G\"{o}del"#), "Gauß and Go\u{308}del");
+        assert_eq!(plain(r"Fran\c cois"), "Franc\u{327}ois");
+        assert_eq!(plain(r"The {\TeX}book and \LaTeX"), "The TeXbook and LaTeX");
+        assert_eq!(
+            plain(r"\emph{Pages} 1--10 --- 50\% off"),
+            "Pages 1–10 — 50% off"
+        );
+        assert_eq!(plain("Donald~E. \n  Knuth"), "Donald\u{A0}E. Knuth");
+    }
+
+    #[test]
+    fn splits_names() {
+        let names: Vec<_> = names(
+            "Knuth, Donald E. and Leslie Lamport and {Barnes and Noble} and \
+             de la Fontaine, Jr., Jean AND others",
+        )
+        .into_iter()
+        .map(|name| name.map(|name| (name.first, name.last)))
+        .collect();
+        let name = |first: &str, last: &str| Some((first.to_owned(), last.to_owned()));
+        assert_eq!(
+            names,
+            [
+                name("Donald E.", "Knuth"),
+                name("Leslie", "Lamport"),
+                name("", "Barnes and Noble"),
+                name("Jean", "de la Fontaine"),
+                None,
+            ]
+        );
+    }
+}
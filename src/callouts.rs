@@ -0,0 +1,136 @@
+use std::collections::VecDeque;
+
+use pulldown_cmark::{Event, Tag};
+
+/// Kinds of the callouts (like on GitHub), their default titles and their icons. The icons are
+/// text, so the themes can color them
+const KINDS: &[(&str, &str, &str)] = &[
+    ("note", "Note", "\u{2139}\u{FE0E}"),
+    ("tip", "Tip", "\u{2605}\u{FE0E}"),
+    ("important", "Important", "\u{2757}\u{FE0E}"),
+    ("warning", "Warning", "\u{26A0}\u{FE0E}"),
+    ("caution", "Caution", "\u{26D4}\u{FE0E}"),
+];
+
+/// `[!WARNING] Custom title` -> the kind and the rest of the line
+fn parse_marker(text: &str) -> Option<(&'static (&'static str, &'static str, &'static str), &str)> {
+    let rest = text.trim_start().strip_prefix("[!")?;
+    let (kind, rest) = rest.split_once(']')?;
+    let kind = KINDS
+        .iter()
+        .find(|(name, _title, _icon)| name.eq_ignore_ascii_case(kind))?;
+    Some((kind, rest.trim_start()))
+}
+
+/// Turns the block quotes that start with `[!NOTE]`, `[!TIP]`, `[!IMPORTANT]`, `[!WARNING]` or
+/// `[!CAUTION]` into `<aside class="callout callout-note">` and so on, with a title (the rest of
+/// the marker's line, or the name of the kind) and an icon. The rest of the quote stays as it is
+pub fn transform(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut transformed = Vec::with_capacity(events.len());
+    // Whether the block quotes that are open are callouts
+    let mut quotes = Vec::new();
+    let mut events = VecDeque::from(events);
+    while let Some(event) = events.pop_front() {
+        match event {
+            Event::Start(Tag::BlockQuote) => {
+                let marker = match (events.front(), events.get(1)) {
+                    (Some(Event::Start(Tag::Paragraph)), Some(Event::Text(text))) => {
+                        parse_marker(text).map(|(kind, title)| (kind, title.to_owned()))
+                    }
+                    _ => None,
+                };
+                let Some(((kind, default_title, icon), title)) = marker else {
+                    quotes.push(false);
+                    transformed.push(event);
+                    continue;
+                };
+                quotes.push(true);
+                // The paragraph and the marker
+                events.drain(..2);
+                transformed.push(Event::Html(
+                    format!(
+                        r#"<aside class="callout callout-{kind}"><p class="callout-title"><span class="callout-icon" aria-hidden="true">{icon}</span> "#
+                    )
+                    .into(),
+                ));
+                let is_title_line_empty = matches!(
+                    events.front(),
+                    Some(Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph)) | None
+                );
+                if title.trim().is_empty() && is_title_line_empty {
+                    transformed.push(Event::Text((*default_title).into()));
+                } else if !title.is_empty() {
+                    transformed.push(Event::Text(title.into()));
+                }
+                // The rest of the title's line can have emphasis, links and so on
+                while let Some(event) = events.pop_front() {
+                    if matches!(
+                        event,
+                        Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph)
+                    ) {
+                        events.push_front(event);
+                        break;
+                    }
+                    transformed.push(event);
+                }
+                transformed.push(Event::Html("</p>\n".into()));
+                match events.pop_front() {
+                    Some(Event::SoftBreak | Event::HardBreak) => {
+                        transformed.push(Event::Start(Tag::Paragraph));
+                    }
+                    Some(Event::End(Tag::Paragraph)) | None => (),
+                    Some(event) => transformed.push(event),
+                }
+            }
+            Event::End(Tag::BlockQuote) if quotes.pop() == Some(true) => {
+                transformed.push(Event::Html("</aside>\n".into()));
+            }
+            event => transformed.push(event),
+        }
+    }
+    transformed
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::{html, Event, Parser};
+
+    use super::transform;
+
+    /// The text is joined like the article compiler does it
+    fn render(markdown: &str) -> String {
+        let mut events: Vec<Event> = Vec::new();
+        for event in Parser::new(markdown) {
+            match (events.last_mut(), event) {
+                (Some(Event::Text(text)), Event::Text(more)) => {
+                    *text = format!("{text}{more}").into();
+                }
+                (_, event) => events.push(event),
+            }
+        }
+        let mut output = String::new();
+        html::push_html(&mut output, transform(events).into_iter());
+        output
+    }
+
+    #[test]
+    fn turns_marked_quotes_into_callouts() {
+        assert_eq!(
+            render("> [!TIP] Use *this*\n> Body\n\n> Plain quote"),
+            "<aside class=\"callout callout-tip\"><p class=\"callout-title\">\
+             <span class=\"callout-icon\" aria-hidden=\"true\">\u{2605}\u{FE0E}</span> \
+             Use <em>this</em></p>\n<p>Body</p>\n</aside>\n\
+             <blockquote>\n<p>Plain quote</p>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn nested_quotes_close_their_own_tags() {
+        assert_eq!(
+            render("> [!NOTE]\n> > [!unknown]\n> > inner"),
+            "<aside class=\"callout callout-note\"><p class=\"callout-title\">\
+             <span class=\"callout-icon\" aria-hidden=\"true\">\u{2139}\u{FE0E}</span> \
+             Note</p>\n<blockquote>\n<p>[!unknown]\ninner</p>\n</blockquote>\n</aside>\n"
+        );
+    }
+}
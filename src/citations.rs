@@ -0,0 +1,546 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bibtex::{self, Entry, Name},
+    diagnostics::Problem,
+    shortcodes::Piece,
+};
+
+/// How the `[@key]` citations and the bibliography look
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CitationStyle {
+    /// `[1]`, with the references in the order of the citations
+    #[default]
+    Numbered,
+    /// `(Knuth 1984)`, with the references sorted by their authors
+    AuthorYear,
+}
+
+/// A part of `[see @knuth84, p. 33; @lamport94]`
+struct Reference<'text> {
+    prefix: &'text str,
+    key: &'text str,
+    locator: &'text str,
+}
+
+fn is_key_character(character: char) -> bool {
+    character.is_alphanumeric() || "_-:.+/".contains(character)
+}
+
+/// `see @knuth84, p. 33`. The `@` starts the part or follows a space, so the emails are not
+/// citations
+fn parse_reference(part: &str) -> Option<Reference<'_>> {
+    let at = part
+        .char_indices()
+        .find(|&(index, character)| {
+            character == '@' && part[..index].chars().last().is_none_or(char::is_whitespace)
+        })?
+        .0;
+    let rest = &part[at + 1..];
+    // The sentence's punctuation after the key isn't a part of it
+    let length = rest
+        .find(|character| !is_key_character(character))
+        .unwrap_or(rest.len());
+    let key = rest[..length].trim_end_matches(['.', ':']);
+    if key.is_empty() {
+        return None;
+    }
+    let locator = rest[key.len()..].trim();
+    Some(Reference {
+        prefix: part[..at].trim(),
+        key,
+        locator: locator.strip_prefix(',').unwrap_or(locator).trim(),
+    })
+}
+
+/// `[@key]`, `[see @key, p. 12]` or `[@first; @second]`: contents of the brackets and the length
+/// of the citation
+fn citation_at(text: &str) -> Option<(usize, Vec<Reference<'_>>)> {
+    let contents = text.strip_prefix('[')?;
+    let length = contents.find(['[', ']'])?;
+    if !contents[length..].starts_with(']') || contents[length + 1..].starts_with(['(', '[']) {
+        return None;
+    }
+    let references = contents[..length]
+        .split(';')
+        .map(parse_reference)
+        .collect::<Option<Vec<_>>>()?;
+    Some((length + 2, references))
+}
+
+fn year(entry: &Entry) -> String {
+    entry
+        .fields
+        .get("year")
+        .map(|year| bibtex::plain(year))
+        .or_else(|| {
+            let date = entry.fields.get("date")?;
+            Some(date.chars().take(4).collect())
+        })
+        .unwrap_or_else(|| "n.d.".to_owned())
+}
+
+/// Authors, or the editors if there are no authors
+fn people(entry: &Entry) -> (Vec<Option<Name>>, bool) {
+    match (entry.fields.get("author"), entry.fields.get("editor")) {
+        (Some(authors), _) => (bibtex::names(authors), false),
+        (None, Some(editors)) => (bibtex::names(editors), true),
+        (None, None) => (Vec::new(), false),
+    }
+}
+
+fn title(entry: &Entry) -> String {
+    entry
+        .fields
+        .get("title")
+        .map(|title| bibtex::plain(title))
+        .unwrap_or_default()
+}
+
+/// `Knuth`, `Knuth and Lamport` or `Knuth et al.`
+fn short_authors(entry: &Entry) -> String {
+    let (people, _is_editors) = people(entry);
+    let last_name = |name: &Option<Name>| {
+        name.as_ref()
+            .map_or_else(|| "others".to_owned(), |name| name.last.clone())
+    };
+    match &people[..] {
+        [] => title(entry),
+        [only] => last_name(only),
+        [first, second] if second.is_some() => {
+            format!("{} and {}", last_name(first), last_name(second))
+        }
+        [first, ..] => format!("{} et al.", last_name(first)),
+    }
+}
+
+/// `Donald E. Knuth and Leslie Lamport`, or `Donald E. Knuth, Leslie Lamport et al.`
+fn full_names(people: &[Option<Name>]) -> String {
+    let names: Vec<_> = people
+        .iter()
+        .flatten()
+        .map(|name| {
+            if name.first.is_empty() {
+                name.last.clone()
+            } else {
+                format!("{} {}", name.first, name.last)
+            }
+        })
+        .collect();
+    if people.last().is_some_and(Option::is_none) {
+        return format!("{} et al.", names.join(", "));
+    }
+    match &names[..] {
+        [] => String::new(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+fn field(entry: &Entry, name: &str) -> Option<String> {
+    entry
+        .fields
+        .get(name)
+        .map(|value| bibtex::plain(value))
+        .filter(|value| !value.is_empty())
+}
+
+/// HTML of a reference in the bibliography
+fn reference_html(entry: &Entry, style: CitationStyle) -> String {
+    let encode = |text: &str| html_escape::encode_text(text).into_owned();
+    let mut parts = Vec::new();
+    let (people, is_editors) = people(entry);
+    let mut authors = encode(&full_names(&people));
+    if is_editors && !authors.is_empty() {
+        authors.push_str(" (ed.)");
+    }
+    match style {
+        CitationStyle::Numbered => {
+            parts.extend(Some(authors).filter(|authors| !authors.is_empty()));
+        }
+        CitationStyle::AuthorYear => {
+            parts.push(
+                format!("{} ({})", authors, encode(&year(entry)))
+                    .trim_start()
+                    .to_owned(),
+            );
+        }
+    }
+    let title = title(entry);
+    if !title.is_empty() {
+        parts.push(format!("<cite>{}</cite>", encode(&title)));
+    }
+    let mut container = ["journal", "booktitle", "series"]
+        .iter()
+        .find_map(|name| field(entry, name))
+        .map(|container| {
+            // A chapter or a paper is a part of its book
+            let is_part = ["inproceedings", "incollection", "inbook", "conference"]
+                .contains(&entry.kind.as_str());
+            let preposition = if is_part { "In " } else { "" };
+            format!("{}<em>{}</em>", preposition, encode(&container))
+        })
+        .unwrap_or_default();
+    if let Some(volume) = field(entry, "volume") {
+        write!(container, " {}", encode(&volume)).unwrap();
+    }
+    if let Some(number) = field(entry, "number") {
+        write!(container, "({})", encode(&number)).unwrap();
+    }
+    if let Some(pages) = field(entry, "pages") {
+        if !container.is_empty() {
+            container.push_str(", ");
+        }
+        write!(container, "pp. {}", encode(&pages)).unwrap();
+    }
+    parts.extend(Some(container.trim().to_owned()).filter(|container| !container.is_empty()));
+    let thesis = match entry.kind.as_str() {
+        "phdthesis" => Some("PhD thesis".to_owned()),
+        "mastersthesis" => Some("Master's thesis".to_owned()),
+        _ => None,
+    };
+    let mut publication: Vec<_> = thesis
+        .into_iter()
+        .chain(
+            ["publisher", "school", "institution", "organization"]
+                .iter()
+                .filter_map(|name| field(entry, name)),
+        )
+        .map(|publisher| encode(&publisher))
+        .collect();
+    if style == CitationStyle::Numbered {
+        publication.push(encode(&year(entry)));
+    }
+    parts.extend(Some(publication.join(", ")).filter(|publication| !publication.is_empty()));
+    // `et al.` already ends with a period
+    let mut html = String::new();
+    for part in parts {
+        if !html.is_empty() {
+            html.push_str(if html.ends_with('.') { " " } else { ". " });
+        }
+        html.push_str(&part);
+    }
+    if !html.ends_with('.') {
+        html.push('.');
+    }
+    // The links are not LaTeX, so their `~` and `--` stay
+    let raw_field = |name| {
+        entry
+            .fields
+            .get(name)
+            .map(|value| value.replace(['{', '}'], "").trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    let link = raw_field("url").or_else(|| {
+        raw_field("doi").map(|doi| {
+            if doi.starts_with("http") {
+                doi
+            } else {
+                format!("https://doi.org/{doi}")
+            }
+        })
+    });
+    if let Some(link) = link {
+        write!(
+            html,
+            r#" <a href="{}">{}</a>"#,
+            html_escape::encode_double_quoted_attribute(&link),
+            encode(&link)
+        )
+        .unwrap();
+    }
+    html
+}
+
+fn anchor(key: &str) -> String {
+    format!("ref-{key}")
+}
+
+/// Resolves the `[@key]` citations of an article against a BibTeX file, and makes the
+/// bibliography of the cited works. Without a BibTeX file the brackets are left alone
+#[derive(Default)]
+pub struct Citations {
+    /// `None` if there's no BibTeX file
+    entries: Option<HashMap<String, Entry>>,
+    style: CitationStyle,
+    /// Known keys in the order of their first citations
+    cited: Vec<String>,
+    problems: Vec<Problem>,
+}
+
+impl Citations {
+    /// Reads the BibTeX file, which becomes a dependency of the article
+    pub fn load(path: &Path, style: CitationStyle, dependencies: &mut Vec<PathBuf>) -> Self {
+        dependencies.push(path.to_owned());
+        let mut problems = Vec::new();
+        let entries = fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| bibtex::parse(&text))
+            .unwrap_or_else(|details| {
+                problems.push(Problem::InvalidBibliography {
+                    path: path.to_owned(),
+                    details,
+                });
+                HashMap::new()
+            });
+        Self {
+            entries: Some(entries),
+            style,
+            cited: Vec::new(),
+            problems,
+        }
+    }
+
+    fn cite(&mut self, references: &[Reference]) -> String {
+        let entries = self.entries.as_ref().unwrap();
+        let style = self.style;
+        let mut parts = Vec::new();
+        for reference in references {
+            let Some(entry) = entries.get(reference.key) else {
+                if !self
+                    .problems
+                    .iter()
+                    .any(|problem| matches!(problem, Problem::UnknownCitation(key) if key == reference.key))
+                {
+                    self.problems
+                        .push(Problem::UnknownCitation(reference.key.to_owned()));
+                }
+                parts.push(format!(
+                    r#"<span class="missing-citation" title="There's no such key in the bibliography">@{}</span>"#,
+                    html_escape::encode_text(reference.key)
+                ));
+                continue;
+            };
+            let number = if let Some(index) = self.cited.iter().position(|key| key == reference.key)
+            {
+                index + 1
+            } else {
+                self.cited.push(reference.key.to_owned());
+                self.cited.len()
+            };
+            let label = match style {
+                CitationStyle::Numbered => number.to_string(),
+                CitationStyle::AuthorYear => {
+                    format!("{} {}", short_authors(entry), year(entry))
+                }
+            };
+            let mut part = String::new();
+            if !reference.prefix.is_empty() {
+                part.push_str(&html_escape::encode_text(reference.prefix));
+                part.push(' ');
+            }
+            write!(
+                part,
+                r##"<a href="#{}">{}</a>"##,
+                html_escape::encode_double_quoted_attribute(&anchor(reference.key)),
+                html_escape::encode_text(&label)
+            )
+            .unwrap();
+            if !reference.locator.is_empty() {
+                part.push_str(", ");
+                part.push_str(&html_escape::encode_text(reference.locator));
+            }
+            parts.push(part);
+        }
+        let (opening, closing) = match style {
+            CitationStyle::Numbered => ('[', ']'),
+            CitationStyle::AuthorYear => ('(', ')'),
+        };
+        format!(
+            r#"<span class="citation">{}{}{}</span>"#,
+            opening,
+            parts.join("; "),
+            closing
+        )
+    }
+
+    /// Splits the text into the text and the citations
+    pub fn expand<'text>(&mut self, text: &'text str) -> Vec<Piece<'text>> {
+        if self.entries.is_none() {
+            return vec![Piece::Text(text)];
+        }
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        let mut index = 0;
+        while let Some(offset) = text[index..].find('[') {
+            index += offset;
+            // `[[wiki links]]` are not citations
+            let is_doubled = text[..index].ends_with('[') || text[index + 1..].starts_with('[');
+            match citation_at(&text[index..]).filter(|_citation| !is_doubled) {
+                Some((length, references)) => {
+                    if start < index {
+                        pieces.push(Piece::Text(&text[start..index]));
+                    }
+                    let html = self.cite(&references);
+                    pieces.push(Piece::Html(html));
+                    index += length;
+                    start = index;
+                }
+                None => index += 1,
+            }
+        }
+        if start < text.len() {
+            pieces.push(Piece::Text(&text[start..]));
+        }
+        pieces
+    }
+
+    /// `<section>` with the cited works, if some are cited
+    pub fn bibliography(&self) -> Option<String> {
+        let entries = self.entries.as_ref()?;
+        let style = self.style;
+        if self.cited.is_empty() {
+            return None;
+        }
+        let mut cited: Vec<_> = self.cited.iter().map(|key| (key, &entries[key])).collect();
+        let list = match style {
+            CitationStyle::Numbered => "ol",
+            CitationStyle::AuthorYear => {
+                cited.sort_by_cached_key(|(_key, entry)| {
+                    (
+                        short_authors(entry).to_lowercase(),
+                        year(entry),
+                        title(entry),
+                    )
+                });
+                "ul"
+            }
+        };
+        let mut html =
+            format!(r#"<section class="bibliography"><h2 id="references">References</h2><{list}>"#);
+        for (key, entry) in cited {
+            write!(
+                html,
+                r#"<li id="{}">{}</li>"#,
+                html_escape::encode_double_quoted_attribute(&anchor(key)),
+                reference_html(entry, style)
+            )
+            .unwrap();
+        }
+        write!(html, "</{list}></section>").unwrap();
+        Some(html)
+    }
+
+    pub fn problems(&self) -> impl Iterator<Item = Problem> + '_ {
+        self.problems.iter().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CitationStyle, Citations};
+    use crate::{bibtex, diagnostics::Problem, shortcodes::Piece};
+
+    const BIBLIOGRAPHY: &str = r"
+        @book{knuth84, author = {Knuth, Donald E.}, title = {The {\TeX}book},
+            publisher = {Addison-Wesley}, year = 1984}
+        @article{lamport78, author = {Leslie Lamport}, title = {Time, Clocks},
+            journal = {Communications of the ACM}, volume = 21, number = 7, pages = {558--565},
+            year = 1978, doi = {10.1145/359545.359563}}
+        @inproceedings{abadi93, author = {Abadi, Martín and Lamport, Leslie and others},
+            title = {Composing Specifications}, booktitle = {Proceedings}, year = 1993}
+    ";
+
+    fn citations(style: CitationStyle) -> Citations {
+        Citations {
+            entries: Some(bibtex::parse(BIBLIOGRAPHY).unwrap()),
+            style,
+            ..Citations::default()
+        }
+    }
+
+    fn expand(citations: &mut Citations, text: &str) -> String {
+        citations
+            .expand(text)
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.to_owned(),
+                Piece::Html(html) => html,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn numbers_the_works_in_the_order_of_citations() {
+        let mut citations = citations(CitationStyle::Numbered);
+        let html = expand(
+            &mut citations,
+            "As [see @lamport78, p. 560; @knuth84] and [@lamport78] say.",
+        );
+        assert_eq!(
+            html,
+            r##"As <span class="citation">[see <a href="#ref-lamport78">1</a>, p. 560; <a href="#ref-knuth84">2</a>]</span> and <span class="citation">[<a href="#ref-lamport78">1</a>]</span> say."##
+        );
+        let bibliography = citations.bibliography().unwrap();
+        assert!(
+            bibliography.starts_with(
+                r#"<section class="bibliography"><h2 id="references">References</h2><ol><li id="ref-lamport78">Leslie Lamport. <cite>Time, Clocks</cite>. <em>Communications of the ACM</em> 21(7), pp. 558–565. 1978. <a href="https://doi.org/10.1145/359545.359563">"#
+            ),
+            "{bibliography}"
+        );
+        assert!(
+            bibliography.ends_with(
+                r#"<li id="ref-knuth84">Donald E. Knuth. <cite>The TeXbook</cite>. Addison-Wesley, 1984.</li></ol></section>"#
+            ),
+            "{bibliography}"
+        );
+    }
+
+    #[test]
+    fn labels_the_works_with_their_authors_and_years() {
+        let mut citations = citations(CitationStyle::AuthorYear);
+        let html = expand(&mut citations, "[@lamport78; @abadi93] and [@knuth84]");
+        assert_eq!(
+            html,
+            r##"<span class="citation">(<a href="#ref-lamport78">Lamport 1978</a>; <a href="#ref-abadi93">Abadi et al. 1993</a>)</span> and <span class="citation">(<a href="#ref-knuth84">Knuth 1984</a>)</span>"##
+        );
+        let bibliography = citations.bibliography().unwrap();
+        let order: Vec<_> = ["ref-abadi93", "ref-knuth84", "ref-lamport78"]
+            .iter()
+            .map(|anchor| bibliography.find(anchor).unwrap())
+            .collect();
+        assert!(order.is_sorted(), "{bibliography}");
+        assert!(
+            bibliography.contains(
+                "Martín Abadi, Leslie Lamport et al. (1993). <cite>Composing Specifications</cite>. In <em>Proceedings</em>.</li>"
+            ),
+            "{bibliography}"
+        );
+    }
+
+    #[test]
+    fn marks_unknown_keys() {
+        let mut citations = citations(CitationStyle::Numbered);
+        let html = expand(&mut citations, "[@nobody] [@nobody]");
+        assert_eq!(
+            html.matches(r#"<span class="missing-citation" title="There's no such key in the bibliography">@nobody</span>"#)
+                .count(),
+            2
+        );
+        let problems: Vec<_> = citations.problems().collect();
+        assert!(matches!(&problems[..], [Problem::UnknownCitation(key)] if key == "nobody"));
+        assert!(citations.bibliography().is_none());
+    }
+
+    #[test]
+    fn leaves_other_brackets_alone() {
+        let mut citations = citations(CitationStyle::Numbered);
+        let text = "[[wiki]], [link](/url), [mail me@example.com], [@knuth84][reference]";
+        assert_eq!(expand(&mut citations, text), text);
+        assert!(citations.bibliography().is_none());
+        let mut without_bibliography = Citations::default();
+        assert_eq!(
+            expand(&mut without_bibliography, "[@knuth84]"),
+            "[@knuth84]"
+        );
+    }
+}
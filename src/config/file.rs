@@ -0,0 +1,363 @@
+use std::{
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use log::warn;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{overrides, Raw, SiteBase, SAMPLE_SITE_NAME, SITE_FIELD_DESCRIPTIONS};
+
+static FILE_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Remembers where the configuration file is. Should be called once, before anything else touches
+/// the configuration. Relative paths are taken relative to the current working directory.
+///
+/// # Panics
+/// Panics if the path was already set.
+pub fn set_path(path: PathBuf) {
+    let path = if path.is_absolute() {
+        path
+    } else {
+        env::current_dir().unwrap().join(path)
+    };
+    assert!(
+        FILE_PATH.set(path).is_ok(),
+        "Configuration file path is already set!"
+    );
+}
+
+/// # Panics
+/// Panics if [`set_path`] wasn't called yet.
+pub fn path() -> &'static Path {
+    FILE_PATH
+        .get()
+        .expect("Configuration file path is not set yet!")
+}
+
+/// The directory that contains the configuration file. Relative paths from the configuration are
+/// resolved against it.
+pub fn directory() -> &'static Path {
+    path().parent().unwrap_or_else(|| Path::new(""))
+}
+
+/// Makes a path from the configuration file absolute (if it isn't already)
+pub fn resolve(path: impl AsRef<Path>) -> PathBuf {
+    directory().join(path)
+}
+
+#[derive(Clone, Copy)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Picks the format by the file extension.
+    ///
+    /// # Errors
+    /// Returns an error if the extension is missing or unknown.
+    pub fn of(path: &Path) -> Result<Self, String> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Ok(Self::Json),
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            _ => Err(format!(
+                "cannot tell the format of `{}` by its extension! Use one of `.json`, `.toml`, \
+                `.yaml` or `.yml`",
+                path.display()
+            )),
+        }
+    }
+
+    /// # Errors
+    /// Returns an error with the parser's explanation if the contents are invalid.
+    pub fn parse<T: DeserializeOwned>(self, contents: &str) -> Result<T, String> {
+        match self {
+            Self::Json => serde_json::from_str(contents).map_err(|error| error.to_string()),
+            Self::Toml => toml::from_str(contents).map_err(|error| error.to_string()),
+            Self::Yaml => serde_yaml::from_str(contents).map_err(|error| error.to_string()),
+        }
+    }
+
+    /// # Panics
+    /// Panics if the value cannot be represented in this format.
+    pub fn serialize(self, value: &impl Serialize) -> String {
+        match self {
+            Self::Json => serde_json::to_string_pretty(value).unwrap(),
+            // Converting to `toml::Value` first puts plain values before the tables, which the
+            // direct serialization of a struct doesn't do
+            Self::Toml => toml::to_string_pretty(&toml::Value::try_from(value).unwrap()).unwrap(),
+            Self::Yaml => serde_yaml::to_string(value).unwrap(),
+        }
+    }
+
+    /// Serializes the value and puts the descriptions of the fields before their first occurrences
+    /// as comments. JSON has no comments, so it is left as is.
+    pub fn serialize_with_comments(
+        self,
+        value: &impl Serialize,
+        descriptions: &[(&str, &str)],
+    ) -> String {
+        let serialized = self.serialize(value);
+        if matches!(self, Self::Json) {
+            return serialized;
+        }
+        let mut commented = String::with_capacity(serialized.len());
+        let mut described = Vec::new();
+        for line in serialized.lines() {
+            let trimmed_line = line.trim_start();
+            let indentation = &line[..line.len() - trimmed_line.len()];
+            let key = match self {
+                // `[sites.main]` and `[[sites.main.index_page_colors]]` are described by `sites`
+                Self::Toml => trimmed_line
+                    .trim_start_matches('[')
+                    .split([' ', ']', '.'])
+                    .next(),
+                _ => trimmed_line.split(':').next(),
+            };
+            if let Some((key, description)) = descriptions
+                .iter()
+                .find(|(name, _description)| Some(*name) == key)
+            {
+                if !described.contains(key) {
+                    described.push(*key);
+                    if indentation.is_empty()
+                        && !commented.is_empty()
+                        && !commented.ends_with("\n\n")
+                        && commented != "---\n"
+                    {
+                        commented.push('\n');
+                    }
+                    for description_line in description.lines() {
+                        commented.push_str(indentation);
+                        commented.push_str("# ");
+                        commented.push_str(description_line);
+                        commented.push('\n');
+                    }
+                }
+            }
+            commented.push_str(line);
+            commented.push('\n');
+        }
+        commented
+    }
+}
+
+pub enum ReadError {
+    Inaccessible(io::Error),
+    PoorlyFormatted(String),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inaccessible(error) => write!(f, "{error}"),
+            Self::PoorlyFormatted(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+fn read_value(path: &Path) -> Result<(Format, serde_json::Value), ReadError> {
+    let format = Format::of(path).map_err(ReadError::PoorlyFormatted)?;
+    let contents = fs::read_to_string(path).map_err(ReadError::Inaccessible)?;
+    let value = format
+        .parse(&contents)
+        .map_err(ReadError::PoorlyFormatted)?;
+    Ok((format, value))
+}
+
+type Map = serde_json::Map<String, serde_json::Value>;
+
+fn as_map(value: serde_json::Value, what: &str) -> Result<Map, ReadError> {
+    match value {
+        serde_json::Value::Object(map) => Ok(map),
+        _ => Err(ReadError::PoorlyFormatted(format!(
+            "{what} should be a map of fields"
+        ))),
+    }
+}
+
+/// Puts the `fields` on top of the `defaults`, warning about the fields that the program doesn't
+/// know. `location` is either empty or starts with a space, like " of site `main`"
+fn merge_known_fields(defaults: &mut Map, fields: Map, location: &str, path: &Path) {
+    for (key, value) in fields {
+        if defaults.contains_key(&key) {
+            defaults.insert(key, value);
+        } else {
+            warn!(
+                "Unknown field `{key}`{location} in the configuration file `{path:?}` is ignored"
+            );
+        }
+    }
+}
+
+/// Puts the fields from the file on top of the defaults (which are the values from
+/// [`Raw::sample`] and [`SiteBase::sample`]), warning about the fields that the program doesn't
+/// know.
+///
+/// Configurations from the times when only one site was supported (with the site fields at the
+/// top level) are turned into a configuration with a single site.
+fn with_defaults(
+    from_file: serde_json::Value,
+    path: &Path,
+) -> Result<serde_json::Value, ReadError> {
+    let mut from_file = as_map(from_file, "the configuration")?;
+    if !from_file.contains_key("sites")
+        && SITE_FIELD_DESCRIPTIONS
+            .iter()
+            .any(|(name, _)| from_file.contains_key(*name))
+    {
+        warn!(
+            "Configuration file `{:?}` has the old layout with a single site. Consider running \
+            `blog config migrate`",
+            path
+        );
+        let mut site = Map::new();
+        for (name, _) in SITE_FIELD_DESCRIPTIONS {
+            if let Some(value) = from_file.remove(*name) {
+                site.insert((*name).to_owned(), value);
+            }
+        }
+        from_file.insert(
+            "sites".into(),
+            serde_json::json!({ SAMPLE_SITE_NAME: site }),
+        );
+    }
+    let mut config = as_map(serde_json::to_value(Raw::sample()).unwrap(), "")?;
+    let sites = from_file.remove("sites");
+    merge_known_fields(&mut config, from_file, "", path);
+    if let Some(sites) = sites {
+        let site_defaults = serde_json::to_value(SiteBase::sample()).unwrap();
+        let mut merged_sites = Map::new();
+        for (name, site) in as_map(sites, "`sites`")? {
+            let mut merged_site = as_map(site_defaults.clone(), "")?;
+            merge_known_fields(
+                &mut merged_site,
+                as_map(site, &format!("site `{name}`"))?,
+                &format!(" of site `{name}`"),
+                path,
+            );
+            merged_sites.insert(name, merged_site.into());
+        }
+        config.insert("sites".into(), merged_sites.into());
+    }
+    Ok(config.into())
+}
+
+fn into_base(config: serde_json::Value) -> Result<Raw, ReadError> {
+    serde_json::from_value(config).map_err(|error| ReadError::PoorlyFormatted(error.to_string()))
+}
+
+/// Reads and parses the configuration file, filling the missing fields with the defaults and
+/// applying the environment and command line overrides.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or doesn't describe a configuration.
+pub fn read() -> Result<Raw, ReadError> {
+    let (_format, from_file) = read_value(path())?;
+    let mut config = with_defaults(from_file, path())?;
+    overrides::apply(&mut config).map_err(ReadError::PoorlyFormatted)?;
+    into_base(config)
+}
+
+/// Rewrites the configuration file in the current layout: the missing fields are filled with the
+/// defaults, the unknown ones are dropped, and the field descriptions are put into comments. The
+/// old file is kept next to the new one with `.bak` appended to its name. Overrides are not
+/// applied, because they don't belong to the file.
+///
+/// # Errors
+/// Returns an error if the old file cannot be read or parsed, or the new one cannot be written.
+pub fn migrate() -> Result<PathBuf, ReadError> {
+    migrate_file(path())
+}
+
+fn migrate_file(path: &Path) -> Result<PathBuf, ReadError> {
+    let (format, from_file) = read_value(path)?;
+    let config = into_base(with_defaults(from_file, path)?)?;
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(".bak");
+    let backup_path = PathBuf::from(backup_path);
+    fs::copy(path, &backup_path).map_err(ReadError::Inaccessible)?;
+    fs::write(
+        path,
+        format.serialize_with_comments(&config, &super::descriptions()),
+    )
+    .map_err(ReadError::Inaccessible)?;
+    Ok(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use serde_json::json;
+
+    use super::{migrate_file, with_defaults, Format};
+    use crate::config::{Raw, SiteBase, SAMPLE_SITE_NAME};
+
+    fn defaults(from_file: serde_json::Value) -> serde_json::Value {
+        with_defaults(from_file, Path::new("blog.yaml")).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    fn sample_site() -> serde_json::Value {
+        serde_json::to_value(SiteBase::sample()).unwrap()
+    }
+
+    #[test]
+    fn fills_missing_fields_with_defaults() {
+        let from_file = json!({
+            "port": 9000,
+            "unknown": true,
+            "sites": { "a": { "author_name": "A", "unknown": true }, "b": {} }
+        });
+        let config = defaults(from_file);
+        let mut expected = serde_json::to_value(Raw::sample()).unwrap();
+        expected["port"] = json!(9000);
+        let mut site_a = sample_site();
+        site_a["author_name"] = json!("A");
+        expected["sites"] = json!({ "a": site_a, "b": sample_site() });
+        assert_eq!(config, expected);
+    }
+
+    #[test]
+    fn turns_the_old_layout_into_a_single_site() {
+        let from_file = json!({ "port": 9000, "author_name": "Old" });
+        let config = defaults(from_file);
+        assert_eq!(config["port"], 9000);
+        assert!(config.get("author_name").is_none());
+        let mut site = sample_site();
+        site["author_name"] = json!("Old");
+        assert_eq!(config["sites"], json!({ SAMPLE_SITE_NAME: site }));
+
+        // Only the site fields mean the old layout
+        let config = defaults(json!({ "port": 9000 }));
+        assert_eq!(config["sites"], json!({ SAMPLE_SITE_NAME: sample_site() }));
+    }
+
+    #[test]
+    fn migrates_and_keeps_a_backup() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("blog.yaml");
+        let old = "port: 9000\nauthor_name: Old\nunknown: true\n";
+        fs::write(&path, old).unwrap();
+        let backup_path = migrate_file(&path).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(backup_path, directory.path().join("blog.yaml.bak"));
+        assert_eq!(fs::read_to_string(backup_path).unwrap(), old);
+        let migrated = fs::read_to_string(&path).unwrap();
+        assert!(
+            migrated.contains("# Port to listen on\nport: 9000\n"),
+            "{migrated}"
+        );
+        assert!(!migrated.contains("unknown:"), "{migrated}");
+        let config: Raw = Format::Yaml.parse(&migrated).unwrap();
+        assert_eq!(config.port, 9000);
+        assert_eq!(config.sites[SAMPLE_SITE_NAME].author_name, "Old");
+        // The migrated file is already in the current layout
+        let backup_path = migrate_file(&path).unwrap_or_else(|error| panic!("{error}"));
+        assert_eq!(fs::read_to_string(backup_path).unwrap(), migrated);
+        assert_eq!(fs::read_to_string(&path).unwrap(), migrated);
+    }
+}
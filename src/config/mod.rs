@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use log::{error, info};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    set_file_watcher_delay, sites::Sites, utils::set_global_log_level, watch_config, ConfigWatcher,
+    CustomServerHandle, WatchContext,
+};
+
+pub mod file;
+pub mod overrides;
+mod site;
+
+pub use site::{SiteBase, SiteConfig, FIELD_DESCRIPTIONS as SITE_FIELD_DESCRIPTIONS};
+
+/// Descriptions of the configuration fields, which are put into the sample configuration files
+/// (where the format allows comments)
+pub const FIELD_DESCRIPTIONS: &[(&str, &str)] = &[
+    (
+        "sites",
+        "Blogs served by this process, by their names. A site is picked by the `Host` header",
+    ),
+    (
+        "default_site",
+        "Name of the site that serves the requests with unknown `Host` headers",
+    ),
+    ("host_name", "Host name (or IP address) to listen on"),
+    ("port", "Port to listen on"),
+    (
+        "log_level",
+        "One of \"off\", \"error\", \"warn\", \"info\", \"debug\", \"trace\"",
+    ),
+    (
+        "file_watcher_delay_in_milliseconds",
+        "How long to wait for the file changes to settle before reacting to them",
+    ),
+];
+
+/// Descriptions of the server's fields and the site fields together
+pub fn descriptions() -> Vec<(&'static str, &'static str)> {
+    [FIELD_DESCRIPTIONS, SITE_FIELD_DESCRIPTIONS].concat()
+}
+
+/// Every field is optional in the configuration file: the missing ones are taken from
+/// [`Base::sample`] (and the missing fields of a site are taken from [`SiteBase::sample`])
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct Base<Site> {
+    pub sites: BTreeMap<String, Site>,
+    pub default_site: String,
+    pub host_name: String,
+    pub port: u16,
+    pub log_level: String,
+    pub file_watcher_delay_in_milliseconds: u64,
+}
+
+/// Configuration as it is written in the file
+pub type Raw = Base<SiteBase<PathBuf>>;
+
+/// Name of the only site in the sample configuration (and in the configurations from the times
+/// when there was only one site)
+pub const SAMPLE_SITE_NAME: &str = "main";
+
+impl Raw {
+    /// Returns a sample configuration, which should __not__ be used in production (because it
+    /// lacks the author's name)
+    pub fn sample() -> Self {
+        Self {
+            sites: BTreeMap::from([(SAMPLE_SITE_NAME.to_owned(), SiteBase::sample())]),
+            default_site: SAMPLE_SITE_NAME.into(),
+            host_name: "localhost".into(),
+            port: 8080,
+            log_level: "info".into(),
+            file_watcher_delay_in_milliseconds: 2000,
+        }
+    }
+
+    /// Upgrades itself to [`Config`], returning an error (and the failed path) if
+    /// `articles_directory.canonicalize()` failed for some site.
+    pub fn upgrade(self) -> Result<Config, UpgradeError> {
+        if !self.sites.contains_key(&self.default_site) {
+            return Err(UpgradeError::UnknownDefaultSite(self.default_site));
+        }
+        let mut sites = BTreeMap::new();
+        for (name, site) in self.sites {
+            match site.upgrade() {
+                Ok(site) => {
+                    sites.insert(name, Arc::new(Mutex::new(site)));
+                }
+                Err((error, articles_directory)) => {
+                    return Err(UpgradeError::ArticlesDirectory {
+                        site_name: name,
+                        articles_directory,
+                        error,
+                    })
+                }
+            }
+        }
+        Ok(Config {
+            sites,
+            default_site: self.default_site,
+            port: self.port,
+            host_name: self.host_name,
+            log_level: self.log_level,
+            file_watcher_delay_in_milliseconds: self.file_watcher_delay_in_milliseconds,
+        })
+    }
+}
+
+pub enum UpgradeError {
+    UnknownDefaultSite(String),
+    ArticlesDirectory {
+        site_name: String,
+        articles_directory: PathBuf,
+        error: io::Error,
+    },
+}
+
+impl std::fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownDefaultSite(name) => {
+                write!(f, "default site `{name}` is not in the list of sites!")
+            }
+            Self::ArticlesDirectory {
+                site_name,
+                articles_directory,
+                error,
+            } => write!(
+                f,
+                "articles directory path (`{}`) of site `{site_name}` cannot be expanded! \
+                Details: {error}",
+                articles_directory.display()
+            ),
+        }
+    }
+}
+
+/// Returns a JSON Schema of the configuration file, which editors can use for validation and
+/// autocompletion. The field descriptions and defaults are the ones from the sample configuration.
+pub fn schema() -> serde_json::Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Raw)).unwrap();
+    let defaults = serde_json::to_value(Raw::sample()).unwrap();
+    let site_defaults = serde_json::to_value(SiteBase::sample()).unwrap();
+    schema.as_object_mut().unwrap().remove("required");
+    for (name, description) in FIELD_DESCRIPTIONS {
+        let property = &mut schema["properties"][name];
+        property["description"] = (*description).into();
+        property["default"] = defaults[name].clone();
+    }
+    for (definition_name, definition) in schema["definitions"].as_object_mut().unwrap() {
+        if definition_name.starts_with("SiteBase") {
+            definition.as_object_mut().unwrap().remove("required");
+            for (name, description) in SITE_FIELD_DESCRIPTIONS {
+                let property = &mut definition["properties"][name];
+                property["description"] = (*description).into();
+                property["default"] = site_defaults[name].clone();
+            }
+        }
+    }
+    schema
+}
+
+/// Working config that should be used in the server's code
+pub type Config = Base<Arc<Mutex<SiteConfig>>>;
+
+impl Config {
+    /// Applies the new configuration. The sites are compiled again if their configuration
+    /// changed, or anyway with `reload_everything`
+    #[deny(unused_variables)] // Unused variables will mean that I haven't handled everything
+    pub fn update(
+        old_config: &Mutex<Self>,
+        new_config: Raw,
+        server_handle: &CustomServerHandle,
+        sites: &Mutex<Sites>,
+        config_watch_context: &Arc<Mutex<WatchContext<ConfigWatcher>>>,
+        reload_everything: bool,
+    ) {
+        let mut reload_server = false;
+        let site_updates;
+        {
+            let mut old_config = old_config.lock().unwrap();
+            macro_rules! if_changed {
+                ($field_name:ident, $body:block) => {
+                    if old_config.$field_name != $field_name {
+                        $body
+                        old_config.$field_name = $field_name;
+                    }
+                };
+            }
+            let Base {
+                sites: new_sites,
+                default_site,
+                host_name,
+                port,
+                log_level,
+                file_watcher_delay_in_milliseconds,
+            } = new_config;
+            if !new_sites.contains_key(&default_site) {
+                error!(
+                    "Default site `{}` is not in the list of sites! Using the old configuration \
+                    for now",
+                    default_site
+                );
+                if reload_everything {
+                    Sites::reload_all(sites);
+                }
+                return;
+            }
+            {
+                let host_name_was_changed = host_name != old_config.host_name;
+                let port_was_changed = port != old_config.port;
+                let target = match (host_name_was_changed, port_was_changed) {
+                    (true, true) => "host name and port were",
+                    (false, false) => "",
+                    (false, true) => "host name was",
+                    (true, false) => "port was",
+                };
+                if !target.is_empty() {
+                    old_config.host_name = host_name;
+                    old_config.port = port;
+                    info!("{} changed. Restarting", target);
+                    reload_server = true;
+                }
+            }
+            if_changed!(log_level, {
+                set_global_log_level(&log_level).unwrap_or_else(|error| error!("{}", error));
+            });
+            if_changed!(file_watcher_delay_in_milliseconds, {
+                set_file_watcher_delay(file_watcher_delay_in_milliseconds);
+                sites.lock().unwrap().rewatch();
+                match watch_config(&old_config) {
+                    Ok(new_context) => {
+                        *config_watch_context.lock().unwrap() = new_context;
+                    }
+                    Err(error) => {
+                        error!(
+                            "An error occured while changing the config watcher delay: {}",
+                            error
+                        );
+                    }
+                }
+            });
+            if_changed!(default_site, {});
+            site_updates = old_config.update_site_list(new_sites, sites);
+        }
+        // Sites are updated without holding the lock of the main configuration, because their
+        // recompilation takes a while
+        for (old_site, new_site, name) in site_updates {
+            update_site(&old_site, new_site, &name, sites, reload_everything);
+        }
+        {
+            let old_config = old_config.lock().unwrap();
+            sites
+                .lock()
+                .unwrap()
+                .reindex_hosts(&old_config.sites, &old_config.default_site);
+        }
+        if reload_server {
+            tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap()
+                .block_on(async { server_handle.request_restart().await });
+        }
+    }
+
+    /// Removes the sites that are not in the new list and starts the new ones. Returns the sites
+    /// that are still there with their new configurations (and their names)
+    fn update_site_list(
+        &mut self,
+        new_sites: BTreeMap<String, SiteBase<PathBuf>>,
+        sites: &Mutex<Sites>,
+    ) -> Vec<(Arc<Mutex<SiteConfig>>, SiteBase<PathBuf>, String)> {
+        let removed_sites: Vec<String> = self
+            .sites
+            .keys()
+            .filter(|name| !new_sites.contains_key(*name))
+            .cloned()
+            .collect();
+        for name in removed_sites {
+            info!("Site `{name}` was removed from the configuration");
+            self.sites.remove(&name);
+            sites.lock().unwrap().remove(&name);
+        }
+        let mut site_updates = Vec::new();
+        for (name, new_site) in new_sites {
+            if let Some(old_site) = self.sites.get(&name) {
+                site_updates.push((old_site.clone(), new_site, name));
+                continue;
+            }
+            match new_site.upgrade() {
+                Ok(site_config) => {
+                    info!("Site `{name}` was added to the configuration");
+                    let site_config = Arc::new(Mutex::new(site_config));
+                    let added = sites.lock().unwrap().add(name.clone(), site_config.clone());
+                    match added {
+                        Ok(()) => {
+                            self.sites.insert(name, site_config);
+                        }
+                        Err(error) => error!(
+                            "Articles directory of the new site `{name}` is not accessible! \
+                            Details: {error}"
+                        ),
+                    }
+                }
+                Err((error, articles_directory)) => error!(
+                    "Articles directory path (`{}`) of the new site `{name}` cannot be expanded! \
+                    Details: {error}",
+                    articles_directory.display()
+                ),
+            }
+        }
+        site_updates
+    }
+}
+
+/// Applies the new configuration of a site, and watches its new files and theme directories
+fn update_site(
+    old_site: &Mutex<SiteConfig>,
+    new_site: SiteBase<PathBuf>,
+    name: &str,
+    sites: &Mutex<Sites>,
+    reload_everything: bool,
+) {
+    let (website, articles_watch_context) = sites.lock().unwrap().parts_of(name);
+    let (old_files_directory, old_theme_directory) = {
+        let old_site = old_site.lock().unwrap();
+        (
+            old_site.files_directory.clone(),
+            old_site.theme_directory.clone(),
+        )
+    };
+    SiteConfig::update(
+        old_site,
+        new_site,
+        &website,
+        &articles_watch_context,
+        reload_everything,
+    );
+    if old_site.lock().unwrap().files_directory != old_files_directory {
+        sites.lock().unwrap().rewatch_files(name);
+    }
+    if old_site.lock().unwrap().theme_directory != old_theme_directory {
+        sites.lock().unwrap().rewatch_theme(name);
+    }
+}
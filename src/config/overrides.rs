@@ -0,0 +1,301 @@
+use std::{env, sync::OnceLock};
+
+use serde_json::Value;
+
+use super::{Raw, SiteBase, FIELD_DESCRIPTIONS, SITE_FIELD_DESCRIPTIONS};
+
+/// Fields that hold paths. Overridden paths are relative to the current working directory, unlike
+/// the paths from the configuration file
+const PATH_FIELDS: &[&str] = &[
+    "articles_directory",
+    "files_directory",
+    "theme_directory",
+    "include_directory",
+    "bibliography",
+];
+
+const ENVIRONMENT_PREFIX: &str = "BLOG_";
+
+static COMMAND_LINE: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Remembers the overrides from the command line, so they are applied every time the
+/// configuration is read (including the reloads).
+///
+/// # Panics
+/// Panics if the overrides were already set.
+pub fn set_command_line(overrides: Vec<(String, String)>) {
+    assert!(
+        COMMAND_LINE.set(overrides).is_ok(),
+        "Command line overrides are already set!"
+    );
+}
+
+/// Adds the dedicated flags (like `--port`) to the `--set` overrides, in the order they are
+/// applied.
+///
+/// # Errors
+/// Returns an error if a field is given both by its dedicated flag and by `--set`, because it's
+/// not clear which value was meant.
+pub fn with_dedicated_flags<'key>(
+    set: Vec<(String, String)>,
+    dedicated: impl IntoIterator<Item = (&'key str, Option<String>)>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut overrides = set;
+    for (key, value) in dedicated {
+        if let Some(value) = value {
+            if let Some((_key, set_value)) =
+                overrides.iter().find(|(set_key, _value)| set_key == key)
+            {
+                return Err(format!(
+                    "`--{} {value}` conflicts with `--set {key}={set_value}`, use only one of them",
+                    key.replace('_', "-"),
+                ));
+            }
+            overrides.push((key.to_owned(), value));
+        }
+    }
+    Ok(overrides)
+}
+
+/// Parses `key=value` from the command line.
+///
+/// # Errors
+/// Returns an error if there's no `=` or the key isn't a configuration field.
+pub fn parse_assignment(assignment: &str) -> Result<(String, String), String> {
+    let (key, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| format!("`{assignment}` should look like `key=value`"))?;
+    Target::of(key)?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// What is overridden by a key
+enum Target<'key> {
+    /// `port`
+    Server(&'key str),
+    /// `author_name` (of the default site) or `sites.<site name>.author_name`
+    Site {
+        site_name: Option<&'key str>,
+        field: &'key str,
+    },
+}
+
+impl<'key> Target<'key> {
+    fn of(key: &'key str) -> Result<Self, String> {
+        let is_site_field = |field| {
+            SITE_FIELD_DESCRIPTIONS
+                .iter()
+                .any(|(name, _)| *name == field)
+        };
+        if FIELD_DESCRIPTIONS.iter().any(|(name, _)| *name == key) {
+            return Ok(Self::Server(key));
+        }
+        if is_site_field(key) {
+            return Ok(Self::Site {
+                site_name: None,
+                field: key,
+            });
+        }
+        if let Some((site_name, field)) = key
+            .strip_prefix("sites.")
+            .and_then(|rest| rest.rsplit_once('.'))
+        {
+            if is_site_field(field) {
+                return Ok(Self::Site {
+                    site_name: Some(site_name),
+                    field,
+                });
+            }
+        }
+        Err(format!(
+            "`{}` is not a configuration field! Known fields: {} (site fields apply to the \
+            default site, or to a specific one as `sites.<site name>.<field>`)",
+            key,
+            itertools::Itertools::intersperse(
+                FIELD_DESCRIPTIONS
+                    .iter()
+                    .chain(SITE_FIELD_DESCRIPTIONS)
+                    .map(|(name, _)| *name),
+                ", "
+            )
+            .collect::<String>()
+        ))
+    }
+
+    const fn field(&self) -> &'key str {
+        match self {
+            Self::Server(field) | Self::Site { field, .. } => field,
+        }
+    }
+}
+
+/// `BLOG_PORT=8081` overrides `port`, `BLOG_AUTHOR_NAME=John` overrides `author_name` of the
+/// default site and so on. The variables are read with `var`
+fn from_environment(var: impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+    FIELD_DESCRIPTIONS
+        .iter()
+        .chain(SITE_FIELD_DESCRIPTIONS)
+        .filter_map(|(name, _)| {
+            var(&format!("{}{}", ENVIRONMENT_PREFIX, name.to_uppercase()))
+                .map(|value| ((*name).to_owned(), value))
+        })
+        .collect()
+}
+
+/// Applies the overrides on top of the configuration from the file: environment variables first,
+/// then the command line, so the command line wins.
+///
+/// # Errors
+/// Returns an error if an override cannot be turned into a value of its field, or points to a site
+/// that doesn't exist.
+pub fn apply(config: &mut Value) -> Result<(), String> {
+    let command_line = COMMAND_LINE
+        .get()
+        .map_or(&[][..], |overrides| &overrides[..]);
+    apply_overrides(
+        config,
+        &from_environment(|name| env::var(name).ok()),
+        command_line,
+    )
+}
+
+fn apply_overrides(
+    config: &mut Value,
+    environment: &[(String, String)],
+    command_line: &[(String, String)],
+) -> Result<(), String> {
+    let sample = serde_json::to_value(Raw::sample()).unwrap();
+    let site_sample = serde_json::to_value(SiteBase::sample()).unwrap();
+    for (key, raw_value) in environment.iter().chain(command_line) {
+        let target = Target::of(key)?;
+        let field = target.field();
+        let value = if PATH_FIELDS.contains(&field) {
+            Value::String(
+                env::current_dir()
+                    .unwrap()
+                    .join(raw_value)
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        } else if sample[field].is_string() || site_sample[field].is_string() {
+            Value::String(raw_value.clone())
+        } else {
+            serde_json::from_str(raw_value).map_err(|error| {
+                format!("override of `{key}` (`{raw_value}`) is not a valid value: {error}")
+            })?
+        };
+        let fields = match target {
+            Target::Server(_) => &mut *config,
+            Target::Site { site_name, .. } => {
+                let site_name = site_name
+                    .map(ToOwned::to_owned)
+                    .or_else(|| config["default_site"].as_str().map(ToOwned::to_owned))
+                    .ok_or("`default_site` should be a string")?;
+                config["sites"]
+                    .get_mut(&site_name)
+                    .ok_or_else(|| format!("override `{key}` is for an unknown site"))?
+            }
+        };
+        fields
+            .as_object_mut()
+            .ok_or("the configuration should be a map of fields")?
+            .insert(field.to_owned(), value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::Value;
+
+    use super::{apply_overrides, from_environment, with_dedicated_flags};
+    use crate::config::Raw;
+
+    fn overrides(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+            .collect()
+    }
+
+    /// The sample configuration with the overrides applied
+    fn apply(environment: &[(&str, &str)], command_line: &[(String, String)]) -> Value {
+        let variables: HashMap<_, _> = environment.iter().copied().collect();
+        let environment = from_environment(|name| variables.get(name).map(|&v| v.to_owned()));
+        let mut config = serde_json::to_value(Raw::sample()).unwrap();
+        apply_overrides(&mut config, &environment, command_line).unwrap();
+        config
+    }
+
+    #[test]
+    fn command_line_wins_over_environment_and_file() {
+        let author_name = |config: &Value| {
+            let site = &config["sites"][config["default_site"].as_str().unwrap()];
+            site["author_name"].as_str().unwrap().to_owned()
+        };
+        let file = apply(&[], &[]);
+        assert_eq!(file["port"], 8080);
+        assert_eq!(file["log_level"], "info");
+
+        let environment = [("BLOG_PORT", "8081"), ("BLOG_AUTHOR_NAME", "Env")];
+        let config = apply(&environment, &[]);
+        assert_eq!(config["port"], 8081);
+        assert_eq!(author_name(&config), "Env");
+        assert_eq!(config["log_level"], "info");
+
+        let set = overrides(&[("port", "8082"), ("author_name", "Set")]);
+        let config = apply(&environment, &set);
+        assert_eq!(config["port"], 8082);
+        assert_eq!(author_name(&config), "Set");
+
+        let command_line =
+            with_dedicated_flags(set, [("log_level", Some("debug".to_owned()))]).unwrap();
+        let config = apply(&environment, &command_line);
+        assert_eq!(config["port"], 8082);
+        assert_eq!(config["log_level"], "debug");
+    }
+
+    #[test]
+    fn dedicated_flags_conflict_with_set() {
+        let set = overrides(&[("port", "8082")]);
+        let error = with_dedicated_flags(set.clone(), [("port", Some("9000".to_owned()))])
+            .err()
+            .unwrap();
+        assert_eq!(
+            error,
+            "`--port 9000` conflicts with `--set port=8082`, use only one of them"
+        );
+        let overrides =
+            with_dedicated_flags(set, [("port", None), ("host_name", Some("0.0.0.0".into()))])
+                .unwrap();
+        assert_eq!(
+            overrides,
+            [
+                ("port".to_owned(), "8082".to_owned()),
+                ("host_name".to_owned(), "0.0.0.0".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_poor_overrides() {
+        let mut config = serde_json::to_value(Raw::sample()).unwrap();
+        for (key, value, error) in [
+            (
+                "port",
+                "eighty",
+                "override of `port` (`eighty`) is not a valid value",
+            ),
+            (
+                "sites.missing.author_name",
+                "John",
+                "override `sites.missing.author_name` is for an unknown site",
+            ),
+        ] {
+            let result = apply_overrides(&mut config, &[], &overrides(&[(key, value)]));
+            assert!(result.unwrap_err().starts_with(error), "{key}");
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use log::error;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::file;
+use crate::{
+    absolute_path::AbsolutePath,
+    base_url::BaseUrl,
+    citations::CitationStyle,
+    files::{FilesPolicy, SymlinkPolicy},
+    markdown_extensions::MarkdownExtensions,
+    page_colors::PageColors,
+    watch_articles_directory,
+    website::Website,
+    ArticlesWatcher, WatchContext,
+};
+
+/// Descriptions of the fields of a single site
+pub const FIELD_DESCRIPTIONS: &[(&str, &str)] = &[
+    (
+        "host_names",
+        "Values of the `Host` header (without the port) that select this site",
+    ),
+    (
+        "author_name",
+        "Shown in the title of the index page and in the signature of every article",
+    ),
+    (
+        "index_page_colors",
+        "Color pairs (hex codes without `#`) for the index page. One pair is picked at random\n\
+        on every request",
+    ),
+    (
+        "articles_directory",
+        "Directory with the Markdown articles. Relative paths are taken relative to this file",
+    ),
+    (
+        "files_directory",
+        "Directory with the files that are served as is (images, downloads). Relative paths are\n\
+        taken relative to this file",
+    ),
+    (
+        "date_format",
+        "`strftime`-like format of the dates in the article signatures",
+    ),
+    (
+        "theme_directory",
+        "Optional directory with the site's theme. Its `style.css` is added to every page, and\n\
+        `shortcodes/<name>.html` are the templates of the shortcodes",
+    ),
+    (
+        "base_url",
+        "Where the site is reachable from the outside: a full URL (`https://example.com/blog/`)\n\
+        or just a path (`/blog/`). The site is served under the path of this URL, and the\n\
+        canonical links of the pages are made from it",
+    ),
+    (
+        "trust_forwarded_prefix",
+        "Whether the `X-Forwarded-Prefix` header means that the proxy has already stripped the\n\
+        path of `base_url` from the request. The redirects then lead to the forwarded prefix,\n\
+        and the prefixes with `.` or `..` are rejected. Enable only behind a proxy that sets\n\
+        this header",
+    ),
+    (
+        "symlinks",
+        "What to do with the symbolic links in the files directory: \"deny\" (never serve them),\n\
+        \"inside_files_directory\" (serve them if they point inside the files directory) or\n\
+        \"follow\" (serve them wherever they point)",
+    ),
+    (
+        "directory_listing",
+        "Whether the directories of the files directory are shown as lists of their files",
+    ),
+    (
+        "serve_hidden_files",
+        "Whether the files and directories whose names start with `.` are served",
+    ),
+    (
+        "fingerprint_files",
+        "Whether the links from the articles to the files get content hashes in their names\n\
+        (`files/cat.3f9a1c0b2d.png`), so the browsers can cache the files forever",
+    ),
+    (
+        "image_widths",
+        "Widths (in pixels) of the resized copies of the images from the files directory that\n\
+        the articles show. The browsers pick the smallest one that fits. An empty list turns the\n\
+        resizing off",
+    ),
+    (
+        "markdown_extensions",
+        "Extensions of Markdown: `tables`, `footnotes`, `strikethrough`, `task_lists`,\n\
+        `smart_punctuation` (curly quotes, dashes and ellipses), `heading_attributes`\n\
+        (`# Heading {#id .class}`), `math` (`$inline$` and `$$display$$` LaTeX formulas,\n\
+        rendered to MathML), `shortcodes` and `includes`. Only `footnotes` and `strikethrough`\n\
+        are on by default. An article can turn them on and off with a `markdown` map in its\n\
+        front matter",
+    ),
+    (
+        "diagram_language",
+        "Language of the fenced code blocks (```bob) that are ASCII diagrams, which are drawn as\n\
+        SVG in the colors of the article. An empty string turns the diagrams off",
+    ),
+    (
+        "include_directory",
+        "Optional directory that `{{< include >}}` can read the files from, besides the articles\n\
+        and the files directories (the repository of the code that the articles show, for\n\
+        example). Relative paths are taken relative to this file",
+    ),
+    (
+        "bibliography",
+        "Optional BibTeX file that the `[@key]` citations of the articles are looked up in.\n\
+        Relative paths are taken relative to this file. An article can use another one with\n\
+        `bibliography` in its front matter (relative to the article's Markdown file)",
+    ),
+    (
+        "citation_style",
+        "How the citations look: \"numbered\" (`[1]`, with the references in the order of the\n\
+        citations) or \"author_year\" (`(Knuth 1984)`, with the references sorted by their\n\
+        authors). An article can override it with `citation_style` in its front matter",
+    ),
+];
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[allow(clippy::struct_excessive_bools)] // The switches of the configuration file
+pub struct SiteBase<ArticlesDirectoryPath> {
+    pub host_names: Vec<String>,
+    pub author_name: String,
+    pub index_page_colors: Vec<PageColors>,
+    pub articles_directory: ArticlesDirectoryPath,
+    pub files_directory: PathBuf,
+    pub date_format: String,
+    pub theme_directory: Option<PathBuf>,
+    #[schemars(with = "String")]
+    pub base_url: BaseUrl,
+    pub trust_forwarded_prefix: bool,
+    pub symlinks: SymlinkPolicy,
+    pub directory_listing: bool,
+    pub serve_hidden_files: bool,
+    pub fingerprint_files: bool,
+    pub image_widths: Vec<u32>,
+    pub markdown_extensions: MarkdownExtensions,
+    pub diagram_language: String,
+    pub include_directory: Option<PathBuf>,
+    pub bibliography: Option<PathBuf>,
+    pub citation_style: CitationStyle,
+}
+
+impl SiteBase<PathBuf> {
+    /// Returns a sample site, which should __not__ be used in production (because it lacks the
+    /// author's name)
+    pub fn sample() -> Self {
+        Self {
+            host_names: Vec::new(),
+            author_name: "<author name>".into(),
+            index_page_colors: vec![
+                PageColors::new("C8566B", "F6E5E8"),
+                PageColors::new("E78963", "FBEDE7"),
+                PageColors::new("F2D48F", "FDF8EE"),
+                PageColors::new("9D75BF", "F0EAF5"),
+                PageColors::new("9EC299", "F0F5EF"),
+                PageColors::new("6661AB", "E8E7F2"),
+            ],
+            articles_directory: "articles".into(),
+            files_directory: "files".into(),
+            date_format: "%Y.%m.%d".into(),
+            theme_directory: None,
+            base_url: BaseUrl::default(),
+            trust_forwarded_prefix: false,
+            symlinks: SymlinkPolicy::InsideFilesDirectory,
+            directory_listing: false,
+            serve_hidden_files: false,
+            fingerprint_files: true,
+            image_widths: vec![480, 960, 1600],
+            markdown_extensions: MarkdownExtensions::default(),
+            diagram_language: "bob".into(),
+            include_directory: None,
+            bibliography: None,
+            citation_style: CitationStyle::default(),
+        }
+    }
+
+    /// Upgrades itself to [`SiteConfig`],
+    /// returning an error (and the failed path) if `articles_directory.canonicalize()` failed.
+    pub fn upgrade(self) -> Result<SiteConfig, (io::Error, PathBuf)> {
+        let articles_directory = expand_articles_directory(self.articles_directory)?;
+        Ok(SiteConfig {
+            articles_directory,
+            host_names: self.host_names,
+            author_name: self.author_name,
+            date_format: self.date_format,
+            files_directory: file::resolve(self.files_directory),
+            index_page_colors: self.index_page_colors,
+            theme_directory: self.theme_directory.map(file::resolve),
+            base_url: self.base_url,
+            trust_forwarded_prefix: self.trust_forwarded_prefix,
+            symlinks: self.symlinks,
+            directory_listing: self.directory_listing,
+            serve_hidden_files: self.serve_hidden_files,
+            fingerprint_files: self.fingerprint_files,
+            image_widths: self.image_widths,
+            markdown_extensions: self.markdown_extensions,
+            diagram_language: self.diagram_language,
+            include_directory: self.include_directory.map(file::resolve),
+            bibliography: self.bibliography.map(file::resolve),
+            citation_style: self.citation_style,
+        })
+    }
+}
+
+fn expand_articles_directory(
+    articles_directory: PathBuf,
+) -> Result<AbsolutePath<PathBuf>, (io::Error, PathBuf)> {
+    match AbsolutePath::new(articles_directory) {
+        Ok(articles_directory) => Ok(articles_directory),
+        Err(articles_directory) => match file::resolve(&articles_directory).canonicalize() {
+            Ok(articles_directory) => Ok(AbsolutePath::new(articles_directory).unwrap()),
+            Err(error) => Err((error, articles_directory)),
+        },
+    }
+}
+
+/// Working config of a single site that should be used in the server's code
+pub type SiteConfig = SiteBase<AbsolutePath<PathBuf>>;
+
+impl SiteConfig {
+    pub fn files_policy(&self) -> FilesPolicy<'_> {
+        FilesPolicy {
+            files_directory: &self.files_directory,
+            symlinks: self.symlinks,
+            serve_hidden_files: self.serve_hidden_files,
+        }
+    }
+
+    /// The files of a page bundle are served like the ones from `files_directory`
+    pub const fn bundle_policy<'bundle>(
+        &'bundle self,
+        bundle_directory: &'bundle Path,
+    ) -> FilesPolicy<'bundle> {
+        FilesPolicy {
+            files_directory: bundle_directory,
+            symlinks: self.symlinks,
+            serve_hidden_files: self.serve_hidden_files,
+        }
+    }
+
+    /// Contents of `style.css` from the theme directory, or an empty string if there's no theme
+    pub fn theme_style(&self) -> String {
+        self.theme_directory
+            .as_ref()
+            .and_then(|theme_directory| fs::read_to_string(theme_directory.join("style.css")).ok())
+            .unwrap_or_default()
+    }
+
+    /// Watches the new articles directory, if it's not the same. Returns whether it was changed
+    fn change_articles_directory(
+        &mut self,
+        articles_directory: PathBuf,
+        articles_watch_context: &Mutex<WatchContext<ArticlesWatcher>>,
+    ) -> bool {
+        match expand_articles_directory(articles_directory) {
+            Ok(articles_directory) => {
+                if articles_directory.as_ref() == self.articles_directory.as_ref() {
+                    return false;
+                }
+                match watch_articles_directory(articles_directory.as_ref()) {
+                    Ok(new_context) => {
+                        *articles_watch_context.lock().unwrap() = new_context;
+                        self.articles_directory = articles_directory;
+                        return true;
+                    }
+                    Err(error) => {
+                        error!("An error occured while changing the articles directory: {error}");
+                    }
+                }
+            }
+            Err((error, _articles_directory)) => {
+                error!(
+                    "An error occured while getting the full path to the articles directory \
+                    (which was changed in the config): {error}"
+                );
+            }
+        }
+        false
+    }
+
+    #[deny(unused_variables)] // Unused variables will mean that I haven't handled everything
+    pub fn update(
+        old_config: &Mutex<Self>,
+        new_config: SiteBase<PathBuf>,
+        website: &Mutex<Website>,
+        articles_watch_context: &Arc<Mutex<WatchContext<ArticlesWatcher>>>,
+        reload_everything: bool,
+    ) {
+        let mut reload_articles = reload_everything;
+        let mut reload_index = reload_everything;
+        {
+            let mut old_config = old_config.lock().unwrap();
+            macro_rules! if_changed {
+                ($field_name:ident, $body:block) => {
+                    if old_config.$field_name != $field_name {
+                        $body
+                        old_config.$field_name = $field_name;
+                    }
+                };
+            }
+            let SiteBase {
+                host_names,
+                author_name,
+                index_page_colors,
+                articles_directory,
+                files_directory,
+                date_format,
+                theme_directory,
+                base_url,
+                trust_forwarded_prefix,
+                symlinks,
+                directory_listing,
+                serve_hidden_files,
+                fingerprint_files,
+                image_widths,
+                markdown_extensions,
+                diagram_language,
+                include_directory,
+                bibliography,
+                citation_style,
+            } = new_config;
+            // Hosts are looked up in `Sites`, which is updated by the caller
+            if_changed!(host_names, {});
+            if_changed!(author_name, {
+                reload_articles = true;
+                reload_index = true;
+            });
+            // The diagrams of the articles are drawn in these colors
+            if_changed!(index_page_colors, {
+                reload_articles = true;
+                reload_index = true;
+            });
+            if old_config.change_articles_directory(articles_directory, articles_watch_context) {
+                reload_articles = true;
+                reload_index = true;
+            }
+            let files_directory = file::resolve(files_directory);
+            // The articles link to the files by their hashes
+            if_changed!(files_directory, {
+                reload_articles = true;
+            });
+            if_changed!(date_format, {
+                reload_articles = true;
+            });
+            let theme_directory = theme_directory.map(file::resolve);
+            if_changed!(theme_directory, {
+                reload_articles = true;
+                reload_index = true;
+            });
+            if_changed!(base_url, {
+                reload_articles = true;
+                reload_index = true;
+            });
+            if_changed!(trust_forwarded_prefix, {});
+            if_changed!(symlinks, {
+                reload_articles = true;
+            });
+            if_changed!(directory_listing, {});
+            if_changed!(serve_hidden_files, {
+                reload_articles = true;
+            });
+            if_changed!(fingerprint_files, {
+                reload_articles = true;
+            });
+            if_changed!(image_widths, {
+                reload_articles = true;
+            });
+            if_changed!(markdown_extensions, {
+                reload_articles = true;
+            });
+            if_changed!(diagram_language, {
+                reload_articles = true;
+            });
+            let include_directory = include_directory.map(file::resolve);
+            if_changed!(include_directory, {
+                reload_articles = true;
+            });
+            let bibliography = bibliography.map(file::resolve);
+            if_changed!(bibliography, {
+                reload_articles = true;
+            });
+            if_changed!(citation_style, {
+                reload_articles = true;
+            });
+        }
+        if reload_articles {
+            website.lock().unwrap().reload_articles();
+        }
+        if reload_index {
+            website.lock().unwrap().reload_index_variants();
+        }
+    }
+}
+
+#[cfg(test)]
+impl SiteConfig {
+    /// The sample site with the articles in `directory/articles` and the files in
+    /// `directory/files` (the directories are not created)
+    pub fn for_tests(directory: &Path) -> Self {
+        let sample = SiteBase::sample();
+        Self {
+            host_names: sample.host_names,
+            author_name: sample.author_name,
+            index_page_colors: sample.index_page_colors,
+            articles_directory: AbsolutePath::new(directory.join("articles")).unwrap(),
+            files_directory: directory.join("files"),
+            date_format: sample.date_format,
+            theme_directory: None,
+            base_url: sample.base_url,
+            trust_forwarded_prefix: sample.trust_forwarded_prefix,
+            symlinks: sample.symlinks,
+            directory_listing: sample.directory_listing,
+            serve_hidden_files: sample.serve_hidden_files,
+            fingerprint_files: sample.fingerprint_files,
+            image_widths: sample.image_widths,
+            markdown_extensions: sample.markdown_extensions,
+            diagram_language: sample.diagram_language,
+            include_directory: None,
+            bibliography: None,
+            citation_style: sample.citation_style,
+        }
+    }
+}
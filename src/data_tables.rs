@@ -0,0 +1,406 @@
+use std::{cmp::Ordering, fmt::Write, fs, path::Path};
+
+use serde_json::Value;
+
+use crate::shortcodes::{Arguments, Context};
+
+/// Rows of a CSV file (or a TSV one, with `delimiter` being a tab). The quoted fields can have
+/// the delimiters, the line breaks and the `""` quotes
+fn parse_csv(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut is_quoted = false;
+    let mut is_field_started = false;
+    let mut characters = text.trim_start_matches('\u{FEFF}').chars().peekable();
+    let mut line = 1;
+    let mut quote_line = 1;
+    while let Some(character) = characters.next() {
+        match character {
+            '"' if is_quoted => {
+                if characters.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    is_quoted = false;
+                }
+            }
+            '"' if !is_field_started => {
+                is_quoted = true;
+                quote_line = line;
+                is_field_started = true;
+            }
+            '\n' if is_quoted => {
+                line += 1;
+                field.push(character);
+            }
+            character if is_quoted => field.push(character),
+            '\r' if characters.peek() == Some(&'\n') => (),
+            '\n' => {
+                line += 1;
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+                is_field_started = false;
+            }
+            character if character == delimiter => {
+                row.push(std::mem::take(&mut field));
+                is_field_started = false;
+            }
+            character => {
+                field.push(character);
+                is_field_started = true;
+            }
+        }
+    }
+    if is_quoted {
+        return Err(format!("a quoted field on line {quote_line} is not closed"));
+    }
+    if is_field_started || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    // Blank lines (like the one at the end) are not rows
+    rows.retain(|row| !(row.len() == 1 && row[0].is_empty()));
+    Ok(rows)
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// An array of objects (their keys are the columns) or an array of arrays (the first one is the
+/// header)
+fn parse_json(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let value: Value = serde_json::from_str(text).map_err(|error| error.to_string())?;
+    let Value::Array(items) = value else {
+        return Err("the JSON file must contain an array".to_owned());
+    };
+    if items.iter().all(Value::is_array) {
+        return Ok(items
+            .iter()
+            .map(|item| item.as_array().unwrap().iter().map(cell).collect())
+            .collect());
+    }
+    if !items.iter().all(Value::is_object) {
+        return Err("the JSON array must contain either objects or arrays".to_owned());
+    }
+    let mut header: Vec<String> = Vec::new();
+    for item in &items {
+        for key in item.as_object().unwrap().keys() {
+            if !header.contains(key) {
+                header.push(key.clone());
+            }
+        }
+    }
+    let mut rows = vec![header.clone()];
+    for item in &items {
+        let object = item.as_object().unwrap();
+        rows.push(
+            header
+                .iter()
+                .map(|key| object.get(key).map(cell).unwrap_or_default())
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+/// `12`, `-3.5` or `42%`
+fn number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let number = text.strip_suffix('%').unwrap_or(text).trim();
+    number
+        .contains(|character: char| character.is_ascii_digit())
+        .then(|| number.parse::<f64>().ok())
+        .flatten()
+        .filter(|number| number.is_finite())
+}
+
+/// Numbers (by their values) go before the text (in the alphabetical order), so the columns with
+/// both are still sorted consistently
+fn compare(first: &str, second: &str) -> Ordering {
+    match (number(first), number(second)) {
+        (Some(first), Some(second)) => first.total_cmp(&second),
+        (Some(_number), None) => Ordering::Less,
+        (None, Some(_number)) => Ordering::Greater,
+        (None, None) => first.cmp(second),
+    }
+}
+
+/// Sorts the rows by the values of the column. The rows with the same values keep their order
+fn sort(rows: &mut [Vec<String>], column: usize, is_descending: bool) {
+    rows.sort_by(|first, second| {
+        let ordering = compare(&first[column], &second[column]);
+        if is_descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+fn column_index(header: &[String], name: &str) -> Result<usize, String> {
+    header
+        .iter()
+        .position(|column| column.trim() == name)
+        .ok_or_else(|| {
+            format!(
+                "there's no column `{name}` (the columns are: {})",
+                header.join(", ")
+            )
+        })
+}
+
+/// Indices of the columns from `columns="name, time"`, or of every column
+fn columns(header: &[String], names: Option<&str>) -> Result<Vec<usize>, String> {
+    names.map_or_else(
+        || Ok((0..header.len()).collect()),
+        |names| {
+            names
+                .split(',')
+                .map(|name| column_index(header, name.trim()))
+                .collect()
+        },
+    )
+}
+
+fn read_rows(path: &Path) -> Result<Vec<Vec<String>>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|error| format!("`{}` cannot be read: {error}", path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let rows = match extension.as_str() {
+        "csv" => parse_csv(&text, ','),
+        "tsv" => parse_csv(&text, '\t'),
+        "json" => parse_json(&text),
+        _ => {
+            return Err(format!(
+                "`{}` is not a CSV, TSV or JSON file",
+                path.display()
+            ))
+        }
+    }
+    .map_err(|error| format!("`{}` cannot be parsed: {error}", path.display()))?;
+    if rows.is_empty() {
+        return Err(format!("`{}` has no header", path.display()));
+    }
+    Ok(rows)
+}
+
+/// The header, and the rows with as many values as there are columns
+fn read_table(path: &Path) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    let mut rows = read_rows(path)?;
+    let header = rows.remove(0);
+    for (index, row) in rows.iter_mut().enumerate() {
+        if row.len() > header.len() {
+            return Err(format!(
+                "row {} has {} values, but there are {} columns",
+                index + 1,
+                row.len(),
+                header.len()
+            ));
+        }
+        row.resize(header.len(), String::new());
+    }
+    Ok((header, rows))
+}
+
+/// `{{< table src="results.csv" columns="name, time" sort="time" order="descending"
+/// number_alignment="right" caption="..." >}}`: a table of a CSV, TSV or JSON file from the page
+/// bundle, from the directory of the article or from the files directory. The columns where every
+/// value is a number are aligned (to the right by default)
+pub fn render(arguments: &Arguments, context: &mut dyn Context) -> Result<String, String> {
+    let src = &arguments["src"];
+    let path = context
+        .data_file(src)
+        .ok_or_else(|| format!("there's no file `{src}`"))?;
+    let (header, mut rows) = read_table(&path)?;
+    let columns = columns(&header, arguments.get("columns").map(String::as_str))?;
+    let is_descending = match arguments.get("order").map(String::as_str) {
+        None | Some("ascending") => false,
+        Some("descending") => true,
+        Some(order) => {
+            return Err(format!(
+                "`order` must be `ascending` or `descending`, not `{order}`"
+            ))
+        }
+    };
+    if let Some(name) = arguments.get("sort") {
+        sort(
+            &mut rows,
+            column_index(&header, name.trim())?,
+            is_descending,
+        );
+    } else if arguments.contains_key("order") {
+        return Err("`order` needs `sort`".to_owned());
+    }
+    let number_alignment = match arguments.get("number_alignment").map(String::as_str) {
+        None | Some("right") => "right",
+        Some(alignment @ ("left" | "center")) => alignment,
+        Some(alignment) => {
+            return Err(format!(
+                "`number_alignment` must be `left`, `center` or `right`, not `{alignment}`"
+            ))
+        }
+    };
+    let styles: Vec<_> = columns
+        .iter()
+        .map(|&column| {
+            let values = rows.iter().map(|row| row[column].trim());
+            let is_numeric = values.clone().any(|value| !value.is_empty())
+                && values
+                    .filter(|value| !value.is_empty())
+                    .all(|value| number(value).is_some());
+            if is_numeric {
+                format!(r#" style="text-align: {number_alignment}""#)
+            } else {
+                String::new()
+            }
+        })
+        .collect();
+    let mut html = String::from(r#"<table class="data-table">"#);
+    if let Some(caption) = arguments.get("caption") {
+        write!(
+            html,
+            "<caption>{}</caption>",
+            html_escape::encode_text(caption)
+        )
+        .unwrap();
+    }
+    html.push_str("<thead><tr>");
+    for (&column, style) in columns.iter().zip(&styles) {
+        write!(
+            html,
+            "<th{style}>{}</th>",
+            html_escape::encode_text(header[column].trim())
+        )
+        .unwrap();
+    }
+    html.push_str("</tr></thead><tbody>");
+    for row in &rows {
+        html.push_str("<tr>");
+        for (&column, style) in columns.iter().zip(&styles) {
+            write!(
+                html,
+                "<td{style}>{}</td>",
+                html_escape::encode_text(&row[column])
+            )
+            .unwrap();
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</tbody></table>");
+    Ok(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{columns, parse_csv, parse_json, sort};
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|&value| value.to_owned()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn parses_quoted_fields() {
+        let text =
+            "\u{FEFF}name,notes\r\n\"Smith, J.\",\"said \"\"hi\"\"\nand left\"\r\n\r\nDoe,\n";
+        assert_eq!(
+            parse_csv(text, ',').unwrap(),
+            rows(&[
+                &["name", "notes"],
+                &["Smith, J.", "said \"hi\"\nand left"],
+                &["Doe", ""],
+            ])
+        );
+        assert_eq!(
+            parse_csv("a\tb, c\n1\t2", '\t').unwrap(),
+            rows(&[&["a", "b, c"], &["1", "2"]])
+        );
+        assert_eq!(
+            parse_csv("a,b\n\"1,2\n", ',').unwrap_err(),
+            "a quoted field on line 2 is not closed"
+        );
+    }
+
+    #[test]
+    fn parses_json() {
+        let objects = r#"[{"name": "a", "time": 1.5}, {"time": null, "place": "b"}]"#;
+        assert_eq!(
+            parse_json(objects).unwrap(),
+            rows(&[
+                &["name", "time", "place"],
+                &["a", "1.5", ""],
+                &["", "", "b"],
+            ])
+        );
+        assert_eq!(
+            parse_json(r#"[["name", "time"], ["a", 2]]"#).unwrap(),
+            rows(&[&["name", "time"], &["a", "2"]])
+        );
+        assert!(parse_json(r#"{"name": "a"}"#).is_err());
+        assert!(parse_json(r#"[{"name": "a"}, ["a"]]"#).is_err());
+    }
+
+    #[test]
+    fn chooses_columns() {
+        let header = rows(&[&["name", " time ", "place"]]).remove(0);
+        assert_eq!(columns(&header, None).unwrap(), [0, 1, 2]);
+        assert_eq!(columns(&header, Some("place, time")).unwrap(), [2, 1]);
+        assert_eq!(
+            columns(&header, Some("name, date")).unwrap_err(),
+            "there's no column `date` (the columns are: name,  time , place)"
+        );
+    }
+
+    #[test]
+    fn sorts_numbers_before_text() {
+        let mut table = rows(&[
+            &["b", "10"],
+            &["c", "n/a"],
+            &["d", "9.5"],
+            &["e", "50%"],
+            &["f", ""],
+            &["g", "-1"],
+            &["h", "abc"],
+            &["i", "9.5"],
+        ]);
+        sort(&mut table, 1, false);
+        let names: Vec<_> = table.iter().map(|row| row[0].as_str()).collect();
+        assert_eq!(names, ["g", "d", "i", "b", "e", "f", "h", "c"]);
+        sort(&mut table, 1, true);
+        let names: Vec<_> = table.iter().map(|row| row[0].as_str()).collect();
+        assert_eq!(names, ["c", "h", "f", "e", "b", "d", "i", "g"]);
+    }
+
+    #[test]
+    fn sorts_many_mixed_values() {
+        // A comparison that isn't a total order can make the sort panic with enough rows
+        let mut table: Vec<_> = (0..500)
+            .map(|index| {
+                let value = match index % 3 {
+                    0 => index.to_string(),
+                    1 => format!("text {}", index % 7),
+                    _ => String::new(),
+                };
+                vec![value]
+            })
+            .collect();
+        sort(&mut table, 0, false);
+        let first_text = table
+            .iter()
+            .position(|row| super::number(&row[0]).is_none())
+            .unwrap();
+        assert!(table[first_text..]
+            .iter()
+            .all(|row| super::number(&row[0]).is_none()));
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fmt, path::PathBuf, sync::Arc};
+
+use crate::external_links::Outcome;
+
+/// Something wrong with an article that doesn't stop it from being served
+#[derive(Clone)]
+pub enum Problem {
+    /// A link to a page or a file that doesn't exist
+    BrokenLink(String),
+    /// A link to a part of a page that doesn't have it
+    MissingAnchor(String),
+    /// A wiki link to no article (as [`crate::page_compilers::normalize_wiki_name`] returns it)
+    MissingWikiLink(String),
+    /// An alias that doesn't lead to the article, because another article has the path or the
+    /// article's slug
+    ConflictingAlias { alias: Arc<str>, details: String },
+    /// The title is shared with the other articles
+    DuplicateTitle {
+        title: Arc<str>,
+        others: Vec<Arc<str>>,
+    },
+    /// Nothing except the front matter
+    EmptyArticle,
+    /// The front matter cannot be parsed, so it's ignored
+    InvalidFrontMatter(String),
+    /// An ASCII diagram that cannot be drawn, so it's shown as text
+    InvalidDiagram(String),
+    /// A formula that cannot be rendered, so it's shown as it was written
+    InvalidMath { formula: String, details: String },
+    /// A shortcode that cannot be expanded, so it's shown as it was written
+    InvalidShortcode { shortcode: String, details: String },
+    /// An include directive whose file cannot be included, so it's shown as it was written
+    InvalidInclude { include: String, details: String },
+    /// A citation of a key that the bibliography doesn't have, so it's marked as missing
+    UnknownCitation(String),
+    /// The BibTeX file cannot be read or parsed, so none of the citations can be resolved
+    InvalidBibliography { path: PathBuf, details: String },
+    /// A link to another site that doesn't simply work
+    ExternalLink { link: String, outcome: Outcome },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BrokenLink(link) => write!(formatter, "link `{link}` leads nowhere"),
+            Self::MissingAnchor(link) => write!(
+                formatter,
+                "link `{link}` leads to a part of the page that doesn't exist"
+            ),
+            Self::MissingWikiLink(target) => {
+                write!(formatter, "wiki link `{target}` leads to no article")
+            }
+            Self::ConflictingAlias { alias, details } => write!(
+                formatter,
+                "alias `{alias}` doesn't lead to the article. Details: {details}"
+            ),
+            Self::DuplicateTitle { title, others } => write!(
+                formatter,
+                "title `{title}` is also used by {}",
+                itertools::Itertools::intersperse(
+                    others.iter().map(|file_name| format!("`{file_name}`")),
+                    ", ".to_owned()
+                )
+                .collect::<String>()
+            ),
+            Self::EmptyArticle => write!(formatter, "the article is empty"),
+            Self::InvalidFrontMatter(details) => write!(
+                formatter,
+                "the front matter cannot be parsed, so it's ignored. Details: {details}"
+            ),
+            Self::InvalidDiagram(details) => write!(
+                formatter,
+                "a diagram cannot be drawn, so it's shown as text. Details: {details}"
+            ),
+            Self::InvalidMath { formula, details } => write!(
+                formatter,
+                "formula `{formula}` cannot be rendered. Details: {details}"
+            ),
+            Self::InvalidShortcode { shortcode, details } => write!(
+                formatter,
+                "shortcode `{shortcode}` cannot be expanded. Details: {details}"
+            ),
+            Self::InvalidInclude { include, details } => write!(
+                formatter,
+                "`{include}` cannot be included. Details: {details}"
+            ),
+            Self::UnknownCitation(key) => {
+                write!(formatter, "citation `@{key}` is not in the bibliography")
+            }
+            Self::InvalidBibliography { path, details } => write!(
+                formatter,
+                "bibliography `{}` cannot be used. Details: {details}",
+                path.display()
+            ),
+            Self::ExternalLink { link, outcome } => {
+                write!(formatter, "link `{link}` {outcome}")
+            }
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub file_name: Arc<str>,
+    pub problem: Problem,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "`{}`: {}", self.file_name, self.problem)
+    }
+}
+
+/// A link of an article that can only be checked when every article is compiled
+pub enum LinkCheck {
+    /// `#part`, `other.md#part` or `[[Other#part]]`
+    Anchor {
+        link: String,
+        /// File name of the other article, or `None` for the article itself
+        article: Option<Arc<str>>,
+        anchor: String,
+    },
+    /// A link to a path of the site (an article, a file or a redirect)
+    Path {
+        link: String,
+        /// Relative to the root of the site
+        path: String,
+        /// Empty if there's no fragment
+        anchor: String,
+    },
+}
+
+/// Values of the `id` and `name` attributes of the compiled page, which the fragments of the links
+/// can lead to
+pub fn anchors_of(html: &str) -> Vec<String> {
+    let mut anchors = Vec::new();
+    for attribute in [" id=\"", " name=\""] {
+        let mut rest = html;
+        while let Some(start) = rest.find(attribute) {
+            rest = &rest[start + attribute.len()..];
+            let Some(end) = rest.find('"') else {
+                break;
+            };
+            anchors.push(html_escape::decode_html_entities(&rest[..end]).into_owned());
+            rest = &rest[end..];
+        }
+    }
+    anchors
+}
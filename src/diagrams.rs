@@ -0,0 +1,96 @@
+use std::{cell::Cell, panic, sync::Once};
+
+use svgbob::Settings;
+
+use crate::{config::SiteConfig, page_colors::PageColors};
+
+/// Colors of an article: one of the index page colors, always the same for the same slug
+pub fn article_colors(config: &SiteConfig, slug: &str) -> PageColors {
+    if config.index_page_colors.is_empty() {
+        return PageColors::default();
+    }
+    let hash = slug.bytes().fold(0_usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(usize::from(byte))
+    });
+    config.index_page_colors[hash % config.index_page_colors.len()].clone()
+}
+
+thread_local! {
+    /// Set while the renderer runs on this thread, so its panics aren't printed
+    static IS_RENDERING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The panics of the renderer are reported as the problems of the articles, not printed to the
+/// terminal. The other panics are printed as usual
+fn silence_renderer_panics() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !IS_RENDERING.with(Cell::get) {
+                default_hook(info);
+            }
+        }));
+    });
+}
+
+/// Runs the renderer, returning the message of its panic as the error
+fn quietly_catch_panic<T>(render: impl FnOnce() -> T + panic::UnwindSafe) -> Result<T, String> {
+    silence_renderer_panics();
+    IS_RENDERING.with(|is_rendering| is_rendering.set(true));
+    let result = panic::catch_unwind(render);
+    IS_RENDERING.with(|is_rendering| is_rendering.set(false));
+    result.map_err(|payload| {
+        payload
+            .downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "the renderer panicked".to_owned())
+    })
+}
+
+/// Inline SVG of an ASCII diagram, drawn in the title color of the article. Returns the reason if
+/// the diagram cannot be rendered
+pub fn render(ascii: &str, colors: &PageColors) -> Result<String, String> {
+    let settings = Settings {
+        stroke_color: format!("#{}", colors.title()),
+        fill_color: format!("#{}", colors.title()),
+        background: format!("#{}", colors.background()),
+        include_backdrop: false,
+        ..Settings::default()
+    };
+    // The renderer panics instead of returning errors
+    let svg = quietly_catch_panic(|| svgbob::to_svg_with_settings(ascii, &settings))?;
+    Ok(format!(r#"<figure class="diagram">{svg}</figure>"#))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{quietly_catch_panic, render};
+    use crate::page_colors::PageColors;
+
+    #[test]
+    fn renders_diagrams_in_the_colors() {
+        let svg = render("+--+\n|  |\n+--+", &PageColors::new("C8566B", "F6E5E8")).unwrap();
+        assert!(
+            svg.starts_with(r#"<figure class="diagram"><svg"#),
+            "{}",
+            svg
+        );
+        assert!(svg.contains("#C8566B"), "{}", svg);
+    }
+
+    #[test]
+    fn panics_become_errors() {
+        let number = 3;
+        assert_eq!(
+            quietly_catch_panic(|| panic!("no {number} here")),
+            Err::<(), _>("no 3 here".to_owned())
+        );
+        assert_eq!(
+            quietly_catch_panic(|| panic!("static message")),
+            Err::<(), _>("static message".to_owned())
+        );
+        assert_eq!(quietly_catch_panic(|| number), Ok(3));
+    }
+}
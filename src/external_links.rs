@@ -0,0 +1,591 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::StopFlag;
+
+/// How many hosts are checked at the same time
+const CONCURRENT_HOSTS: usize = 8;
+
+/// What checking a link to another site showed
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum Outcome {
+    Working,
+    Redirected {
+        status: u16,
+        location: String,
+    },
+    /// 4xx or 5xx
+    Failed {
+        status: u16,
+    },
+    TimedOut,
+    /// DNS, connection, TLS and other errors
+    Unreachable {
+        details: String,
+    },
+}
+
+impl Outcome {
+    pub const fn is_working(&self) -> bool {
+        matches!(self, Self::Working)
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Working => write!(formatter, "works"),
+            Self::Redirected { status, location } => {
+                write!(formatter, "redirects ({status}) to `{location}`")
+            }
+            Self::Failed { status } => write!(formatter, "responds with {status}"),
+            Self::TimedOut => write!(formatter, "doesn't respond in time"),
+            Self::Unreachable { details } => write!(formatter, "cannot be reached: {details}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Method {
+    Head,
+    Get,
+}
+
+pub struct Response {
+    pub status: u16,
+    pub location: Option<String>,
+}
+
+pub enum RequestError {
+    TimedOut,
+    Other(String),
+}
+
+/// Makes the requests of the checker. Redirects must not be followed, so they can be reported
+pub trait HttpClient: Send + Sync {
+    fn request(&self, method: Method, url: &str) -> Result<Response, RequestError>;
+}
+
+/// The client that goes to the actual sites
+pub struct UreqClient {
+    agent: ureq::Agent,
+}
+
+impl UreqClient {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new()
+                .timeout(timeout)
+                .redirects(0)
+                .user_agent(concat!(
+                    env!("CARGO_PKG_NAME"),
+                    "/",
+                    env!("CARGO_PKG_VERSION"),
+                    " (link checker)"
+                ))
+                .build(),
+        }
+    }
+}
+
+impl HttpClient for UreqClient {
+    fn request(&self, method: Method, url: &str) -> Result<Response, RequestError> {
+        let request = match method {
+            Method::Head => self.agent.head(url),
+            Method::Get => self.agent.get(url),
+        };
+        match request.call() {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(Response {
+                status: response.status(),
+                location: response.header("Location").map(ToOwned::to_owned),
+            }),
+            Err(error) => {
+                let mut source = error.source();
+                while let Some(cause) = source {
+                    if let Some(io_error) = cause.downcast_ref::<io::Error>() {
+                        if matches!(
+                            io_error.kind(),
+                            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                        ) {
+                            return Err(RequestError::TimedOut);
+                        }
+                    }
+                    source = cause.source();
+                }
+                Err(RequestError::Other(error.to_string()))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedOutcome {
+    /// Seconds since the Unix epoch
+    checked_at: u64,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+/// Results of the previous checks, kept in `<articles directory>.links.json`, so the working links
+/// aren't requested on every check
+pub struct LinkCache {
+    path: PathBuf,
+    map: BTreeMap<String, CachedOutcome>,
+}
+
+impl LinkCache {
+    pub fn path_for(articles_directory: &Path) -> PathBuf {
+        let mut file_name = articles_directory
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        file_name.push(".links.json");
+        articles_directory.with_file_name(file_name)
+    }
+
+    /// A missing or broken file means that nothing was checked yet
+    pub fn load(articles_directory: &Path) -> Self {
+        let path = Self::path_for(articles_directory);
+        let map = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+                error!(
+                    "Link cache `{:?}` cannot be parsed, ignoring it! Details: {}",
+                    path, error
+                );
+                BTreeMap::new()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => {
+                error!(
+                    "Link cache `{:?}` cannot be read, ignoring it! Details: {}",
+                    path, error
+                );
+                BTreeMap::new()
+            }
+        };
+        Self { path, map }
+    }
+
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(
+            &self.path,
+            serde_json::to_string_pretty(&self.map).unwrap() + "\n",
+        )
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Pauses between the requests to the same host. Shared by the checks of every site, because the
+/// sites often link to the same hosts
+pub struct Throttle {
+    delay: Duration,
+    /// Host -> when it can be requested again
+    next_requests: Mutex<HashMap<String, Instant>>,
+}
+
+impl Throttle {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            next_requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until the host can be requested, and takes the turn
+    fn wait_for(&self, host: &str) {
+        let turn = {
+            let mut next_requests = self.next_requests.lock().unwrap();
+            let now = Instant::now();
+            let turn = next_requests
+                .get(host)
+                .map_or(now, |next_request| (*next_request).max(now));
+            next_requests.insert(host.to_owned(), turn + self.delay);
+            turn
+        };
+        thread::sleep(turn.saturating_duration_since(Instant::now()));
+    }
+}
+
+pub struct CheckOptions {
+    pub throttle: Arc<Throttle>,
+    /// Working links are trusted for this long. The broken ones are always checked again
+    pub recheck_after: Duration,
+    /// Raised to stop the check early. The links that were checked by then are still returned
+    /// (and cached)
+    pub interrupted: StopFlag,
+}
+
+fn seconds_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// `https://Example.com:8080/a?b` -> `example.com:8080`
+fn host_of(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_scheme, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    authority
+        .rsplit_once('@')
+        .map_or(authority, |(_user, host)| host)
+        .to_lowercase()
+}
+
+fn check_link(client: &dyn HttpClient, url: &str) -> Outcome {
+    let mut result = client.request(Method::Head, url);
+    // Some servers don't answer `HEAD` requests properly
+    if let Ok(Response {
+        status: 403 | 404 | 405 | 501,
+        ..
+    }) = result
+    {
+        result = client.request(Method::Get, url);
+    }
+    match result {
+        Ok(Response { status, location }) => match status {
+            200..=299 => Outcome::Working,
+            300..=399 => Outcome::Redirected {
+                status,
+                location: location.unwrap_or_default(),
+            },
+            status => Outcome::Failed { status },
+        },
+        Err(RequestError::TimedOut) => Outcome::TimedOut,
+        Err(RequestError::Other(details)) => Outcome::Unreachable { details },
+    }
+}
+
+/// Checks the links that aren't known to work from the cache. The hosts are checked concurrently,
+/// and the links of the same host one by one, as the throttle allows
+#[allow(clippy::needless_pass_by_value)] // So an `Arc` of any client can be passed
+pub fn check(
+    urls: impl IntoIterator<Item = String>,
+    client: Arc<dyn HttpClient>,
+    cache: &mut LinkCache,
+    options: &CheckOptions,
+) -> BTreeMap<String, Outcome> {
+    let now = seconds_since_epoch();
+    let mut outcomes = BTreeMap::new();
+    let mut by_host: HashMap<String, Vec<String>> = HashMap::new();
+    for url in urls {
+        match cache.map.get(&url) {
+            Some(cached)
+                if cached.outcome.is_working()
+                    && now.saturating_sub(cached.checked_at) < options.recheck_after.as_secs() =>
+            {
+                outcomes.insert(url, cached.outcome.clone());
+            }
+            _ => by_host.entry(host_of(&url)).or_default().push(url),
+        }
+    }
+    let pool = threadpool::Builder::new()
+        .num_threads(CONCURRENT_HOSTS)
+        .thread_name("Link checking".into())
+        .build();
+    let (sender, receiver) = mpsc::channel();
+    for (host, urls) in by_host {
+        let (client, sender, throttle) = (client.clone(), sender.clone(), options.throttle.clone());
+        let interrupted = options.interrupted.clone();
+        pool.execute(move || {
+            for url in urls {
+                throttle.wait_for(&host);
+                if interrupted.is_raised() {
+                    return;
+                }
+                let outcome = check_link(&*client, &url);
+                if sender.send((url, outcome)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+    drop(sender);
+    let checked_at = seconds_since_epoch();
+    for (url, outcome) in receiver {
+        cache.map.insert(
+            url.clone(),
+            CachedOutcome {
+                checked_at,
+                outcome: outcome.clone(),
+            },
+        );
+        outcomes.insert(url, outcome);
+    }
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        io::{Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{
+        check, seconds_since_epoch, CachedOutcome, CheckOptions, HttpClient, LinkCache, Method,
+        Outcome, RequestError, Response, Throttle, UreqClient,
+    };
+    use crate::StopFlag;
+
+    /// Answers `/status/<code>` with that status (`/status/301` redirects to `/status/200`), and
+    /// `/slow` after a second
+    fn start_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                thread::spawn(move || {
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.ends_with(b"\r\n\r\n") {
+                        match stream.read(&mut buffer) {
+                            Ok(0) | Err(_) => return,
+                            Ok(count) => request.extend_from_slice(&buffer[..count]),
+                        }
+                    }
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split(' ').nth(1).unwrap_or_default();
+                    let status = if path == "/slow" {
+                        thread::sleep(Duration::from_secs(1));
+                        "200"
+                    } else {
+                        path.strip_prefix("/status/").unwrap_or("404")
+                    };
+                    let location = if status == "301" {
+                        "Location: /status/200\r\n"
+                    } else {
+                        ""
+                    };
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {status} Status\r\n{location}Content-Length: 0\r\nConnection: \
+                        close\r\n\r\n"
+                    );
+                });
+            }
+        });
+        format!("http://{address}")
+    }
+
+    fn cache(entries: impl IntoIterator<Item = (String, CachedOutcome)>) -> LinkCache {
+        LinkCache {
+            path: PathBuf::new(),
+            map: entries.into_iter().collect(),
+        }
+    }
+
+    fn options(delay: Duration) -> CheckOptions {
+        CheckOptions {
+            throttle: Arc::new(Throttle::new(delay)),
+            recheck_after: Duration::from_hours(1),
+            interrupted: StopFlag::default(),
+        }
+    }
+
+    #[test]
+    fn reports_what_the_server_answers() {
+        let server = start_server();
+        let client = Arc::new(UreqClient::new(Duration::from_millis(300)));
+        let urls = [
+            "/status/200",
+            "/status/301",
+            "/status/404",
+            "/status/503",
+            "/slow",
+        ]
+        .map(|path| format!("{server}{path}"));
+        let outcomes = check(
+            urls.clone(),
+            client,
+            &mut cache([]),
+            &options(Duration::ZERO),
+        );
+        let outcomes: Vec<_> = urls.iter().map(|url| outcomes[url].to_string()).collect();
+        assert_eq!(
+            outcomes,
+            [
+                "works",
+                "redirects (301) to `/status/200`",
+                "responds with 404",
+                "responds with 503",
+                "doesn't respond in time",
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_hosts_are_reported() {
+        // Nothing listens on the port after the listener is dropped
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let url = format!("http://{address}/");
+        let client = Arc::new(UreqClient::new(Duration::from_secs(5)));
+        let outcomes = check(
+            [url.clone()],
+            client,
+            &mut cache([]),
+            &options(Duration::ZERO),
+        );
+        assert!(matches!(outcomes[&url], Outcome::Unreachable { .. }));
+    }
+
+    /// Answers every request with the status of its URL, remembering when the requests were made
+    #[derive(Default)]
+    struct StandIn {
+        statuses: HashMap<String, u16>,
+        requests: Mutex<Vec<(String, Instant)>>,
+    }
+
+    impl HttpClient for StandIn {
+        fn request(&self, _method: Method, url: &str) -> Result<Response, RequestError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.to_owned(), Instant::now()));
+            match self.statuses.get(url) {
+                Some(&status) => Ok(Response {
+                    status,
+                    location: None,
+                }),
+                None => Err(RequestError::TimedOut),
+            }
+        }
+    }
+
+    impl StandIn {
+        fn requested(&self) -> Vec<String> {
+            let mut requested: Vec<_> = self
+                .requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(url, _time)| url.clone())
+                .collect();
+            requested.sort();
+            requested
+        }
+    }
+
+    #[test]
+    fn cached_outcomes_expire() {
+        let client = Arc::new(StandIn {
+            statuses: HashMap::from([
+                ("https://a.test/fresh".to_owned(), 200),
+                ("https://a.test/stale".to_owned(), 200),
+                ("https://a.test/broken".to_owned(), 200),
+            ]),
+            ..StandIn::default()
+        });
+        let now = seconds_since_epoch();
+        let cached = |checked_at, outcome| CachedOutcome {
+            checked_at,
+            outcome,
+        };
+        let mut cache = cache([
+            (
+                "https://a.test/fresh".to_owned(),
+                cached(now - 60, Outcome::Working),
+            ),
+            (
+                "https://a.test/stale".to_owned(),
+                cached(now - 2 * 60 * 60, Outcome::Working),
+            ),
+            (
+                "https://a.test/broken".to_owned(),
+                cached(now - 60, Outcome::Failed { status: 404 }),
+            ),
+        ]);
+        let urls: Vec<_> = cache.map.keys().cloned().collect();
+        let outcomes = check(urls, client.clone(), &mut cache, &options(Duration::ZERO));
+        assert_eq!(
+            client.requested(),
+            ["https://a.test/broken", "https://a.test/stale"]
+        );
+        assert!(outcomes.values().all(Outcome::is_working));
+        assert!(cache.map["https://a.test/stale"].checked_at >= now);
+        assert!(cache.map["https://a.test/fresh"].checked_at < now);
+    }
+
+    #[test]
+    fn head_failures_are_retried_with_get() {
+        let client = Arc::new(StandIn {
+            statuses: HashMap::from([("https://a.test/".to_owned(), 405)]),
+            ..StandIn::default()
+        });
+        let outcomes = check(
+            ["https://a.test/".to_owned()],
+            client.clone(),
+            &mut cache([]),
+            &options(Duration::ZERO),
+        );
+        assert_eq!(client.requested().len(), 2);
+        assert_eq!(outcomes["https://a.test/"].to_string(), "responds with 405");
+    }
+
+    #[test]
+    fn requests_to_a_host_are_spaced_across_checks() {
+        let delay = Duration::from_millis(200);
+        let urls = ["https://a.test/1", "https://A.test/2", "https://b.test/1"];
+        let client = Arc::new(StandIn {
+            statuses: urls.iter().map(|url| (url.to_string(), 200)).collect(),
+            ..StandIn::default()
+        });
+        let options = options(delay);
+        // As if two sites linked to the same host
+        for urls in [&urls[..], &urls[..1]] {
+            check(
+                urls.iter().map(ToString::to_string),
+                client.clone(),
+                &mut cache([]),
+                &options,
+            );
+        }
+        let mut times: BTreeMap<&str, Vec<Instant>> = BTreeMap::new();
+        for (url, time) in client.requests.lock().unwrap().iter() {
+            let host = if url.to_lowercase().contains("a.test") {
+                "a"
+            } else {
+                "b"
+            };
+            times.entry(host).or_default().push(*time);
+        }
+        assert_eq!(times["a"].len(), 3);
+        // The turns are spaced exactly, the requests that take them a bit less so
+        for pair in times["a"].windows(2) {
+            assert!(
+                pair[1] - pair[0] >= delay * 9 / 10,
+                "{:?}",
+                pair[1] - pair[0]
+            );
+        }
+        // The other host doesn't wait for this one
+        assert!(times["b"][0] - times["a"][0] < delay);
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use percent_encoding::percent_decode_str;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// What to do with the symbolic links in `files_directory`
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Symbolic links are never served
+    Deny,
+    /// Symbolic links are served if they point somewhere inside `files_directory`
+    InsideFilesDirectory,
+    /// Symbolic links are served wherever they point
+    Follow,
+}
+
+/// How the files from `files_directory` are served
+pub struct FilesPolicy<'config> {
+    pub files_directory: &'config Path,
+    pub symlinks: SymlinkPolicy,
+    pub serve_hidden_files: bool,
+}
+
+pub enum Lookup {
+    File(PathBuf),
+    Directory(PathBuf),
+    /// Doesn't exist or is not allowed to be served, which are indistinguishable from the outside
+    NotFound,
+}
+
+/// `.git`, `.env` and so on
+fn is_hidden(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+/// `my%20photo.png` -> `my photo.png`, like the server decodes the requested paths. Should be done
+/// once, before [`FilesPolicy::lookup`] checks the segments. `None` if the result is not UTF-8
+pub fn decode_path(path: &str) -> Option<Cow<'_, str>> {
+    percent_decode_str(path).decode_utf8().ok()
+}
+
+impl FilesPolicy<'_> {
+    /// Finds the file or the directory at `relative_path` (relative to `files_directory`, with `/`
+    /// as the separator), making sure it doesn't lead outside of `files_directory`
+    pub fn lookup(&self, relative_path: &str) -> Lookup {
+        let mut path = self.files_directory.to_path_buf();
+        for segment in relative_path.split('/') {
+            if segment.is_empty() {
+                // `a//b` and the trailing slash of a directory
+                continue;
+            }
+            if segment == "."
+                || segment == ".."
+                || segment.contains(['\\', ':', '\0'])
+                || (is_hidden(segment) && !self.serve_hidden_files)
+            {
+                return Lookup::NotFound;
+            }
+            path.push(segment);
+            if self.symlinks == SymlinkPolicy::Deny
+                && fs::symlink_metadata(&path).map_or(true, |metadata| metadata.is_symlink())
+            {
+                return Lookup::NotFound;
+            }
+        }
+        if self.symlinks == SymlinkPolicy::InsideFilesDirectory {
+            match (path.canonicalize(), self.files_directory.canonicalize()) {
+                (Ok(real_path), Ok(files_directory)) if real_path.starts_with(&files_directory) => {
+                }
+                _ => return Lookup::NotFound,
+            }
+        }
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => Lookup::File(path),
+            Ok(metadata) if metadata.is_dir() => Lookup::Directory(path),
+            _ => Lookup::NotFound,
+        }
+    }
+
+    /// Names of the entries of a directory that can be served, the directories first (with `/` at
+    /// the end)
+    pub fn list(&self, directory: &Path, relative_path: &str) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(directory) else {
+            return Vec::new();
+        };
+        let relative_path = relative_path.trim_matches('/');
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter_map(|name| {
+                let entry_path = if relative_path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{relative_path}/{name}")
+                };
+                match self.lookup(&entry_path) {
+                    Lookup::File(_) => Some(name),
+                    Lookup::Directory(_) => Some(name + "/"),
+                    Lookup::NotFound => None,
+                }
+            })
+            .collect();
+        names.sort_by(|first, second| {
+            second
+                .ends_with('/')
+                .cmp(&first.ends_with('/'))
+                .then_with(|| first.cmp(second))
+        });
+        names
+    }
+}
+
+// The symbolic links are made the Unix way
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::symlink, path::Path};
+
+    use super::{FilesPolicy, Lookup, SymlinkPolicy};
+
+    /// `files` with a file, a hidden file, a directory and the symbolic links that lead inside and
+    /// outside of it, and `outside/secret.txt` next to it
+    fn files_directory() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let files = root.path().join("files");
+        let outside = root.path().join("outside");
+        fs::create_dir_all(files.join("directory")).unwrap();
+        fs::create_dir(&outside).unwrap();
+        fs::write(files.join("cat.txt"), "cat").unwrap();
+        fs::write(files.join(".env"), "hidden").unwrap();
+        fs::write(files.join("directory/dog.txt"), "dog").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(files.join("cat.txt"), files.join("inside_link.txt")).unwrap();
+        symlink(outside.join("secret.txt"), files.join("outside_link.txt")).unwrap();
+        symlink(&outside, files.join("outside_directory")).unwrap();
+        root
+    }
+
+    const fn policy(files_directory: &Path, symlinks: SymlinkPolicy) -> FilesPolicy<'_> {
+        FilesPolicy {
+            files_directory,
+            symlinks,
+            serve_hidden_files: false,
+        }
+    }
+
+    const fn is_file(lookup: &Lookup) -> bool {
+        matches!(lookup, Lookup::File(_))
+    }
+
+    const fn is_not_found(lookup: &Lookup) -> bool {
+        matches!(lookup, Lookup::NotFound)
+    }
+
+    #[test]
+    fn finds_files_and_directories() {
+        let root = files_directory();
+        let files = root.path().join("files");
+        let policy = policy(&files, SymlinkPolicy::Deny);
+        assert!(is_file(&policy.lookup("cat.txt")));
+        assert!(is_file(&policy.lookup("directory//dog.txt")));
+        assert!(matches!(policy.lookup("directory/"), Lookup::Directory(_)));
+        assert!(is_not_found(&policy.lookup("missing.txt")));
+    }
+
+    #[test]
+    fn rejects_traversal() {
+        let root = files_directory();
+        let files = root.path().join("files");
+        let policy = policy(&files, SymlinkPolicy::Follow);
+        for path in [
+            "../outside/secret.txt",
+            "directory/../../outside/secret.txt",
+            "./cat.txt",
+            "..\\outside\\secret.txt",
+            "directory\\..\\cat.txt",
+            "C:/outside/secret.txt",
+            "cat.txt\0",
+            "cat.txt\0.png",
+            // Decoding is done by the callers, so these are just names that don't exist
+            "%2e%2e/outside/secret.txt",
+            "..%2Foutside%2Fsecret.txt",
+            "%5C..%5Coutside%5Csecret.txt",
+        ] {
+            assert!(is_not_found(&policy.lookup(path)), "{path:?} was found");
+        }
+    }
+
+    #[test]
+    fn hides_hidden_files() {
+        let root = files_directory();
+        let files = root.path().join("files");
+        let mut policy = policy(&files, SymlinkPolicy::Deny);
+        assert!(is_not_found(&policy.lookup(".env")));
+        assert!(is_not_found(&policy.lookup("directory/../.env")));
+        policy.serve_hidden_files = true;
+        assert!(is_file(&policy.lookup(".env")));
+    }
+
+    #[test]
+    fn follows_symlinks_by_policy() {
+        let root = files_directory();
+        let files = root.path().join("files");
+        let paths = [
+            "inside_link.txt",
+            "outside_link.txt",
+            "outside_directory/secret.txt",
+        ];
+        let found = |symlinks| paths.map(|path| is_file(&policy(&files, symlinks).lookup(path)));
+        assert_eq!(found(SymlinkPolicy::Deny), [false, false, false]);
+        assert_eq!(
+            found(SymlinkPolicy::InsideFilesDirectory),
+            [true, false, false]
+        );
+        assert_eq!(found(SymlinkPolicy::Follow), [true, true, true]);
+    }
+}
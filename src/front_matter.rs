@@ -0,0 +1,61 @@
+use serde::Deserialize;
+
+use crate::{citations::CitationStyle, markdown_extensions::MarkdownOverrides};
+
+/// Optional YAML block at the very beginning of an article, between two `---` lines:
+///
+/// ```markdown
+/// ---
+/// slug: hello
+/// aliases: [hi, 2019/hello]
+/// markdown: { smart_punctuation: false }
+/// ---
+/// # Hello, world!
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct FrontMatter {
+    /// Overrides the slug that is made from the file name
+    pub slug: Option<String>,
+    /// Other paths that redirect to the article
+    pub aliases: Vec<String>,
+    /// Overrides the site's `markdown_extensions`
+    pub markdown: MarkdownOverrides,
+    /// BibTeX file (relative to the Markdown file) that overrides the site's `bibliography`
+    pub bibliography: Option<String>,
+    /// Overrides the site's `citation_style`
+    pub citation_style: Option<CitationStyle>,
+}
+
+const DELIMITER: &str = "---";
+
+impl FrontMatter {
+    /// Splits the front matter from the rest of the article. A front matter that cannot be parsed
+    /// is ignored (the article is still compiled, just without it), and its error is returned
+    /// for the diagnostics.
+    pub fn extract(contents: &str) -> (Self, &str, Option<serde_yaml::Error>) {
+        let Some(rest) = contents.strip_prefix(DELIMITER).and_then(|rest| {
+            rest.strip_prefix('\n')
+                .or_else(|| rest.strip_prefix("\r\n"))
+        }) else {
+            return (Self::default(), contents, None);
+        };
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            if line.trim_end() == DELIMITER {
+                let front_matter = &rest[..offset];
+                let body = &rest[offset + line.len()..];
+                if front_matter.trim().is_empty() {
+                    return (Self::default(), body, None);
+                }
+                return match serde_yaml::from_str(front_matter) {
+                    Ok(front_matter) => (front_matter, body, None),
+                    Err(error) => (Self::default(), body, Some(error)),
+                };
+            }
+            offset += line.len();
+        }
+        // No closing delimiter: it's just a horizontal rule at the beginning
+        (Self::default(), contents, None)
+    }
+}
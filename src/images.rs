@@ -0,0 +1,299 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+};
+
+use image::{
+    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    imageops::FilterType,
+    DynamicImage, ImageDecoder, ImageReader, ImageResult,
+};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use threadpool::ThreadPool;
+
+use crate::{assets::is_hash, base_url::BaseUrl};
+
+/// Resized images are served under this path of the site, like `resized/3f9a1c0b2d.480.webp`
+pub const RESIZED_PREFIX: &str = "resized/";
+
+const JPEG_QUALITY: u8 = 85;
+const WEBP_QUALITY: f32 = 80.0;
+
+/// Written next to the resized copies of an image after all of them are written, so its presence
+/// means that the copies are ready
+#[derive(Deserialize, Serialize)]
+pub struct Variants {
+    /// Size of the full-size copy
+    pub width: u32,
+    pub height: u32,
+    /// Widths of the copies, the smallest first. The last one is the full size
+    pub widths: Vec<u32>,
+    /// `image_widths` at the moment of resizing, to notice that they were changed
+    requested_widths: Vec<u32>,
+    /// Of the copies in the original format (there are WebP copies too)
+    pub extension: String,
+}
+
+/// Path of the directory with the resized copies of the images from a files directory
+pub fn cache_directory_for(files_directory: &Path) -> PathBuf {
+    let mut directory_name = files_directory
+        .file_name()
+        .unwrap_or_default()
+        .to_os_string();
+    directory_name.push(".resized");
+    files_directory.with_file_name(directory_name)
+}
+
+/// Extension of the resized copies, if the image can be resized. GIFs are left alone, because
+/// they are probably animated
+pub fn resized_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => Some("jpg"),
+        "png" => Some("png"),
+        "webp" => Some("webp"),
+        _ => None,
+    }
+}
+
+fn variant_file_name(hash: &str, width: u32, extension: &str) -> String {
+    format!("{hash}.{width}.{extension}")
+}
+
+/// `resized/3f9a1c0b2d.480.webp` -> `3f9a1c0b2d.480.webp` (the file name in the cache directory).
+/// Returns `None` if the path is not a path of a resized image
+pub fn parse_resized_path(path: &str) -> Option<&str> {
+    let file_name = path.strip_prefix(RESIZED_PREFIX)?;
+    let mut parts = file_name.split('.');
+    let (Some(hash), Some(width), Some(extension), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return None;
+    };
+    (is_hash(hash)
+        && !width.is_empty()
+        && width.bytes().all(|byte| byte.is_ascii_digit())
+        && ["jpg", "png", "webp"].contains(&extension))
+    .then_some(file_name)
+}
+
+/// Resizes the images in the background, so the watchers aren't blocked by large photos
+pub struct ImageProcessor {
+    /// Created with the first job
+    pool: Option<ThreadPool>,
+    /// Sources that are being resized right now
+    pending: Arc<Mutex<HashSet<PathBuf>>>,
+    /// Receives the sources whose copies are ready. Without it, only the copies that are already
+    /// in the cache are used
+    finished: Option<Sender<PathBuf>>,
+}
+
+impl ImageProcessor {
+    pub fn new(finished: Option<Sender<PathBuf>>) -> Self {
+        Self {
+            pool: None,
+            pending: Arc::new(Mutex::new(HashSet::new())),
+            finished,
+        }
+    }
+
+    /// Returns the copies of the image if they are ready, and starts making them otherwise
+    pub fn variants(
+        &mut self,
+        source: &Path,
+        hash: &str,
+        widths: &[u32],
+        cache_directory: &Path,
+    ) -> Option<Variants> {
+        let description_path = cache_directory.join(format!("{hash}.json"));
+        if let Some(variants) = fs::read_to_string(&description_path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Variants>(&contents).ok())
+        {
+            if variants.requested_widths == widths {
+                return Some(variants);
+            }
+        }
+        let finished = self.finished.clone()?;
+        if !self.pending.lock().unwrap().insert(source.to_owned()) {
+            return None;
+        }
+        let job = Job {
+            source: source.to_owned(),
+            hash: hash.to_owned(),
+            widths: widths.to_owned(),
+            cache_directory: cache_directory.to_owned(),
+        };
+        let pending = self.pending.clone();
+        self.pool
+            .get_or_insert_with(|| {
+                threadpool::Builder::new()
+                    .thread_name("Image processing".into())
+                    .build()
+            })
+            .execute(move || {
+                info!("Resizing `{:?}`", job.source);
+                let result = job.run();
+                pending.lock().unwrap().remove(&job.source);
+                match result {
+                    Ok(()) => {
+                        // The website may be gone already
+                        let _ = finished.send(job.source);
+                    }
+                    Err(error) => error!(
+                        "Image `{:?}` cannot be resized! Details: {}",
+                        job.source, error
+                    ),
+                }
+            });
+        None
+    }
+}
+
+struct Job {
+    source: PathBuf,
+    hash: String,
+    widths: Vec<u32>,
+    cache_directory: PathBuf,
+}
+
+impl Job {
+    /// Writes the copies of every width in the original format and in WebP. Decoding and encoding
+    /// again drops the metadata (like the GPS coordinates in EXIF), so the orientation from the
+    /// metadata is applied to the pixels
+    fn run(&self) -> ImageResult<()> {
+        let extension = resized_extension(&self.source).unwrap_or("png");
+        let mut decoder = ImageReader::open(&self.source)?
+            .with_guessed_format()?
+            .into_decoder()?;
+        let orientation = decoder.orientation()?;
+        let mut image = DynamicImage::from_decoder(decoder)?;
+        image.apply_orientation(orientation);
+        let (width, height) = (image.width(), image.height());
+        let mut widths: Vec<u32> = self
+            .widths
+            .iter()
+            .copied()
+            .filter(|&requested_width| requested_width > 0 && requested_width < width)
+            .collect();
+        widths.sort_unstable();
+        widths.dedup();
+        widths.push(width);
+        fs::create_dir_all(&self.cache_directory)?;
+        for &variant_width in &widths {
+            let variant = if variant_width == width {
+                image.clone()
+            } else {
+                image.resize(variant_width, height, FilterType::Lanczos3)
+            };
+            self.write_variant(&variant, extension)?;
+        }
+        let description = serde_json::to_string(&Variants {
+            width,
+            height,
+            widths,
+            requested_widths: self.widths.clone(),
+            extension: extension.to_owned(),
+        })
+        .unwrap();
+        // Renamed, so a half-written description is never read
+        let description_path = self.cache_directory.join(format!("{}.json", self.hash));
+        let temporary_path = description_path.with_extension("json.tmp");
+        fs::write(&temporary_path, description)?;
+        fs::rename(temporary_path, description_path)?;
+        Ok(())
+    }
+
+    fn write_variant(&self, variant: &DynamicImage, extension: &str) -> ImageResult<()> {
+        let path_of = |extension| {
+            self.cache_directory
+                .join(variant_file_name(&self.hash, variant.width(), extension))
+        };
+        match extension {
+            "jpg" => DynamicImage::ImageRgb8(variant.to_rgb8()).write_with_encoder(
+                JpegEncoder::new_with_quality(
+                    BufWriter::new(File::create(path_of("jpg"))?),
+                    JPEG_QUALITY,
+                ),
+            )?,
+            "png" => variant.write_with_encoder(PngEncoder::new(BufWriter::new(File::create(
+                path_of("png"),
+            )?)))?,
+            _ => (),
+        }
+        let pixels = variant.to_rgba8();
+        let encoded =
+            webp::Encoder::from_rgba(&pixels, pixels.width(), pixels.height()).encode(WEBP_QUALITY);
+        fs::write(path_of("webp"), &*encoded)?;
+        Ok(())
+    }
+}
+
+fn srcset(hash: &str, variants: &Variants, extension: &str, base_url: &BaseUrl) -> String {
+    variants
+        .widths
+        .iter()
+        .map(|width| {
+            format!(
+                "{} {}w",
+                base_url.link(&format!(
+                    "{}{}",
+                    RESIZED_PREFIX,
+                    variant_file_name(hash, *width, extension)
+                )),
+                width
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// `<picture>` with the WebP copies and the copies in the original format, which the browser
+/// picks from by the width of the screen
+pub fn picture_html(
+    hash: &str,
+    variants: &Variants,
+    alt: &str,
+    title: &str,
+    base_url: &BaseUrl,
+) -> String {
+    let sizes = format!(
+        "(max-width: {width}px) 100vw, {width}px",
+        width = variants.width
+    );
+    let webp_srcset = srcset(hash, variants, "webp", base_url);
+    let srcset = srcset(hash, variants, &variants.extension, base_url);
+    let src = base_url.link(&format!(
+        "{}{}",
+        RESIZED_PREFIX,
+        variant_file_name(hash, variants.width, &variants.extension)
+    ));
+    let title = if title.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r#" title="{}""#,
+            html_escape::encode_double_quoted_attribute(title)
+        )
+    };
+    let source = if variants.extension == "webp" {
+        String::new()
+    } else {
+        format!(r#"<source type="image/webp" srcset="{webp_srcset}" sizes="{sizes}" />"#)
+    };
+    format!(
+        r#"<picture>{}<img src="{}" srcset="{}" sizes="{}" width="{}" height="{}" loading="lazy" decoding="async" alt="{}"{} /></picture>"#,
+        source,
+        src,
+        srcset,
+        sizes,
+        variants.width,
+        variants.height,
+        html_escape::encode_double_quoted_attribute(alt),
+        title,
+    )
+}
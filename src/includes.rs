@@ -0,0 +1,416 @@
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    diagnostics::Problem,
+    front_matter::FrontMatter,
+    prose,
+    shortcodes::{self, Arguments},
+};
+
+/// `ANCHOR: name` and `ANCHOR_END: name` (like in mdBook) mark the regions of the source files
+const REGION_START: &str = "ANCHOR:";
+const REGION_END: &str = "ANCHOR_END:";
+
+/// `/a/./b/../c` -> `/a/c`, so the paths match the ones that the watchers report
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// `12`, `12-20`, `12-` or `-20` (counting from 1, with the last line included)
+fn parse_lines(lines: &str, count: usize) -> Result<(usize, usize), String> {
+    let invalid = || format!("`{lines}` is not a line range like `12-20`");
+    let parse = |number: &str, default| match number.trim() {
+        "" => Ok(default),
+        number => number.parse::<usize>().map_err(|_error| invalid()),
+    };
+    let (first, last) = if let Some((first, last)) = lines.split_once('-') {
+        (parse(first, 1)?, parse(last, count)?)
+    } else {
+        let line = parse(lines, 0)?;
+        (line, line)
+    };
+    if first == 0 || first > last {
+        return Err(invalid());
+    }
+    if last > count {
+        return Err(format!(
+            "the file has {count} lines, so `{lines}` is out of it"
+        ));
+    }
+    Ok((first, last))
+}
+
+fn is_region_marker(line: &str, marker: &str, name: &str) -> bool {
+    line.split_once(marker)
+        .is_some_and(|(_comment, rest)| rest.trim() == name)
+}
+
+/// Lines between `ANCHOR: name` and `ANCHOR_END: name`
+fn region<'source>(lines: &[&'source str], name: &str) -> Result<Vec<&'source str>, String> {
+    let start = lines
+        .iter()
+        .position(|line| is_region_marker(line, REGION_START, name))
+        .ok_or_else(|| format!("the file has no `{REGION_START} {name}` line"))?;
+    let length = lines[start + 1..]
+        .iter()
+        .position(|line| is_region_marker(line, REGION_END, name))
+        .ok_or_else(|| format!("region `{name}` has no `{REGION_END} {name}` line"))?;
+    Ok(lines[start + 1..start + 1 + length].to_vec())
+}
+
+/// A fenced code block with the lines, without their common indentation. The markers of the nested
+/// regions are left out of a region
+fn code_block(lines: &[&str], language: &str, is_region: bool) -> String {
+    let lines: Vec<_> = lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\r', '\n']))
+        .filter(|line| !is_region || !(line.contains(REGION_START) || line.contains(REGION_END)))
+        .collect();
+    let indentation = lines
+        .iter()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.chars().take_while(|c| c.is_whitespace()).count())
+        .min()
+        .unwrap_or(0);
+    // The fence must be longer than the backtick runs of the code
+    let fence_length = lines
+        .iter()
+        .map(|line| prose::run_length(line.trim_start(), '`'))
+        .max()
+        .unwrap_or(0)
+        .max(2)
+        + 1;
+    let fence = "`".repeat(fence_length);
+    let mut block = format!("{fence}{language}\n");
+    for line in lines {
+        // The whitespace-only lines can be shorter than the indentation
+        let mut chars = line.chars();
+        for _ in 0..indentation {
+            if !chars.clone().next().is_some_and(char::is_whitespace) {
+                break;
+            }
+            chars.next();
+        }
+        block.push_str(chars.as_str());
+        block.push('\n');
+    }
+    block.push_str(&fence);
+    block
+}
+
+/// Includes the files into an article. The paths are relative to the file with the directive
+struct Inclusion<'compilation> {
+    /// The files outside of these cannot be included
+    directories: Vec<PathBuf>,
+    dependencies: &'compilation mut Vec<PathBuf>,
+    problems: &'compilation mut Vec<Problem>,
+    /// Markdown files that are being included, to find the cycles
+    stack: Vec<PathBuf>,
+}
+
+impl Inclusion<'_> {
+    /// Whether the file is inside one of the directories, also after following the symbolic links
+    /// (if it exists already)
+    fn is_allowed(&self, path: &Path) -> bool {
+        let real_path = path.canonicalize().ok();
+        self.directories.iter().any(|directory| {
+            path.starts_with(directory)
+                && match (&real_path, directory.canonicalize()) {
+                    (Some(real_path), Ok(real_directory)) => real_path.starts_with(real_directory),
+                    _ => true,
+                }
+        })
+    }
+
+    fn include(&mut self, arguments: &Arguments, directory: &Path) -> Result<String, String> {
+        shortcodes::validate(arguments, &["src"], &["lines", "region", "language"])?;
+        let src = &arguments["src"];
+        if Path::new(src).is_absolute() {
+            return Err(format!("`{src}` is not a relative path"));
+        }
+        let path = normalize(&directory.join(src));
+        if !self.is_allowed(&path) {
+            return Err(format!(
+                "`{}` is outside of the articles, the files and the include directories",
+                path.display()
+            ));
+        }
+        // The article is compiled again when the file appears
+        self.dependencies.push(path.clone());
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| format!("`{}` cannot be read: {error}", path.display()))?;
+        let is_markdown = path
+            .extension()
+            .is_some_and(|extension| extension == "md" || extension == "markdown");
+        if is_markdown && !arguments.contains_key("language") {
+            if arguments.contains_key("lines") || arguments.contains_key("region") {
+                return Err("Markdown files are included as a whole".to_owned());
+            }
+            if self.stack.contains(&path) {
+                return Err(format!(
+                    "`{}` is already being included, so it's a cycle",
+                    path.display()
+                ));
+            }
+            let (_front_matter, body, _error) = FrontMatter::extract(&contents);
+            self.stack.push(path.clone());
+            let expanded = self.expand(body, path.parent().unwrap_or(directory));
+            self.stack.pop();
+            return Ok(expanded);
+        }
+        let lines: Vec<_> = contents.split_inclusive('\n').collect();
+        let lines = match (arguments.get("lines"), arguments.get("region")) {
+            (Some(_lines), Some(_region)) => {
+                return Err("`lines` and `region` cannot be used together".to_owned())
+            }
+            (Some(range), None) => {
+                let (first, last) = parse_lines(range, lines.len())?;
+                lines[first - 1..last].to_vec()
+            }
+            (None, Some(name)) => region(&lines, name)?,
+            (None, None) => lines,
+        };
+        let language = arguments.get("language").map_or_else(
+            || {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .unwrap_or_default()
+            },
+            String::as_str,
+        );
+        Ok(code_block(
+            &lines,
+            language,
+            arguments.contains_key("region"),
+        ))
+    }
+
+    fn expand(&mut self, markdown: &str, directory: &Path) -> String {
+        prose::replace(markdown, |text| {
+            let (length, call) = shortcodes::shortcode_at(text)?;
+            let Ok((name, arguments)) = &call.parsed else {
+                return None;
+            };
+            if name != "include" {
+                return None;
+            }
+            match self.include(arguments, directory) {
+                // The included blocks don't stick to the paragraph around them
+                Ok(included) => Some((length, format!("\n\n{included}\n\n"))),
+                Err(details) => {
+                    // Shown as it was written
+                    let html = format!(
+                        r#"<span class="include-error" title="{}">{}</span>"#,
+                        html_escape::encode_double_quoted_attribute(&details),
+                        // The quotes are encoded, so the smart punctuation doesn't change them
+                        html_escape::encode_double_quoted_attribute(&call.source)
+                    );
+                    self.problems.push(Problem::InvalidInclude {
+                        include: call.source.clone(),
+                        details,
+                    });
+                    Some((length, html))
+                }
+            }
+        })
+    }
+}
+
+/// Replaces `{{< include src="other.md" >}}` with the Markdown of the other file (which can have
+/// its own includes), and `{{< include src="main.rs" lines="10-20" >}}` or
+/// `{{< include src="main.rs" region="setup" >}}` with a code block. The included files become
+/// the dependencies of the article. Only the files inside of `directories` can be included
+pub fn expand(
+    markdown: &str,
+    markdown_path: &Path,
+    directories: &[&Path],
+    dependencies: &mut Vec<PathBuf>,
+    problems: &mut Vec<Problem>,
+) -> String {
+    let markdown_path = normalize(markdown_path);
+    let directory = markdown_path.parent().unwrap_or(&markdown_path).to_owned();
+    Inclusion {
+        directories: directories
+            .iter()
+            .map(|directory| normalize(directory))
+            .collect(),
+        dependencies,
+        problems,
+        stack: vec![markdown_path],
+    }
+    .expand(markdown, &directory)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{code_block, expand, parse_lines, region};
+
+    #[test]
+    fn parses_line_ranges() {
+        assert_eq!(parse_lines("3", 10), Ok((3, 3)));
+        assert_eq!(parse_lines("2-5", 10), Ok((2, 5)));
+        assert_eq!(parse_lines(" 4 - ", 10), Ok((4, 10)));
+        assert_eq!(parse_lines("-4", 10), Ok((1, 4)));
+        for invalid in ["0", "5-2", "a-b", "1-2-3", ""] {
+            assert!(parse_lines(invalid, 10).is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            parse_lines("8-12", 10),
+            Err("the file has 10 lines, so `8-12` is out of it".to_owned())
+        );
+    }
+
+    #[test]
+    fn finds_regions() {
+        let lines = [
+            "fn main() {\n",
+            "    // ANCHOR: setup\n",
+            "    let a = 1;\n",
+            "    // ANCHOR: inner\n",
+            "    let b = 2;\n",
+            "    // ANCHOR_END: inner\n",
+            "    // ANCHOR_END: setup\n",
+            "}\n",
+        ];
+        assert_eq!(region(&lines, "inner"), Ok(vec!["    let b = 2;\n"]));
+        let setup = region(&lines, "setup").unwrap();
+        assert_eq!(setup.len(), 4);
+        assert_eq!(
+            code_block(&setup, "rs", true),
+            "```rs\nlet a = 1;\nlet b = 2;\n```"
+        );
+        assert!(region(&lines, "missing").is_err());
+        assert!(region(&lines[..6], "setup").is_err());
+    }
+
+    #[test]
+    fn keeps_markers_outside_of_regions() {
+        assert_eq!(
+            code_block(&["  // ANCHOR: a\n", "  b\n"], "", false),
+            "```\n// ANCHOR: a\nb\n```"
+        );
+    }
+
+    #[test]
+    fn strips_only_the_common_indentation() {
+        assert_eq!(
+            code_block(
+                &["    if a {\n", "\n", "  \n", "        b\n", "    }\n"],
+                "",
+                false
+            ),
+            "```\nif a {\n\n\n    b\n}\n```"
+        );
+        assert_eq!(
+            code_block(&["\t\u{3000}ёж\n", "\t\u{3000}  ёлка\n", "\t\n"], "", false),
+            "```\nёж\n  ёлка\n\n```"
+        );
+        assert_eq!(
+            code_block(&["  ab\n", " é\n"], "", false),
+            "```\n ab\né\n```"
+        );
+        // The indentation is counted in characters, not bytes
+        assert_eq!(
+            code_block(&["  a\n", "\u{3000}b\n"], "", false),
+            "```\n a\nb\n```"
+        );
+    }
+
+    #[test]
+    fn fences_are_longer_than_the_code_backticks() {
+        assert_eq!(
+            code_block(&["````md\n", "text\n", "````\n"], "md", false),
+            "`````md\n````md\ntext\n````\n`````"
+        );
+    }
+
+    /// Writes the files and expands the includes of the first one
+    fn include(
+        root: &Path,
+        files: &[(&str, &str)],
+        directories: &[&Path],
+    ) -> (String, Vec<String>) {
+        for (path, contents) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let (mut dependencies, mut problems) = (Vec::new(), Vec::new());
+        let markdown = expand(
+            files[0].1,
+            &root.join(files[0].0),
+            directories,
+            &mut dependencies,
+            &mut problems,
+        );
+        (markdown, problems.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let root = tempfile::tempdir().unwrap();
+        let articles = root.path().join("articles");
+        let (markdown, problems) = include(
+            root.path(),
+            &[
+                ("articles/a.md", r#"{{< include src="b.md" >}}"#),
+                ("articles/b.md", r#"B {{< include src="./c/../a.md" >}}"#),
+            ],
+            &[&articles],
+        );
+        assert!(
+            markdown.contains("B <span class=\"include-error\""),
+            "{}",
+            markdown
+        );
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("so it's a cycle"), "{}", problems[0]);
+    }
+
+    #[test]
+    fn files_outside_of_the_directories_are_not_included() {
+        let root = tempfile::tempdir().unwrap();
+        let articles = root.path().join("articles");
+        let code = root.path().join("code");
+        let secret = root.path().join("secret.txt");
+        let (_markdown, problems) = include(
+            root.path(),
+            &[
+                (
+                    "articles/a.md",
+                    &format!(
+                        "{{{{< include src=\"../secret.txt\" >}}}}\n\n\
+                         {{{{< include src=\"{}\" >}}}}\n\n\
+                         {{{{< include src=\"../code/main.rs\" >}}}}",
+                        secret.display()
+                    ),
+                ),
+                ("secret.txt", "secret"),
+                ("code/main.rs", "fn main() {}"),
+            ],
+            &[&articles, &code],
+        );
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[0].contains("is outside of"), "{}", problems[0]);
+        assert!(
+            problems[1].contains("is not a relative path"),
+            "{}",
+            problems[1]
+        );
+    }
+}
@@ -2,36 +2,69 @@ use std::{
     fs, io,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         mpsc, Arc, Mutex,
     },
-    thread,
+    thread::{self, JoinHandle},
     time::Duration,
 };
 
 use actix_web::{
     dev::{Server, ServerHandle},
+    http::StatusCode,
+    middleware::ErrorHandlers,
     web, App, HttpServer,
 };
 use clap::{crate_description, Parser, Subcommand};
-use config::Config;
-use log::{error, warn};
+use config::{Config, SiteConfig};
+use log::{error, info, warn};
 use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode};
+use signals::Signal;
 use simple_logger::SimpleLogger;
-use utils::{set_global_log_level, FileNameShortcut};
+use sites::Sites;
+use utils::set_global_log_level;
 use website::Website;
 
 mod absolute_path;
+mod assets;
+mod base_url;
+mod bibtex;
+mod callouts;
+mod citations;
 mod config;
+mod data_tables;
+mod diagnostics;
+mod diagrams;
+mod external_links;
+mod files;
+mod front_matter;
+mod images;
+mod includes;
+mod markdown_extensions;
+mod math;
 mod page_colors;
 mod page_compilers;
+mod prose;
+mod redirects;
 mod routes;
+mod shortcodes;
+mod signals;
+mod sites;
 mod utils;
 mod website;
 
 #[derive(Parser)]
 #[clap(author, version, about = crate_description!(), long_about=None)]
 struct Args {
+    /// Path to the configuration file. Its extension (`.json`, `.toml`, `.yaml` or `.yml`)
+    /// determines the format
+    #[clap(
+        long,
+        global = true,
+        env = "BLOG_CONFIG",
+        default_value = "config.json"
+    )]
+    config: PathBuf,
     #[clap(subcommand)]
     command: Command,
 }
@@ -45,21 +78,146 @@ enum Command {
         force: bool,
     },
     /// Run the server
-    Run,
+    Run(RunArguments),
+    /// Inspect or update the configuration file
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Inspect the redirects of the sites (from the renamed articles and the manual ones)
+    Redirects {
+        /// Only look at this site (every site is looked at by default)
+        #[clap(long)]
+        site: Option<String>,
+        #[clap(subcommand)]
+        command: RedirectsCommand,
+    },
+    /// Compile the articles without running the server and report the broken links, duplicate
+    /// titles, empty articles and invalid front matter. Exits with an error if there are any
+    Check {
+        /// Only check this site (every site is checked by default)
+        #[clap(long)]
+        site: Option<String>,
+        #[clap(flatten)]
+        external: ExternalLinksArguments,
+    },
 }
 
-const CONFIG_FILE_NAME: &str = "config.json";
+#[derive(clap::Args)]
+struct ExternalLinksArguments {
+    /// Check the links to the other sites too. The results are cached in
+    /// `<articles directory>.links.json`
+    #[clap(long = "external", takes_value = false)]
+    enabled: bool,
+    /// How long to wait for another site to respond
+    #[clap(long, default_value = "10")]
+    timeout_in_seconds: u64,
+    /// Pause between the requests to the same host
+    #[clap(long, default_value = "1000")]
+    per_host_delay_in_milliseconds: u64,
+    /// How long the working links are trusted before they are checked again
+    #[clap(long, default_value = "168")]
+    recheck_after_hours: u64,
+}
 
-fn run_server(website: Arc<Mutex<Website>>, config: &Config) -> Server {
-    HttpServer::new(move || {
+#[derive(Subcommand)]
+enum RedirectsCommand {
+    /// Print every redirect and where it leads
+    List,
+    /// Remove the redirects that are shadowed by pages, lead nowhere or form cycles
+    Prune,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Validate the configuration and print it with the defaults and overrides applied
+    Check(RunArguments),
+    /// Print a JSON Schema of the configuration file
+    Schema,
+    /// Rewrite the configuration file in the current layout, keeping a backup of the old one
+    Migrate,
+}
+
+/// Every configuration field can be overridden for a single run. The precedence is: command line,
+/// then `BLOG_<FIELD NAME>` environment variables (like `BLOG_PORT`), then the configuration file
+#[derive(clap::Args)]
+struct RunArguments {
+    /// Override any configuration field, like `--set author_name=John`. Can be repeated
+    #[clap(long = "set", value_name = "KEY=VALUE", parse(try_from_str = config::overrides::parse_assignment))]
+    set: Vec<(String, String)>,
+    /// Override `host_name`
+    #[clap(long)]
+    host_name: Option<String>,
+    /// Override `port`
+    #[clap(long)]
+    port: Option<u16>,
+    /// Override `log_level`
+    #[clap(long)]
+    log_level: Option<String>,
+    /// Override `articles_directory`
+    #[clap(long)]
+    articles_directory: Option<PathBuf>,
+    /// Override `files_directory`
+    #[clap(long)]
+    files_directory: Option<PathBuf>,
+}
+
+impl RunArguments {
+    /// The overrides of the command line, in the order they are applied.
+    ///
+    /// # Errors
+    /// Returns an error if a field is given both by its dedicated flag and by `--set`.
+    fn into_overrides(self) -> Result<Vec<(String, String)>, String> {
+        config::overrides::with_dedicated_flags(
+            self.set,
+            [
+                ("host_name", self.host_name),
+                ("port", self.port.map(|port| port.to_string())),
+                ("log_level", self.log_level),
+                (
+                    "articles_directory",
+                    self.articles_directory
+                        .map(|path| path.to_string_lossy().into_owned()),
+                ),
+                (
+                    "files_directory",
+                    self.files_directory
+                        .map(|path| path.to_string_lossy().into_owned()),
+                ),
+            ],
+        )
+    }
+}
+
+fn run_server(sites: Arc<Mutex<Sites>>, config: &Config) -> Server {
+    let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::from(website.clone()))
-            .route("/", web::get().to(routes::index))
-            .route("/{filename}", web::get().to(routes::file))
+            .app_data(web::Data::from(sites.clone()))
+            .wrap(
+                ErrorHandlers::new()
+                    .handler(StatusCode::INTERNAL_SERVER_ERROR, routes::internal_error),
+            )
+            .route("/{path:.*}", web::get().to(routes::page))
     })
     .bind((&config.host_name[..], config.port))
-    .unwrap()
-    .run()
+    .unwrap();
+    // Signals are handled by the `signals` module on Unix, so the server shouldn't stop by itself
+    #[cfg(unix)]
+    let server = server.disable_signals();
+    server.run()
+}
+
+/// Shared by every watcher, because it is a server-wide setting and the watchers of the sites don't
+/// have access to the server's configuration
+static FILE_WATCHER_DELAY_IN_MILLISECONDS: AtomicU64 = AtomicU64::new(2000);
+
+/// Affects only the watchers that are created after the call
+pub fn set_file_watcher_delay(delay_in_milliseconds: u64) {
+    FILE_WATCHER_DELAY_IN_MILLISECONDS.store(delay_in_milliseconds, Ordering::Relaxed);
+}
+
+fn file_watcher_delay() -> Duration {
+    Duration::from_millis(FILE_WATCHER_DELAY_IN_MILLISECONDS.load(Ordering::Relaxed))
 }
 
 pub struct WatchContext<Watcher> {
@@ -76,17 +234,13 @@ pub type WatchResult<Watcher> = Result<WatchContext<Watcher>, notify::Error>;
 /// changes.
 #[allow(clippy::missing_errors_doc)]
 pub fn watch<Watcher>(
-    config: &Config,
     path: &Path,
     recursive_mode: RecursiveMode,
     watcher_maker: fn(RecommendedWatcher) -> Watcher,
 ) -> WatchResult<Watcher> {
     let (event_sender, event_receiver) = mpsc::channel();
-    let mut watcher: RecommendedWatcher = notify::Watcher::new(
-        event_sender,
-        Duration::from_millis(config.file_watcher_delay_in_milliseconds),
-    )
-    .unwrap();
+    let mut watcher: RecommendedWatcher =
+        notify::Watcher::new(event_sender, file_watcher_delay()).unwrap();
     notify::Watcher::watch(&mut watcher, path, recursive_mode)?;
     Ok(WatchContext {
         _watcher: watcher_maker(watcher),
@@ -97,37 +251,76 @@ pub fn watch<Watcher>(
 pub struct ArticlesWatcher(pub RecommendedWatcher);
 
 #[allow(clippy::missing_errors_doc)]
-pub fn watch_articles(config: &Config) -> WatchResult<ArticlesWatcher> {
-    if config.articles_directory.as_ref().is_dir() {
+pub fn watch_articles(config: &SiteConfig) -> WatchResult<ArticlesWatcher> {
+    watch_articles_directory(config.articles_directory.as_ref())
+}
+
+#[allow(clippy::missing_errors_doc)]
+pub fn watch_articles_directory(articles_directory: &Path) -> WatchResult<ArticlesWatcher> {
+    if articles_directory.is_dir() {
         watch(
-            config,
-            config.articles_directory.as_ref(),
+            articles_directory,
             RecursiveMode::Recursive,
             ArticlesWatcher,
         )
     } else {
         Err(notify::Error::Generic(format!(
-            "{:?} is not a directory!",
-            config.articles_directory.as_ref()
+            "`{}` is not a directory!",
+            articles_directory.display()
         )))
     }
 }
 
+pub struct FilesWatcher(pub RecommendedWatcher);
+
+#[allow(clippy::missing_errors_doc)]
+pub fn watch_files(config: &SiteConfig) -> WatchResult<FilesWatcher> {
+    if config.files_directory.is_dir() {
+        watch(
+            &config.files_directory,
+            RecursiveMode::Recursive,
+            FilesWatcher,
+        )
+    } else {
+        Err(notify::Error::Generic(format!(
+            "`{}` is not a directory!",
+            config.files_directory.display()
+        )))
+    }
+}
+
+pub struct ThemeWatcher(pub RecommendedWatcher);
+
+#[allow(clippy::missing_errors_doc)]
+pub fn watch_theme(config: &SiteConfig) -> WatchResult<ThemeWatcher> {
+    match &config.theme_directory {
+        Some(theme_directory) if theme_directory.is_dir() => {
+            watch(theme_directory, RecursiveMode::Recursive, ThemeWatcher)
+        }
+        Some(theme_directory) => Err(notify::Error::Generic(format!(
+            "`{}` is not a directory!",
+            theme_directory.display()
+        ))),
+        None => Err(notify::Error::Generic(
+            "there's no theme directory".to_owned(),
+        )),
+    }
+}
+
 pub struct ConfigWatcher(pub RecommendedWatcher);
 
 #[allow(clippy::missing_errors_doc)]
-pub fn watch_config(config: &Config) -> WatchResult<ConfigWatcher> {
-    if Path::new(CONFIG_FILE_NAME).is_file() {
+pub fn watch_config(_config: &Config) -> WatchResult<ConfigWatcher> {
+    if config::file::path().is_file() {
         watch(
-            config,
-            Path::new(CONFIG_FILE_NAME),
+            config::file::path(),
             RecursiveMode::NonRecursive,
             ConfigWatcher,
         )
     } else {
         Err(notify::Error::Generic(format!(
-            "{:?} is not a file!",
-            Path::new(CONFIG_FILE_NAME)
+            "`{}` is not a file!",
+            config::file::path().display()
         )))
     }
 }
@@ -146,17 +339,47 @@ trait CompareWithAbsolutePath {
     fn compare(&self, absolute_path: &Path) -> bool;
 }
 
-fn begin_watching<Watcher: 'static + Send>(
+struct ConfigFile;
+
+impl CompareWithAbsolutePath for ConfigFile {
+    fn compare(&self, _absolute_path: &Path) -> bool {
+        // Config watcher is non-recursive, so the path will always be the same
+        // (the configuration file's path)
+        true
+    }
+}
+
+/// A flag that is shared between a watcher thread and whoever wants it to finish
+#[derive(Clone, Default)]
+pub struct StopFlag(Arc<AtomicBool>);
+
+impl StopFlag {
+    pub fn raise(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_raised(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn begin_watching<Watcher: 'static + Send, WatchedConfig: 'static + Send>(
     watch_context: Arc<Mutex<WatchContext<Watcher>>>,
-    config: Arc<Mutex<Config>>,
+    config: Arc<Mutex<WatchedConfig>>,
+    stop_flag: StopFlag,
     filesystem_entry_name: &'static str,
     filesystem_entry_path: impl CompareWithAbsolutePath + Send + 'static,
-    watch_context_maker: fn(&Config) -> WatchResult<Watcher>,
+    watch_context_maker: fn(&WatchedConfig) -> WatchResult<Watcher>,
     mut event_receiver: impl FnMut(DebouncedEvent) + Send + 'static,
     mut resource_reloader: impl FnMut() + Send + 'static,
-) {
-    thread::spawn(move || loop {
+) -> JoinHandle<()> {
+    thread::spawn(move || 'watching: loop {
         loop {
+            if stop_flag.is_raised() {
+                break 'watching;
+            }
             let event = watch_context.lock().unwrap().event_receiver.try_recv();
             match event {
                 Ok(event) => match event {
@@ -171,23 +394,32 @@ fn begin_watching<Watcher: 'static + Send>(
         }
         // TODO: if a deleted thing is a directory, it is definitely deleted completely
         thread::sleep(Duration::from_secs(1));
-        if let Ok(new_context) = watch_context_maker(&config.lock().unwrap()) {
+        // The configuration lock shouldn't be held while reloading, since the reloader may need it
+        let new_context = watch_context_maker(&config.lock().unwrap());
+        if let Ok(new_context) = new_context {
+            // The entry was replaced (editors often save files this way), so it has to be
+            // re-read, and the watching goes on
             *watch_context.lock().unwrap() = new_context;
-            break;
+            resource_reloader();
+            continue;
         }
         error!(
             "{} seems to be deleted. Waiting until it will be created...",
             filesystem_entry_name
         );
         loop {
+            if stop_flag.is_raised() {
+                break 'watching;
+            }
             thread::sleep(Duration::from_secs(1));
-            if let Ok(new_context) = watch_context_maker(&config.lock().unwrap()) {
+            let new_context = watch_context_maker(&config.lock().unwrap());
+            if let Ok(new_context) = new_context {
                 *watch_context.lock().unwrap() = new_context;
                 break;
             }
         }
         resource_reloader();
-    });
+    })
 }
 
 #[derive(Clone)]
@@ -214,169 +446,433 @@ impl CustomServerHandle {
         self.restart_was_requested.store(true, Ordering::Relaxed);
         self.server_handle.stop(true).await;
     }
+
+    /// Stops the server gracefully: the listeners are closed at once, and the connections that are
+    /// already open are served until they finish (or until the shutdown timeout runs out)
+    pub async fn request_shutdown(&self) {
+        self.server_handle.stop(true).await;
+    }
+}
+
+/// Re-reads the configuration. With `reload_everything`, every site is compiled again (once),
+/// even if its configuration is the same
+fn reload_config(
+    config: &Mutex<Config>,
+    server_handle: &CustomServerHandle,
+    sites: &Mutex<Sites>,
+    config_watch_context: &Arc<Mutex<WatchContext<ConfigWatcher>>>,
+    reload_everything: bool,
+) {
+    match config::file::read() {
+        Ok(new_config) => {
+            Config::update(
+                config,
+                new_config,
+                server_handle,
+                sites,
+                config_watch_context,
+                reload_everything,
+            );
+            return;
+        }
+        Err(error @ config::file::ReadError::PoorlyFormatted(_)) => warn!(
+            "Updated configuration file is poorly formatted! \
+            Consider fixing it. Using the old configuration file \
+            for now. Details: {}",
+            error
+        ),
+        Err(error @ config::file::ReadError::Inaccessible(_)) => error!(
+            "Configuration file update was noticed, but the file \
+            couldn't be read. Details: {}",
+            error
+        ),
+    }
+    if reload_everything {
+        Sites::reload_all(sites);
+    }
+}
+
+fn run_config_command(command: ConfigCommand) {
+    match command {
+        ConfigCommand::Check(arguments) => {
+            config::overrides::set_command_line(
+                arguments
+                    .into_overrides()
+                    .unwrap_or_else(|error| clean_panic!("{}", error)),
+            );
+            let config = config::file::read().unwrap_or_else(|error| {
+                clean_panic!("Configuration is invalid! Details: {}", error);
+            });
+            let format = config::file::Format::of(config::file::path()).unwrap();
+            let effective_config = format.serialize(&config);
+            set_global_log_level(&config.log_level)
+                .unwrap_or_else(|error| clean_panic!("{}", error));
+            let config = config.upgrade().unwrap_or_else(|error| {
+                clean_panic!("Configuration is invalid: {}", error);
+            });
+            for (name, site_config) in &config.sites {
+                let files_directory = site_config.lock().unwrap().files_directory.clone();
+                if !files_directory.is_dir() {
+                    warn!(
+                        "Files directory `{}` of site `{name}` doesn't exist, no files will be \
+                        served",
+                        files_directory.display()
+                    );
+                }
+            }
+            println!("{effective_config}");
+        }
+        ConfigCommand::Schema => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&config::schema()).unwrap()
+            );
+        }
+        ConfigCommand::Migrate => match config::file::migrate() {
+            Ok(backup_path) => info!(
+                "Configuration file `{:?}` is migrated, the old version is kept in `{:?}`",
+                config::file::path(),
+                backup_path
+            ),
+            Err(error) => clean_panic!("Couldn't migrate the configuration! Details: {}", error),
+        },
+    }
+}
+
+fn run_redirects_command(site: Option<&str>, command: &RedirectsCommand) {
+    let config = config::file::read()
+        .unwrap_or_else(|error| clean_panic!("Configuration is invalid! Details: {}", error));
+    set_global_log_level(&config.log_level).unwrap_or_else(|error| clean_panic!("{}", error));
+    let config = config
+        .upgrade()
+        .unwrap_or_else(|error| clean_panic!("Configuration is invalid: {}", error));
+    if let Some(site) = site {
+        if !config.sites.contains_key(site) {
+            clean_panic!("There's no site `{}` in the configuration!", site);
+        }
+    }
+    for (name, site_config) in &config.sites {
+        if site.is_some_and(|site| site != name) {
+            continue;
+        }
+        let mut website = Website::new(site_config.clone(), None);
+        println!("{name} (`{}`):", website.redirects_path().display());
+        let redirects = match command {
+            RedirectsCommand::List => website.redirect_statuses(),
+            RedirectsCommand::Prune => website.prune_redirects().unwrap_or_else(|error| {
+                clean_panic!("Couldn't write the redirects file! Details: {}", error)
+            }),
+        };
+        if redirects.is_empty() {
+            println!("    (nothing)");
+        }
+        for (from, to, status) in redirects {
+            println!(
+                "    {} -> {}: {}",
+                from,
+                to.as_deref().unwrap_or("(deleted)"),
+                status
+            );
+        }
+    }
+}
+
+fn run_check_command(site: Option<&str>, external: &ExternalLinksArguments) {
+    let config = config::file::read()
+        .unwrap_or_else(|error| clean_panic!("Configuration is invalid! Details: {}", error));
+    // The problems are printed below, and not logged while compiling
+    log::set_max_level(log::LevelFilter::Error);
+    let config = config
+        .upgrade()
+        .unwrap_or_else(|error| clean_panic!("Configuration is invalid: {}", error));
+    if let Some(site) = site {
+        if !config.sites.contains_key(site) {
+            clean_panic!("There's no site `{}` in the configuration!", site);
+        }
+    }
+    let client: Arc<dyn external_links::HttpClient> = Arc::new(external_links::UreqClient::new(
+        Duration::from_secs(external.timeout_in_seconds),
+    ));
+    let options = external_links::CheckOptions {
+        throttle: Arc::new(external_links::Throttle::new(Duration::from_millis(
+            external.per_host_delay_in_milliseconds,
+        ))),
+        recheck_after: Duration::from_secs(external.recheck_after_hours * 60 * 60),
+        interrupted: StopFlag::default(),
+    };
+    if external.enabled {
+        let interrupted = options.interrupted.clone();
+        // The links that were checked before the interruption are still saved to the cache
+        signals::listen(move |signal| {
+            if matches!(signal, Signal::Shutdown) {
+                if interrupted.is_raised() {
+                    std::process::exit(130);
+                }
+                eprintln!(
+                    "Stopping the check and saving the link cache (interrupt again to exit right \
+                    away)"
+                );
+                interrupted.raise();
+            }
+        })
+        .unwrap_or_else(|error| {
+            clean_panic!("Couldn't subscribe to the signals! Details: {}", error);
+        });
+    }
+    let mut problems_count = 0;
+    for (name, site_config) in &config.sites {
+        if site.is_some_and(|site| site != name) {
+            continue;
+        }
+        if options.interrupted.is_raised() {
+            break;
+        }
+        let website = Website::new(site_config.clone(), None);
+        let mut diagnostics = website.diagnostics();
+        if external.enabled {
+            let mut cache = external_links::LinkCache::load(
+                site_config.lock().unwrap().articles_directory.as_ref(),
+            );
+            let links = website.external_links();
+            let outcomes =
+                external_links::check(links.keys().cloned(), client.clone(), &mut cache, &options);
+            if let Err(error) = cache.save() {
+                error!(
+                    "Link cache `{:?}` cannot be written! Details: {}",
+                    cache.path(),
+                    error
+                );
+            }
+            for (link, outcome) in outcomes {
+                if outcome.is_working() {
+                    continue;
+                }
+                for file_name in &links[&link] {
+                    diagnostics.push(diagnostics::Diagnostic {
+                        file_name: file_name.clone(),
+                        problem: diagnostics::Problem::ExternalLink {
+                            link: link.clone(),
+                            outcome: outcome.clone(),
+                        },
+                    });
+                }
+            }
+            // Stable, so the problems of an article stay in their order
+            diagnostics.sort_by(|first, second| first.file_name.cmp(&second.file_name));
+        }
+        println!("{} ({} articles):", name, website.articles_count());
+        if diagnostics.is_empty() {
+            println!("    (no problems)");
+        }
+        for diagnostic in &diagnostics {
+            println!("    {diagnostic}");
+        }
+        problems_count += diagnostics.len();
+    }
+    if options.interrupted.is_raised() {
+        eprintln!("The check was interrupted, so some links weren't checked");
+        std::process::exit(130);
+    }
+    if problems_count > 0 {
+        eprintln!("Found {problems_count} problems");
+        std::process::exit(1);
+    }
 }
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
     let args = Args::parse();
+    config::file::set_path(args.config);
+    SimpleLogger::new().init().unwrap();
+    // Until the configured log level is known
+    log::set_max_level(log::LevelFilter::Info);
 
     match args.command {
-        Command::Run => (),
+        Command::Run(arguments) => config::overrides::set_command_line(
+            arguments
+                .into_overrides()
+                .unwrap_or_else(|error| clean_panic!("{}", error)),
+        ),
+        Command::Config { command } => {
+            run_config_command(command);
+            return Ok(());
+        }
+        Command::Redirects { site, command } => {
+            run_redirects_command(site.as_deref(), &command);
+            return Ok(());
+        }
+        Command::Check { site, external } => {
+            run_check_command(site.as_deref(), &external);
+            return Ok(());
+        }
         Command::CreateSampleConfig { force } => {
-            let config_path = Path::new(CONFIG_FILE_NAME);
+            let config_path = config::file::path();
             assert!(
                 !config_path.exists() || force,
-                "`{:?}` already exists! To overwrite it, add a `--force` flag.",
-                config_path
+                "`{}` already exists! To overwrite it, add a `--force` flag.",
+                config_path.display()
             );
+            let format = config::file::Format::of(config_path)
+                .unwrap_or_else(|error| clean_panic!("Configuration file path is bad: {}", error));
             fs::write(
                 config_path,
-                serde_json::to_string_pretty(&config::Base::<PathBuf>::sample()).unwrap(),
+                format.serialize_with_comments(&config::Raw::sample(), &config::descriptions()),
             )
-            .unwrap();
+            .unwrap_or_else(|error| {
+                clean_panic!("Couldn't write `{:?}`! Details: {}", config_path, error)
+            });
             return Ok(());
         }
     }
 
-    let config: config::Base<PathBuf> = serde_json::from_str(
-        &fs::read_to_string(Path::new(CONFIG_FILE_NAME)).unwrap_or_else(|error| {
-            clean_panic!(
-                "Configuration file isn't accessble! Consider creating a sample configuration \
-                using `blog create-sample-config`, and then editing it. Details: {}",
-                error
-            );
-        }),
-    )
-    .unwrap_or_else(|error| {
-        clean_panic!(
+    let config = config::file::read().unwrap_or_else(|error| match error {
+        config::file::ReadError::Inaccessible(error) => clean_panic!(
+            "Configuration file `{:?}` isn't accessble! Consider creating a sample configuration \
+            using `blog create-sample-config`, and then editing it. Details: {}",
+            config::file::path(),
+            error
+        ),
+        config::file::ReadError::PoorlyFormatted(error) => clean_panic!(
             "Configuration file is poorly formatted!
             Fix it and try to `run` the program again. Details: {}",
             error
-        );
+        ),
     });
-    SimpleLogger::new().init().unwrap();
     set_global_log_level(&config.log_level).unwrap_or_else(|error| clean_panic!("{}", error));
-    let config = config.upgrade().unwrap_or_else(|(error, config)| {
-        clean_panic!(
-            "Articles directory path (`{:?}`) cannot be expanded! Details: {}",
-            config.articles_directory,
-            error
-        );
+    set_file_watcher_delay(config.file_watcher_delay_in_milliseconds);
+    let config = config.upgrade().unwrap_or_else(|error| {
+        clean_panic!("Configuration is invalid: {}", error);
     });
-    let articles_watch_context: Arc<Mutex<WatchContext<ArticlesWatcher>>> = Arc::new(Mutex::new(
-        watch_articles(&config).unwrap_or_else(|error| {
-            clean_panic!(
-                "Articles directory `{:?}` is not accessible! Consider creating it. Details: {}",
-                config.articles_directory.as_ref(),
-                error
-            );
-        }),
-    ));
 
     let config_watch_context: Arc<Mutex<WatchContext<ConfigWatcher>>> =
         Arc::new(Mutex::new(watch_config(&config).unwrap_or_else(|error| {
             clean_panic!(
-                "Configuration file `{}` is not accessible! This is a really rare occasion that
+                "Configuration file `{:?}` is not accessible! This is a really rare occasion that
                 happened here. Consider creating the configuration file (probably, using the `blog
                 create-sample-config` command). Details: {}",
-                CONFIG_FILE_NAME,
+                config::file::path(),
                 error
             );
         })));
 
+    let sites = Arc::new(Mutex::new(Sites::start(&config).unwrap_or_else(
+        |(site_name, error)| {
+            clean_panic!(
+                "Articles directory of site `{}` is not accessible! Consider creating it. \
+                Details: {}",
+                site_name,
+                error
+            );
+        },
+    )));
     let config = Arc::new(Mutex::new(config));
-    let website = Arc::new(Mutex::new(Website::new(config.clone())));
+    let shutdown_flag = StopFlag::default();
+    let current_server_handle: Arc<Mutex<Option<CustomServerHandle>>> = Arc::new(Mutex::new(None));
 
     {
         let config = config.clone();
-        let website = website.clone();
-        begin_watching(
-            articles_watch_context.clone(),
-            config.clone(),
-            "Articles directory",
-            {
-                struct ArticlesDirectory {
-                    config: Arc<Mutex<Config>>,
-                }
-
-                impl CompareWithAbsolutePath for ArticlesDirectory {
-                    fn compare(&self, absolute_path: &Path) -> bool {
-                        self.config.lock().unwrap().articles_directory.as_ref() == absolute_path
+        let sites = sites.clone();
+        let config_watch_context = config_watch_context.clone();
+        let shutdown_flag = shutdown_flag.clone();
+        let current_server_handle = current_server_handle.clone();
+        signals::listen(move |signal| match signal {
+            Signal::Reload => {
+                info!("Reload was requested, re-reading the configuration and the articles");
+                let server_handle = current_server_handle.lock().unwrap().clone();
+                match server_handle {
+                    Some(server_handle) => {
+                        reload_config(&config, &server_handle, &sites, &config_watch_context, true);
                     }
+                    // The server is restarting, so only the articles are reloaded
+                    None => Sites::reload_all(&sites),
                 }
-
-                ArticlesDirectory { config }
-            },
-            watch_articles,
-            {
-                let website = website.clone();
-                move |event| {
-                    match event {
-                        DebouncedEvent::Remove(path) => {
-                            website
-                                .lock()
-                                .unwrap()
-                                .remove_article(&path.file_name_arc_str());
-                        }
-                        DebouncedEvent::Rename(from, to) => {
-                            website
-                                .lock()
-                                .unwrap()
-                                .rename_article(&from.file_name_arc_str(), to.file_name_arc_str());
-                        }
-                        DebouncedEvent::Write(path) | DebouncedEvent::Create(path) => {
-                            website
-                                .lock()
-                                .unwrap()
-                                .update_article(&path.file_name_arc_str());
-                        }
-                        _ => (),
-                    };
+            }
+            Signal::DumpStatus => {
+                let config = config.lock().unwrap();
+                info!(
+                    "Status: listening on {}:{}, watchers are {}",
+                    config.host_name,
+                    config.port,
+                    if shutdown_flag.is_raised() {
+                        "stopping"
+                    } else {
+                        "running"
+                    },
+                );
+                for (name, website) in sites.lock().unwrap().websites() {
+                    let website = website.lock().unwrap();
+                    info!(
+                        "Site `{}`{}: serving {} articles from `{:?}`",
+                        name,
+                        if *name == config.default_site {
+                            " (default)"
+                        } else {
+                            ""
+                        },
+                        website.articles_count(),
+                        website.config().lock().unwrap().articles_directory.as_ref(),
+                    );
                 }
-            },
-            move || website.lock().unwrap().reload_articles_and_index(),
-        );
+                drop(config);
+            }
+            Signal::Shutdown => {
+                if shutdown_flag.is_raised() {
+                    warn!(
+                        "Shutdown was requested again, exiting without waiting for the connections"
+                    );
+                    log::logger().flush();
+                    std::process::exit(130);
+                }
+                info!(
+                    "Shutdown was requested, finishing the open connections (send the signal again \
+                    to exit right away)"
+                );
+                shutdown_flag.raise();
+                let server_handle = current_server_handle.lock().unwrap().clone();
+                if let Some(server_handle) = server_handle {
+                    // The draining takes a while, and the signals are still listened to meanwhile
+                    thread::spawn(move || {
+                        tokio::runtime::Builder::new_current_thread()
+                            .build()
+                            .unwrap()
+                            .block_on(async { server_handle.request_shutdown().await });
+                    });
+                }
+            }
+        })
+        .unwrap_or_else(|error| {
+            clean_panic!("Couldn't subscribe to the signals! Details: {}", error);
+        });
     }
 
-    loop {
-        let server = run_server(website.clone(), &config.lock().unwrap());
+    let result = loop {
+        if shutdown_flag.is_raised() {
+            break Ok(());
+        }
+
+        let server = run_server(sites.clone(), &config.lock().unwrap());
         let server_handle = CustomServerHandle::new(server.handle());
+        *current_server_handle.lock().unwrap() = Some(server_handle.clone());
+        // Every server run gets its own configuration watcher, because the watcher needs to know
+        // the server it should restart
+        let config_watcher_stop_flag = StopFlag::default();
 
-        {
+        let config_watcher_thread = {
             let config_watch_context = config_watch_context.clone();
-            let website = website.clone();
-            let articles_watch_context = articles_watch_context.clone();
 
             let reload_config = {
                 let config_watch_context = config_watch_context.clone();
-                let website = website.clone();
+                let sites = sites.clone();
                 let config = config.clone();
-                let articles_watch_context = articles_watch_context.clone();
                 let server_handle = server_handle.clone();
                 move || {
-                    match fs::read_to_string(Path::new(CONFIG_FILE_NAME)) {
-                        Ok(file_contents) => match serde_json::from_str(&file_contents) {
-                            Ok(new_config) => Config::update(
-                                &config,
-                                new_config,
-                                &server_handle,
-                                &website,
-                                &articles_watch_context,
-                                &config_watch_context,
-                            ),
-                            Err(error) => warn!(
-                                "Updated configuration file is poorly formatted! \
-                                Consider fixing it. Using the old configuration file \
-                                for now. Details: {}",
-                                error
-                            ),
-                        },
-                        Err(error) => error!(
-                            "Configuration file update was noticed, but the file \
-                            couldn't be read. Details: {}",
-                            error
-                        ),
-                    };
+                    reload_config(
+                        &config,
+                        &server_handle,
+                        &sites,
+                        &config_watch_context,
+                        false,
+                    );
                 }
             };
 
@@ -384,36 +880,38 @@ async fn main() -> io::Result<()> {
             begin_watching(
                 config_watch_context,
                 config.clone(),
+                config_watcher_stop_flag.clone(),
                 "Configuration file",
-                {
-                    impl CompareWithAbsolutePath for () {
-                        fn compare(&self, _absolute_path: &Path) -> bool {
-                            // Config watcher is non-recursive, so the path will always be the same
-                            // (the configuration file's path)
-                            true
-                        }
-                    }
-                },
+                ConfigFile,
                 watch_config,
                 {
                     let reload_config = reload_config.clone();
-                    move |event| {
-                        match event {
-                            DebouncedEvent::Write(_path) | DebouncedEvent::Create(_path) => {
-                                reload_config();
-                            }
-                            _ => (),
-                        };
+                    move |event| match event {
+                        DebouncedEvent::Write(_path) | DebouncedEvent::Create(_path) => {
+                            reload_config();
+                        }
+                        _ => (),
                     }
                 },
                 reload_config,
-            );
-        }
+            )
+        };
 
-        server.await?;
+        let server_result = server.await;
+        config_watcher_stop_flag.raise();
+        config_watcher_thread.join().unwrap();
 
-        if !server_handle.restart_was_requested() {
-            break Ok(());
+        if server_result.is_err() || !server_handle.restart_was_requested() {
+            break server_result;
         }
-    }
+    };
+
+    shutdown_flag.raise();
+    // The server is stopped and the signal thread only holds a reference to the sites, so the
+    // watchers can be taken out and stopped
+    let sites = std::mem::replace(&mut *sites.lock().unwrap(), Sites::empty());
+    sites.stop();
+    info!("Watchers are stopped and the caches are saved, shutting down");
+    log::logger().flush();
+    result
 }
@@ -0,0 +1,127 @@
+use pulldown_cmark::Options;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Extensions of the Markdown dialect on top of `CommonMark`. The missing fields take the default
+/// values: footnotes and strikethrough are on (like before the extensions could be chosen), and
+/// the rest is off, so the existing articles don't change
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)] // A switch for every extension
+pub struct MarkdownExtensions {
+    /// `| a | b |` tables
+    pub tables: bool,
+    /// `[^1]` footnotes
+    pub footnotes: bool,
+    /// `~~struck out~~` text
+    pub strikethrough: bool,
+    /// `- [x] done` lists
+    pub task_lists: bool,
+    /// Curly quotes, dashes from `--` and `---`, and ellipses from `...`
+    pub smart_punctuation: bool,
+    /// `# Heading {#id .class}`
+    pub heading_attributes: bool,
+    /// `$inline$` and `$$display$$` LaTeX formulas, rendered to `MathML`
+    pub math: bool,
+    /// `{{< figure src="photo.jpg" caption="..." >}}` and the other shortcodes
+    pub shortcodes: bool,
+    /// `{{< include src="other.md" >}}` and `{{< include src="main.rs" lines="10-20" >}}`
+    pub includes: bool,
+}
+
+impl Default for MarkdownExtensions {
+    fn default() -> Self {
+        Self {
+            tables: false,
+            footnotes: true,
+            strikethrough: true,
+            task_lists: false,
+            smart_punctuation: false,
+            heading_attributes: false,
+            math: false,
+            shortcodes: false,
+            includes: false,
+        }
+    }
+}
+
+/// Extensions that an article turns on or off in its front matter
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct MarkdownOverrides {
+    tables: Option<bool>,
+    footnotes: Option<bool>,
+    strikethrough: Option<bool>,
+    task_lists: Option<bool>,
+    smart_punctuation: Option<bool>,
+    heading_attributes: Option<bool>,
+    math: Option<bool>,
+    shortcodes: Option<bool>,
+    includes: Option<bool>,
+}
+
+impl MarkdownExtensions {
+    pub fn with_overrides(self, overrides: &MarkdownOverrides) -> Self {
+        Self {
+            tables: overrides.tables.unwrap_or(self.tables),
+            footnotes: overrides.footnotes.unwrap_or(self.footnotes),
+            strikethrough: overrides.strikethrough.unwrap_or(self.strikethrough),
+            task_lists: overrides.task_lists.unwrap_or(self.task_lists),
+            smart_punctuation: overrides
+                .smart_punctuation
+                .unwrap_or(self.smart_punctuation),
+            heading_attributes: overrides
+                .heading_attributes
+                .unwrap_or(self.heading_attributes),
+            math: overrides.math.unwrap_or(self.math),
+            shortcodes: overrides.shortcodes.unwrap_or(self.shortcodes),
+            includes: overrides.includes.unwrap_or(self.includes),
+        }
+    }
+
+    pub fn options(&self) -> Options {
+        let mut options = Options::empty();
+        options.set(Options::ENABLE_TABLES, self.tables);
+        options.set(Options::ENABLE_FOOTNOTES, self.footnotes);
+        options.set(Options::ENABLE_STRIKETHROUGH, self.strikethrough);
+        options.set(Options::ENABLE_TASKLISTS, self.task_lists);
+        options.set(Options::ENABLE_SMART_PUNCTUATION, self.smart_punctuation);
+        options.set(Options::ENABLE_HEADING_ATTRIBUTES, self.heading_attributes);
+        options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pulldown_cmark::Options;
+
+    use super::{MarkdownExtensions, MarkdownOverrides};
+
+    #[test]
+    fn defaults_are_the_old_options() {
+        let extensions = MarkdownExtensions::default();
+        assert_eq!(
+            extensions.options(),
+            Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH
+        );
+        assert!(!extensions.math && !extensions.shortcodes && !extensions.includes);
+        let missing_fields: MarkdownExtensions = serde_json::from_str("{}").unwrap();
+        assert!(missing_fields == extensions);
+    }
+
+    #[test]
+    fn front_matter_overrides_the_site() {
+        let site: MarkdownExtensions = serde_json::from_str(r#"{ "tables": true }"#).unwrap();
+        let overrides: MarkdownOverrides =
+            serde_yaml::from_str("{ smart_punctuation: true, tables: false, math: true }").unwrap();
+        let extensions = site.with_overrides(&overrides);
+        assert_eq!(
+            extensions.options(),
+            Options::ENABLE_FOOTNOTES
+                | Options::ENABLE_STRIKETHROUGH
+                | Options::ENABLE_SMART_PUNCTUATION
+        );
+        assert!(extensions.math && !extensions.shortcodes);
+        assert!(site.with_overrides(&MarkdownOverrides::default()) == site);
+    }
+}
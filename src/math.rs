@@ -0,0 +1,252 @@
+use std::panic;
+
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use pulldown_cmark::Event;
+
+use crate::{
+    diagnostics::Problem,
+    prose::{self, find_unescaped},
+};
+
+/// The formulas are replaced with `\u{E000}<index>\u{E001}` before the Markdown is parsed, so
+/// neither the emphasis, nor the escapes, nor the smart punctuation touch them. The characters are
+/// from the private use area, so they don't appear in the articles
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+struct Formula {
+    /// Without the dollar signs
+    source: String,
+    is_display: bool,
+    /// `MathML`, or the reason why there's none
+    rendered: Result<String, String>,
+}
+
+enum Piece<'math, 'text> {
+    Text(&'text str),
+    Formula(&'math Formula),
+}
+
+/// `$inline$` and `$$display$$` formulas of an article, rendered to `MathML`
+#[derive(Default)]
+pub struct Math {
+    formulas: Vec<Formula>,
+}
+
+fn render(source: &str, is_display: bool) -> Result<String, String> {
+    let style = if is_display {
+        DisplayStyle::Block
+    } else {
+        DisplayStyle::Inline
+    };
+    // A panic of the converter shouldn't take the whole site down
+    let result = panic::catch_unwind(|| latex_to_mathml(source, style));
+    match result {
+        // Unknown commands become error messages in the output
+        Ok(Ok(mathml)) if mathml.contains("[PARSE ERROR") => {
+            Err("the formula is incomplete or has unknown commands".to_owned())
+        }
+        Ok(Ok(mathml)) => Ok(escape_text(&mathml)),
+        Ok(Err(error)) => Err(error.to_string()),
+        Err(_panic) => Err("the formula cannot be parsed".to_owned()),
+    }
+}
+
+/// The converter doesn't escape the `<`, `>` and `&` of the formulas (like in `x < y`)
+fn escape_text(mathml: &str) -> String {
+    let mut escaped = String::with_capacity(mathml.len());
+    let mut is_in_tag = false;
+    for (index, character) in mathml.char_indices() {
+        let rest = &mathml[index + character.len_utf8()..];
+        match character {
+            '<' if !is_in_tag
+                && rest.starts_with(|next: char| next.is_ascii_alphabetic() || next == '/') =>
+            {
+                is_in_tag = true;
+                escaped.push(character);
+            }
+            '>' if is_in_tag => {
+                is_in_tag = false;
+                escaped.push(character);
+            }
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            // `&#x2061;` and the like are written by the converter itself
+            '&' if !rest.starts_with('#') => escaped.push_str("&amp;"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+impl Math {
+    /// Replaces the formulas outside of the code with placeholders
+    pub fn extract(markdown: &str) -> (String, Self) {
+        let mut math = Self::default();
+        let output = prose::replace(markdown, |text| {
+            let (length, source, is_display) = Self::formula_at(text)?;
+            let placeholder = format!(
+                "{}{}{}",
+                PLACEHOLDER_START,
+                math.formulas.len(),
+                PLACEHOLDER_END
+            );
+            math.formulas.push(Formula {
+                rendered: render(source, is_display),
+                source: source.to_owned(),
+                is_display,
+            });
+            Some((length, placeholder))
+        });
+        (output, math)
+    }
+
+    /// The length of the formula at the start of `text`, its source and whether it's a display
+    /// one. Like in Pandoc, `$` must be followed by a non-space, and the closing `$` must be
+    /// preceded by a non-space and not followed by a digit (so `$5 and $10` are not formulas)
+    fn formula_at(text: &str) -> Option<(usize, &str, bool)> {
+        if !text.starts_with('$') {
+            return None;
+        }
+        if let Some(rest) = text.strip_prefix("$$") {
+            let end = find_unescaped(rest, "$$")?;
+            let source = &rest[..end];
+            return (!source.trim().is_empty()).then_some((end + 4, source.trim(), true));
+        }
+        let rest = &text[1..];
+        if rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let end = find_unescaped(rest, "$")?;
+        let source = &rest[..end];
+        // The closing `$` is not searched for in the code spans
+        let is_closing = !source.is_empty()
+            && !source.contains('`')
+            && !source.ends_with(char::is_whitespace)
+            && !rest[end + 1..].starts_with(|character: char| character.is_ascii_digit());
+        is_closing.then_some((end + 2, source, false))
+    }
+
+    fn formula(&self, text: &str) -> Option<&Formula> {
+        self.formulas.get(text.parse::<usize>().ok()?)
+    }
+
+    fn html(formula: &Formula) -> String {
+        match &formula.rendered {
+            Ok(mathml) => mathml.clone(),
+            Err(error) => {
+                let delimiter = if formula.is_display { "$$" } else { "$" };
+                format!(
+                    r#"<span class="math-error" title="{}">{}{}{}</span>"#,
+                    html_escape::encode_double_quoted_attribute(error),
+                    delimiter,
+                    html_escape::encode_text(&formula.source),
+                    delimiter
+                )
+            }
+        }
+    }
+
+    /// Splits the text at the placeholders
+    fn split<'math, 'text>(&'math self, text: &'text str) -> Vec<Piece<'math, 'text>> {
+        let mut pieces = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(PLACEHOLDER_START) {
+            let contents = &rest[start + PLACEHOLDER_START.len_utf8()..];
+            let Some((index, after)) = contents.split_once(PLACEHOLDER_END) else {
+                break;
+            };
+            let Some(formula) = self.formula(index) else {
+                break;
+            };
+            pieces.push(Piece::Text(&rest[..start]));
+            pieces.push(Piece::Formula(formula));
+            rest = after;
+        }
+        pieces.push(Piece::Text(rest));
+        pieces
+    }
+
+    /// The text with the formulas as they were written, for the titles and the alternative texts
+    pub fn plain_text(&self, text: &str) -> String {
+        self.split(text)
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text,
+                Piece::Formula(formula) => &formula.source,
+            })
+            .collect()
+    }
+
+    /// Turns the placeholders in the text into `MathML`
+    pub fn expand<'event>(&self, event: Event<'event>) -> Vec<Event<'event>> {
+        match event {
+            Event::Text(text) if text.contains(PLACEHOLDER_START) => self
+                .split(&text)
+                .into_iter()
+                .filter_map(|piece| match piece {
+                    Piece::Text("") => None,
+                    Piece::Text(text) => Some(Event::Text(text.to_owned().into())),
+                    Piece::Formula(formula) => Some(Event::Html(Self::html(formula).into())),
+                })
+                .collect(),
+            event => vec![event],
+        }
+    }
+
+    pub fn problems(&self) -> impl Iterator<Item = Problem> + '_ {
+        self.formulas.iter().filter_map(|formula| {
+            formula
+                .rendered
+                .as_ref()
+                .err()
+                .map(|error| Problem::InvalidMath {
+                    formula: formula.source.clone(),
+                    details: error.clone(),
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Math;
+
+    /// The Markdown after the extraction, and the sources of the formulas
+    fn extract(markdown: &str) -> (String, Vec<String>) {
+        let (output, math) = Math::extract(markdown);
+        let sources = math
+            .formulas
+            .into_iter()
+            .map(|formula| formula.source)
+            .collect();
+        (output, sources)
+    }
+
+    #[test]
+    fn finds_formulas() {
+        let (output, sources) = extract("$x$, $5 and $10, $ y$ and $$\\frac{a}{b}$$");
+        assert_eq!(sources, ["x", "\\frac{a}{b}"]);
+        assert_eq!(
+            output,
+            "\u{E000}0\u{E001}, $5 and $10, $ y$ and \u{E000}1\u{E001}"
+        );
+    }
+
+    #[test]
+    fn leaves_code_alone() {
+        let code = "```\n$a$\n```\n\n    let b = $b$;\n    let c = $c$;\n\n    let d = $d$;\n";
+        let (output, sources) = extract(&format!("{code}$x$ and `$e$`"));
+        assert_eq!(sources, ["x"]);
+        assert_eq!(output, format!("{code}\u{E000}0\u{E001} and `$e$`"));
+    }
+
+    #[test]
+    fn leaves_link_destinations_alone() {
+        let markdown = "[$x$](https://x/?a=$1$b \"$t$\") <https://x/?a=$1$b> ![a](b$1$c.png)\n\n\
+                        [q]: https://x/?a=$1$b\n";
+        let (output, sources) = extract(markdown);
+        assert_eq!(sources, ["x"]);
+        assert_eq!(output, markdown.replacen("$x$", "\u{E000}0\u{E001}", 1));
+    }
+}
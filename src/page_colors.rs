@@ -1,6 +1,7 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Deserialize, Serialize, PartialEq, Eq, JsonSchema)]
 pub struct PageColors {
     title: String,
     background: String,
@@ -8,7 +9,10 @@ pub struct PageColors {
 
 impl PageColors {
     pub fn new<T: Into<String>, B: Into<String>>(title: T, background: B) -> Self {
-        Self { background: background.into(), title: title.into() }
+        Self {
+            background: background.into(),
+            title: title.into(),
+        }
     }
 
     pub fn background(&self) -> &str {
@@ -19,3 +23,10 @@ impl PageColors {
         self.title.as_ref()
     }
 }
+
+/// Black on white, for the sites without `index_page_colors`
+impl Default for PageColors {
+    fn default() -> Self {
+        Self::new("000000", "FFFFFF")
+    }
+}
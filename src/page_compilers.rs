@@ -1,27 +1,90 @@
-use std::{fmt::Display, fs, io, iter, path::PathBuf, sync::Arc};
+use std::{
+    fmt::{Display, Write},
+    fs, io, iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use askama::Template;
+use log::warn;
 use peeking_take_while::PeekableExt;
 use pulldown_cmark::CowStr;
+use rand::prelude::SliceRandom;
 
 use crate::{
-    config::Config,
+    assets::{fingerprinted_path, Assets},
+    base_url::BaseUrl,
+    callouts,
+    citations::Citations,
+    config::SiteConfig,
+    diagnostics::{anchors_of, LinkCheck, Problem},
+    diagrams,
+    files::{decode_path, Lookup},
+    front_matter::FrontMatter,
+    images::{cache_directory_for, picture_html, resized_extension},
+    includes,
+    markdown_extensions::MarkdownExtensions,
+    math::Math,
+    page_colors::PageColors,
+    shortcodes::{self, Shortcodes},
     utils::FileNameShortcut,
     website::{ArticleTitle, FileTime, IndexArticleInfo, ModificationTime},
 };
 
+/// The article of a page bundle (a directory in the articles directory)
+pub const BUNDLE_INDEX: &str = "index.md";
+
 pub struct CompiledArticleInfo {
     pub title: ArticleTitle,
     pub file_name: Arc<str>,
+    pub slug: Arc<str>,
+    pub aliases: Vec<Arc<str>>,
+    /// Files that affect the compiled article (the files it links to, for example). The article
+    /// is compiled again when one of them changes
+    pub dependencies: Vec<PathBuf>,
+    /// File names of the articles that this one links to (by wiki links or by their Markdown
+    /// files)
+    pub links_to: Vec<Arc<str>>,
+    /// Targets of the wiki links that lead nowhere, as [`normalize_wiki_name`] returns them
+    pub missing_wiki_links: Vec<String>,
+    /// Problems that are known without looking at the other articles
+    pub problems: Vec<Problem>,
+    /// Links that are checked when the other articles are compiled
+    pub link_checks: Vec<LinkCheck>,
+    /// Parts of the page that the links can lead to (`#part`)
+    pub anchors: Vec<String>,
+    /// Links to the other sites (without the fragments), for `blog check --external`
+    pub external_links: Vec<String>,
     pub body: String,
     pub modification_time: ModificationTime,
 }
 
 #[derive(Template)]
 #[template(path = "article.html")]
-struct ArticleTemplate {
+struct ArticleTemplate<'article_template> {
     body: String,
     title: Arc<str>,
+    theme_style: String,
+    canonical_url: Option<String>,
+    linked_from: &'article_template [IndexArticleInfo],
+    base_url: &'article_template BaseUrl,
+}
+
+/// File name and slug of an article
+pub type ArticleReference = (Arc<str>, Arc<str>);
+
+/// What an article knows about the other articles while it's compiled
+pub struct OtherArticles<'website> {
+    /// Finds the article (its file name and slug) that a wiki link leads to
+    pub resolve_wiki_link: &'website dyn Fn(&str) -> Option<ArticleReference>,
+    /// Articles that link to this one
+    pub linked_from: Vec<IndexArticleInfo>,
+}
+
+/// Wiki links find the articles by their titles and file names (with or without the extension)
+/// regardless of the case and the surrounding spaces
+pub fn normalize_wiki_name(name: &str) -> String {
+    name.trim().to_lowercase()
 }
 
 pub trait ExtractBaseName {
@@ -42,47 +105,736 @@ impl ExtractBaseName for Arc<str> {
     }
 }
 
-pub fn compile_article(path: &PathBuf, config: &Config) -> io::Result<CompiledArticleInfo> {
-    let file_contents = fs::read_to_string(&path)?;
-    let mut parser = pulldown_cmark::Parser::new_ext(&file_contents, {
-        let mut options = pulldown_cmark::Options::empty();
-        options.insert(pulldown_cmark::Options::ENABLE_STRIKETHROUGH);
-        options.insert(pulldown_cmark::Options::ENABLE_FOOTNOTES);
-        options
+/// `My First Post` -> `my-first-post`
+pub fn slug_from_base_name(base_name: &Arc<str>) -> Arc<str> {
+    let mut slug = String::with_capacity(base_name.len());
+    for character in base_name.chars() {
+        if character.is_alphanumeric() {
+            slug.extend(character.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        base_name.clone()
+    } else {
+        slug.into()
+    }
+}
+
+fn article_slug(
+    file_name: &Arc<str>,
+    base_name: &Arc<str>,
+    front_matter: &FrontMatter,
+) -> Arc<str> {
+    match front_matter
+        .slug
+        .as_deref()
+        .map(|slug| slug.trim_matches('/'))
+    {
+        Some(slug) if !slug.is_empty() && !slug.contains('/') => slug.into(),
+        Some(slug) => {
+            warn!(
+                "Slug `{}` of `{}` should be a non-empty string without slashes, ignoring it",
+                slug, file_name
+            );
+            slug_from_base_name(base_name)
+        }
+        None => slug_from_base_name(base_name),
+    }
+}
+
+/// Path and the rest of the link (`?query#fragment`), if the link doesn't lead to another site
+fn split_local_link(link: &str) -> Option<(&str, &str)> {
+    if link.starts_with("//") || link.starts_with('#') {
+        return None;
+    }
+    let (path, suffix) = link.split_at(link.find(['?', '#']).unwrap_or(link.len()));
+    // `https:`, `mailto:` and so on
+    if path.split('/').next().unwrap_or_default().contains(':') || path.is_empty() {
+        None
+    } else {
+        Some((path, suffix))
+    }
+}
+
+/// Path relative to the files directory (still percent-encoded, see [`decode_path`]) and the rest
+/// of the link, if the link leads to the site itself
+fn site_path<'link>(link: &'link str, config: &SiteConfig) -> Option<(&'link str, &'link str)> {
+    let (path, suffix) = split_local_link(link)?;
+    let path = path
+        .strip_prefix(config.base_url.path_prefix())
+        .unwrap_or(path)
+        .trim_start_matches('/');
+    if path.is_empty() {
+        None
+    } else {
+        Some((path, suffix))
+    }
+}
+
+/// Path and the rest of the link, if the link is a relative one to a Markdown file
+fn relative_markdown_link(link: &str) -> Option<(&str, &str)> {
+    let (path, suffix) = split_local_link(link)?;
+    let is_markdown = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("md"));
+    (!path.starts_with('/') && is_markdown).then_some((path, suffix))
+}
+
+/// Text of an HTML fragment without the tags, with the entities decoded
+fn text_of_html(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(tag_start) = rest.find('<') {
+        text.push_str(&rest[..tag_start]);
+        rest = rest[tag_start..]
+            .find('>')
+            .map_or("", |tag_end| &rest[tag_start + tag_end + 1..]);
+    }
+    text.push_str(rest);
+    html_escape::decode_html_entities(&text).into_owned()
+}
+
+/// `../a/./b` from `/c/d` -> `/c/a/b`, without looking at the file system
+fn resolve_relative(directory: &Path, relative_path: &str) -> Option<PathBuf> {
+    let mut resolved = directory.to_path_buf();
+    for segment in relative_path.split('/') {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                if !resolved.pop() {
+                    return None;
+                }
+            }
+            segment => resolved.push(segment),
+        }
+    }
+    Some(resolved)
+}
+
+/// Whether the entry of the articles directory is an article: a Markdown file or a page bundle.
+/// The other files there (the data files and the included files, for example) aren't published
+pub fn is_article(path: &Path) -> bool {
+    if path.is_dir() {
+        path.join(BUNDLE_INDEX).is_file()
+    } else {
+        path.extension().is_some_and(|extension| extension == "md") && path.is_file()
+    }
+}
+
+/// Markdown file of the article at `path` (a file or a page bundle), and the name that its slug
+/// and title come from
+fn markdown_source(path: &Path) -> (PathBuf, Arc<str>) {
+    let file_name = path.file_name_arc_str();
+    if path.is_dir() {
+        (path.join(BUNDLE_INDEX), file_name)
+    } else {
+        (path.to_owned(), file_name.base_name())
+    }
+}
+
+/// Slug of another article, which may be not compiled yet
+fn slug_of_article(path: &Path) -> Option<Arc<str>> {
+    let (markdown_path, base_name) = markdown_source(path);
+    let file_contents = fs::read_to_string(markdown_path).ok()?;
+    let file_name = path.file_name_arc_str();
+    let (front_matter, _file_contents, _error) = FrontMatter::extract(&file_contents);
+    Some(article_slug(&file_name, &base_name, &front_matter))
+}
+
+/// Resolves the links of an article: the links to the other articles' Markdown files lead to the
+/// articles, the files get fingerprinted, the images get resized, and the relative links of a page
+/// bundle lead to the files of the bundle. The links that may lead nowhere are remembered to be
+/// checked
+#[allow(clippy::struct_field_names)] // The same names as in `CompiledArticleInfo`
+struct Links<'compilation> {
+    config: &'compilation SiteConfig,
+    assets: &'compilation mut Assets,
+    slug: &'compilation str,
+    /// If the article is a page bundle
+    bundle_directory: Option<&'compilation Path>,
+    others: &'compilation OtherArticles<'compilation>,
+    dependencies: Vec<PathBuf>,
+    links_to: Vec<Arc<str>>,
+    missing_wiki_links: Vec<String>,
+    problems: Vec<Problem>,
+    link_checks: Vec<LinkCheck>,
+    external_links: Vec<String>,
+}
+
+/// `part` from `?query#part`, if it's not empty
+fn fragment_of(suffix: &str) -> Option<&str> {
+    suffix
+        .split_once('#')
+        .map(|(_query, fragment)| fragment)
+        .filter(|fragment| !fragment.is_empty())
+}
+
+impl Links<'_> {
+    /// The file of the bundle that a relative link leads to, and the link that it's served at
+    fn bundle_file(&self, link: &str) -> Option<(PathBuf, String)> {
+        let bundle_directory = self.bundle_directory?;
+        let (path, suffix) = split_local_link(link)?;
+        let decoded_path = decode_path(path)?;
+        if decoded_path.starts_with('/') || decoded_path.trim_start_matches("./") == BUNDLE_INDEX {
+            return None;
+        }
+        let decoded_path = decoded_path.trim_start_matches("./");
+        match self
+            .config
+            .bundle_policy(bundle_directory)
+            .lookup(decoded_path)
+        {
+            Lookup::File(file_path) => Some((
+                file_path,
+                format!(
+                    "{}{}",
+                    self.config
+                        .base_url
+                        .link(&format!("{}/{}", self.slug, decoded_path)),
+                    suffix
+                ),
+            )),
+            Lookup::Directory(_) | Lookup::NotFound => None,
+        }
+    }
+
+    /// Turns a link to a file from the files directory into the fingerprinted one (if
+    /// fingerprinting is enabled), remembering the file as a dependency
+    fn fingerprint<'link>(&mut self, link: CowStr<'link>) -> CowStr<'link> {
+        if !self.config.fingerprint_files {
+            return link;
+        }
+        let Some((path, suffix)) = site_path(&link, self.config) else {
+            return link;
+        };
+        let Some(decoded_path) = decode_path(path) else {
+            return link;
+        };
+        match self.config.files_policy().lookup(&decoded_path) {
+            Lookup::File(file_path) => {
+                let hash = self.assets.hash_of(&file_path);
+                self.dependencies.push(file_path);
+                match hash {
+                    Some(hash) => format!(
+                        "{}{}",
+                        self.config
+                            .base_url
+                            .link(&fingerprinted_path(&decoded_path, &hash)),
+                        suffix
+                    )
+                    .into(),
+                    None => link,
+                }
+            }
+            // The link will start working when the file appears
+            Lookup::NotFound => {
+                self.dependencies
+                    .push(self.config.files_directory.join(&*decoded_path));
+                link
+            }
+            Lookup::Directory(_) => link,
+        }
+    }
+
+    /// `other-post.md#part` -> `/other-post#part`, if the link leads to the Markdown file of an
+    /// article (relative to this article's file)
+    fn article_link(&mut self, link: &str) -> Option<String> {
+        let (path, suffix) = relative_markdown_link(link)?;
+        let path = decode_path(path)?;
+        let articles_directory = self.config.articles_directory.as_ref();
+        let target = resolve_relative(self.bundle_directory.unwrap_or(articles_directory), &path)?;
+        let mut components = target.strip_prefix(articles_directory).ok()?.components();
+        let article_path = match (components.next(), components.next(), components.next()) {
+            (Some(file_name), None, None) => articles_directory.join(file_name),
+            (Some(bundle_name), Some(index), None) if index.as_os_str() == BUNDLE_INDEX => {
+                articles_directory.join(bundle_name)
+            }
+            _ => return None,
+        };
+        // The link changes with the slug of the other article
+        self.dependencies.push(target);
+        let slug = slug_of_article(&article_path)?;
+        let file_name = article_path.file_name_arc_str();
+        if let Some(fragment) = fragment_of(suffix) {
+            self.link_checks.push(LinkCheck::Anchor {
+                link: link.to_owned(),
+                article: Some(file_name.clone()),
+                anchor: fragment.to_owned(),
+            });
+        }
+        self.links_to.push(file_name);
+        Some(format!("{}{}", self.config.base_url.link(&slug), suffix))
+    }
+
+    /// `[[Article Title]]`, `[[file-name|label]]` or `[[Article Title#part]]`. The wiki links
+    /// that lead nowhere are shown as their labels
+    fn wiki_link(&mut self, contents: &str) -> String {
+        let (target, label) = contents.split_once('|').unwrap_or((contents, contents));
+        let (target, fragment) = target.split_at(target.find('#').unwrap_or(target.len()));
+        let label = html_escape::encode_text(label.trim());
+        let Some((file_name, slug)) = (self.others.resolve_wiki_link)(target) else {
+            // Reported by the website, because the article may be compiled before the target
+            self.missing_wiki_links.push(normalize_wiki_name(target));
+            return format!(r#"<span class="missing-link">{label}</span>"#);
+        };
+        if let Some(fragment) = fragment_of(fragment.trim()) {
+            self.link_checks.push(LinkCheck::Anchor {
+                link: format!("[[{contents}]]"),
+                article: Some(file_name.clone()),
+                anchor: fragment.to_owned(),
+            });
+        }
+        self.links_to.push(file_name);
+        format!(
+            r#"<a href="{}{}">{label}</a>"#,
+            html_escape::encode_double_quoted_attribute(&self.config.base_url.link(&slug)),
+            html_escape::encode_double_quoted_attribute(fragment.trim()),
+        )
+    }
+
+    fn link<'link>(&mut self, link: CowStr<'link>) -> CowStr<'link> {
+        if let Some(fragment) = fragment_of(&link) {
+            if link.starts_with('#') {
+                self.link_checks.push(LinkCheck::Anchor {
+                    link: link.to_string(),
+                    article: None,
+                    anchor: fragment.to_owned(),
+                });
+                return link;
+            }
+        }
+        if link.starts_with("http://") || link.starts_with("https://") {
+            let url = link.split('#').next().unwrap_or_default();
+            self.external_links.push(url.to_owned());
+            return link;
+        }
+        if let Some(article_link) = self.article_link(&link) {
+            return article_link.into();
+        }
+        if let Some((_file_path, bundle_link)) = self.bundle_file(&link) {
+            return bundle_link.into();
+        }
+        if relative_markdown_link(&link).is_some() {
+            // A Markdown file that is not an article
+            self.problems.push(Problem::BrokenLink(link.to_string()));
+        } else if let Some((path, suffix)) = site_path(&link, self.config) {
+            self.link_checks.push(LinkCheck::Path {
+                link: link.to_string(),
+                path: path.to_owned(),
+                anchor: fragment_of(suffix).unwrap_or_default().to_owned(),
+            });
+        }
+        self.fingerprint(link)
+    }
+
+    /// `<picture>` with the resized copies of an image from the bundle or from the files
+    /// directory, if they are ready (they are made in the background otherwise, and the article is
+    /// compiled again after that)
+    fn responsive_image(&mut self, link: &str, alt: &str, title: &str) -> Option<String> {
+        if self.config.image_widths.is_empty() {
+            return None;
+        }
+        let file_path = if let Some((file_path, _bundle_link)) = self.bundle_file(link) {
+            file_path
+        } else {
+            let (path, _suffix) = site_path(link, self.config)?;
+            let path = decode_path(path)?;
+            let Lookup::File(file_path) = self.config.files_policy().lookup(&path) else {
+                return None;
+            };
+            self.dependencies.push(file_path.clone());
+            file_path
+        };
+        resized_extension(&file_path)?;
+        let hash = self.assets.hash_of(&file_path)?;
+        let variants = self.assets.images.variants(
+            &file_path,
+            &hash,
+            &self.config.image_widths,
+            &cache_directory_for(&self.config.files_directory),
+        )?;
+        Some(picture_html(
+            &hash,
+            &variants,
+            alt,
+            title,
+            &self.config.base_url,
+        ))
+    }
+}
+
+impl shortcodes::Context for Links<'_> {
+    fn theme_directory(&self) -> Option<&Path> {
+        self.config.theme_directory.as_deref()
+    }
+
+    fn link(&mut self, link: &str) -> String {
+        Links::link(self, link.to_owned().into()).into_string()
+    }
+
+    fn picture(&mut self, link: &str, alt: &str, title: &str) -> Option<String> {
+        self.responsive_image(link, alt, title)
+    }
+
+    fn data_file(&mut self, path: &str) -> Option<PathBuf> {
+        let config = self.config;
+        // The directory of the Markdown file: the bundle, or the articles directory
+        let article_directory = self
+            .bundle_directory
+            .unwrap_or_else(|| config.articles_directory.as_ref());
+        for policy in [
+            config.bundle_policy(article_directory),
+            config.files_policy(),
+        ] {
+            // The article is compiled again when the file appears
+            self.dependencies.push(policy.files_directory.join(path));
+            if let Lookup::File(file_path) = policy.lookup(path) {
+                self.dependencies.push(file_path.clone());
+                return Some(file_path);
+            }
+        }
+        None
+    }
+
+    fn depend_on(&mut self, path: PathBuf) {
+        self.dependencies.push(path);
+    }
+
+    fn report(&mut self, problem: Problem) {
+        self.problems.push(problem);
+    }
+}
+
+/// Links are resolved, the images become `<picture>`s of their resized copies, and the shortcodes
+/// and the citations are expanded
+fn map_events<'events>(
+    mut parser: pulldown_cmark::Parser<'events, '_>,
+    links: &mut Links,
+    math: &Math,
+    shortcodes: &Shortcodes,
+    citations: &mut Citations,
+) -> Vec<pulldown_cmark::Event<'events>> {
+    use pulldown_cmark::{CodeBlockKind, Event, Tag};
+    let mut events = Vec::new();
+    // The parser splits the text at the brackets, so the text is joined to find the wiki links
+    let mut text = String::new();
+    let mut is_in_code_block = false;
+    while let Some(event) = parser.next() {
+        match event {
+            Event::Text(contents) if !is_in_code_block => {
+                text.push_str(&contents);
+                continue;
+            }
+            // A shortcode that is alone in its paragraph (like a figure) isn't put in a paragraph
+            Event::End(Tag::Paragraph)
+                if matches!(events.last(), Some(Event::Start(Tag::Paragraph))) =>
+            {
+                if let Some(html) = shortcodes.alone(&text, links) {
+                    events.pop();
+                    events.push(Event::Html(html.into()));
+                    text.clear();
+                    continue;
+                }
+                flush_text(&mut text, &mut events, links, shortcodes, citations);
+            }
+            _ => flush_text(&mut text, &mut events, links, shortcodes, citations),
+        }
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref language)))
+                if !links.config.diagram_language.is_empty()
+                    && language.split_whitespace().next()
+                        == Some(links.config.diagram_language.as_str()) =>
+            {
+                let mut ascii = String::new();
+                let mut end = None;
+                for event in parser.by_ref() {
+                    match event {
+                        Event::Text(text) => ascii.push_str(&text),
+                        Event::End(Tag::CodeBlock(_)) => {
+                            end = Some(event);
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+                let colors = diagrams::article_colors(links.config, links.slug);
+                match diagrams::render(&ascii, &colors) {
+                    Ok(svg) => events.push(Event::Html(svg.into())),
+                    Err(details) => {
+                        links.problems.push(Problem::InvalidDiagram(details));
+                        events.push(event);
+                        events.push(Event::Text(ascii.into()));
+                        events.extend(end);
+                    }
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                is_in_code_block = true;
+                events.push(event);
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                is_in_code_block = false;
+                events.push(event);
+            }
+            Event::Start(Tag::Link(link_type, link, title)) => {
+                events.push(Event::Start(Tag::Link(link_type, links.link(link), title)));
+            }
+            Event::Start(Tag::Image(link_type, link, title)) => {
+                push_image(
+                    &mut parser,
+                    link_type,
+                    link,
+                    title,
+                    &mut events,
+                    links,
+                    math,
+                );
+            }
+            event => events.push(event),
+        }
+    }
+    flush_text(&mut text, &mut events, links, shortcodes, citations);
+    events
+}
+
+/// An image, or a `<picture>` of its resized copies. The parser is right after the start of the
+/// image
+fn push_image<'events>(
+    parser: &mut pulldown_cmark::Parser<'events, '_>,
+    link_type: pulldown_cmark::LinkType,
+    link: CowStr<'events>,
+    title: CowStr<'events>,
+    events: &mut Vec<pulldown_cmark::Event<'events>>,
+    links: &mut Links,
+    math: &Math,
+) {
+    use pulldown_cmark::{Event, Tag};
+    // The alternative text is inside the image (and can have images too)
+    let mut contents = Vec::new();
+    let mut depth = 0;
+    let mut end = None;
+    for event in parser.by_ref() {
+        match event {
+            Event::Start(Tag::Image(..)) => depth += 1,
+            Event::End(Tag::Image(..)) if depth == 0 => {
+                end = Some(event);
+                break;
+            }
+            Event::End(Tag::Image(..)) => depth -= 1,
+            _ => (),
+        }
+        // The formulas of the alternative text stay as they were written
+        contents.push(match event {
+            Event::Text(text) => Event::Text(math.plain_text(&text).into()),
+            event => event,
+        });
+    }
+    let alt: String = contents
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(&**text),
+            _ => None,
+        })
+        .collect();
+    if let Some(picture) = links.responsive_image(&link, &alt, &title) {
+        events.push(Event::Html(picture.into()));
+        return;
+    }
+    events.push(Event::Start(Tag::Image(link_type, links.link(link), title)));
+    events.extend(contents);
+    events.extend(end);
+}
+
+/// Turns the collected text into the text, the wiki links, the shortcodes and the citations
+fn flush_text(
+    text: &mut String,
+    events: &mut Vec<pulldown_cmark::Event<'_>>,
+    links: &mut Links,
+    shortcodes: &Shortcodes,
+    citations: &mut Citations,
+) {
+    use pulldown_cmark::Event;
+    if text.is_empty() {
+        return;
+    }
+    for piece in shortcodes.expand(text, links) {
+        match piece {
+            shortcodes::Piece::Text(text) => {
+                for piece in citations.expand(text) {
+                    match piece {
+                        shortcodes::Piece::Text(text) => push_wiki_links(text, events, links),
+                        shortcodes::Piece::Html(html) => events.push(Event::Html(html.into())),
+                    }
+                }
+            }
+            shortcodes::Piece::Html(html) => events.push(Event::Html(html.into())),
+        }
+    }
+    text.clear();
+}
+
+fn push_wiki_links(text: &str, events: &mut Vec<pulldown_cmark::Event<'_>>, links: &mut Links) {
+    use pulldown_cmark::Event;
+    let mut rest = text;
+    let mut plain_text = String::new();
+    while let Some(start) = rest.find("[[") {
+        let contents_start = start + 2;
+        let Some(length) = rest[contents_start..].find("]]") else {
+            break;
+        };
+        let contents = &rest[contents_start..contents_start + length];
+        if contents.trim().is_empty() || contents.contains(['[', ']']) {
+            plain_text.push_str(&rest[..contents_start]);
+            rest = &rest[contents_start..];
+            continue;
+        }
+        plain_text.push_str(&rest[..start]);
+        if !plain_text.is_empty() {
+            events.push(Event::Text(std::mem::take(&mut plain_text).into()));
+        }
+        events.push(Event::Html(links.wiki_link(contents).into()));
+        rest = &rest[contents_start + length + 2..];
+    }
+    plain_text.push_str(rest);
+    if !plain_text.is_empty() {
+        events.push(Event::Text(plain_text.into()));
+    }
+}
+
+/// The Markdown after the includes, and the shortcodes and the formulas that were taken out of it
+fn extract_extensions(
+    file_contents: &str,
+    markdown_path: &Path,
+    config: &SiteConfig,
+    extensions: &MarkdownExtensions,
+    dependencies: &mut Vec<PathBuf>,
+    problems: &mut Vec<Problem>,
+) -> (String, Shortcodes, Math) {
+    let file_contents = if extensions.includes {
+        let include_directories: Vec<&Path> =
+            [config.articles_directory.as_ref(), &config.files_directory]
+                .into_iter()
+                .chain(config.include_directory.as_deref())
+                .collect();
+        includes::expand(
+            file_contents,
+            markdown_path,
+            &include_directories,
+            dependencies,
+            problems,
+        )
+    } else {
+        file_contents.to_owned()
+    };
+    let (file_contents, shortcodes) = if extensions.shortcodes {
+        Shortcodes::extract(&file_contents)
+    } else {
+        (file_contents, Shortcodes::default())
+    };
+    let (file_contents, math) = if extensions.math {
+        Math::extract(&file_contents)
+    } else {
+        (file_contents, Math::default())
+    };
+    problems.extend(math.problems());
+    (file_contents, shortcodes, math)
+}
+
+/// Citations against the bibliography of the article, or of the site
+fn load_citations(
+    front_matter: &FrontMatter,
+    markdown_path: &Path,
+    config: &SiteConfig,
+    dependencies: &mut Vec<PathBuf>,
+) -> Citations {
+    // The front matter's path is relative to the Markdown file
+    let bibliography = front_matter.bibliography.as_ref().map_or_else(
+        || config.bibliography.clone(),
+        |bibliography| Some(markdown_path.parent().unwrap().join(bibliography)),
+    );
+    bibliography.map_or_else(Citations::default, |bibliography| {
+        Citations::load(
+            &bibliography,
+            front_matter.citation_style.unwrap_or(config.citation_style),
+            dependencies,
+        )
     })
-    .peekable();
-    let mut compiled_body = String::new();
+}
+
+/// Pushes the HTML of the article, and returns the text of its first heading
+fn push_body<'events>(
+    compiled_body: &mut String,
+    events: impl Iterator<Item = pulldown_cmark::Event<'events>>,
+    math: &Math,
+) -> Arc<str> {
+    use pulldown_cmark::{Event, Tag};
+    #[allow(clippy::unused_peekable)] // `peeking_take_while` peeks
+    let mut events = events.peekable();
+    // The code is shown as it was written, even if it has the characters of the placeholders
+    let mut is_in_code_block = false;
+    let mut expand = |event: Event<'events>| {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => is_in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => is_in_code_block = false,
+            _ => (),
+        }
+        if is_in_code_block {
+            vec![event]
+        } else {
+            math.expand(event)
+        }
+    };
     pulldown_cmark::html::push_html(
-        &mut compiled_body,
-        parser.by_ref().peeking_take_while(|event| {
-            !matches!(
-                event,
-                pulldown_cmark::Event::Start(pulldown_cmark::Tag::Heading(..))
-            )
-        }),
+        compiled_body,
+        events
+            .by_ref()
+            .peeking_take_while(|event| !matches!(event, Event::Start(Tag::Heading(..))))
+            .flat_map(&mut expand),
     );
     let mut title = String::new();
     pulldown_cmark::html::push_html(
-        &mut compiled_body,
-        parser.by_ref().peeking_take_while(|event| {
-            if let pulldown_cmark::Event::Code(contents)
-            | pulldown_cmark::Event::Html(contents)
-            | pulldown_cmark::Event::Text(contents) = event
-            {
-                title.push_str(contents);
-            }
-            !matches!(
-                event,
-                pulldown_cmark::Event::End(pulldown_cmark::Tag::Heading(..))
-            )
-        }),
+        compiled_body,
+        events
+            .by_ref()
+            .peeking_take_while(|event| {
+                if let Event::Code(contents) = event {
+                    title.push_str(contents);
+                } else if let Event::Html(contents) = event {
+                    // Wiki links, citations and shortcodes show their labels
+                    title.push_str(&text_of_html(contents));
+                } else if let Event::Text(contents) = event {
+                    // The title is plain text, so the formulas stay as they were written
+                    title.push_str(&math.plain_text(contents));
+                }
+                !matches!(event, Event::End(Tag::Heading(..)))
+            })
+            .flat_map(&mut expand),
     );
-    let title: Arc<str> = title.into();
-    pulldown_cmark::html::push_html(&mut compiled_body, parser);
-    let file_info = fs::metadata(&path).unwrap();
+    pulldown_cmark::html::push_html(compiled_body, events.flat_map(expand));
+    title.into()
+}
+
+/// Pushes `- Author, creation date (last edit at modification date)`, and returns the modification
+/// time
+fn push_signature(
+    compiled_body: &mut String,
+    config: &SiteConfig,
+    markdown_path: &Path,
+) -> FileTime {
+    let file_info = fs::metadata(markdown_path).unwrap();
     let modification_time: FileTime = file_info.modified().unwrap().into();
-    {
-        let signature = if let Ok(creation_time) = file_info.created() {
+    let signature = file_info.created().map_or_else(
+        |_| {
+            format!(
+                r#"<p align="right"><em>- {}, {}</em></p>"#,
+                html_escape::encode_text(&config.author_name),
+                modification_time.format(&config.date_format)
+            )
+        },
+        |creation_time| {
             let creation_time: FileTime = creation_time.into();
             let mut signature = format!(
                 r#"<p align="right"><em>- {}, {}"#,
@@ -90,40 +842,124 @@ pub fn compile_article(path: &PathBuf, config: &Config) -> io::Result<CompiledAr
                 creation_time.format(&config.date_format)
             );
             if creation_time.date() != modification_time.date() {
-                signature.push_str(&format!(
+                let _ = write!(
+                    signature,
                     " (last edit at {})",
                     modification_time.format(&config.date_format)
-                ));
+                );
             }
             signature.push_str("</em></p>");
             signature
-        } else {
-            format!(
-                r#"<p align="right"><em>- {}, {}</em></p>"#,
-                html_escape::encode_text(&config.author_name),
-                modification_time.format(&config.date_format)
-            )
-        };
-        pulldown_cmark::html::push_html(
-            &mut compiled_body,
-            iter::once(pulldown_cmark::Event::Html(CowStr::Borrowed(&signature))),
-        );
-    }
+        },
+    );
+    pulldown_cmark::html::push_html(
+        compiled_body,
+        iter::once(pulldown_cmark::Event::Html(CowStr::Borrowed(&signature))),
+    );
+    modification_time
+}
+
+pub fn compile_article(
+    path: &Path,
+    config: &SiteConfig,
+    assets: &mut Assets,
+    others: &OtherArticles,
+) -> io::Result<CompiledArticleInfo> {
     let file_name: Arc<str> = path.file_name_arc_str();
+    // A page bundle is a directory with the article and the files that it shows
+    let bundle_directory = Some(path).filter(|path| path.is_dir());
+    let (markdown_path, base_name) = markdown_source(path);
+    let file_contents = fs::read_to_string(&markdown_path)?;
+    let (front_matter, file_contents, front_matter_error) = FrontMatter::extract(&file_contents);
+    let slug = article_slug(&file_name, &base_name, &front_matter);
+    let mut problems = Vec::new();
+    if let Some(error) = front_matter_error {
+        problems.push(Problem::InvalidFrontMatter(error.to_string()));
+    }
+    if file_contents.trim().is_empty() {
+        problems.push(Problem::EmptyArticle);
+    }
+    let extensions = config
+        .markdown_extensions
+        .with_overrides(&front_matter.markdown);
+    let mut dependencies = Vec::new();
+    let (file_contents, shortcodes, math) = extract_extensions(
+        file_contents,
+        &markdown_path,
+        config,
+        &extensions,
+        &mut dependencies,
+        &mut problems,
+    );
+    let mut citations = load_citations(&front_matter, &markdown_path, config, &mut dependencies);
+    let mut links = Links {
+        config,
+        assets,
+        slug: &slug,
+        bundle_directory,
+        others,
+        dependencies,
+        links_to: Vec::new(),
+        missing_wiki_links: Vec::new(),
+        problems,
+        link_checks: Vec::new(),
+        external_links: Vec::new(),
+    };
+    let parser = pulldown_cmark::Parser::new_ext(&file_contents, extensions.options());
+    let events = callouts::transform(map_events(
+        parser,
+        &mut links,
+        &math,
+        &shortcodes,
+        &mut citations,
+    ));
+    let Links {
+        dependencies,
+        links_to,
+        missing_wiki_links,
+        mut problems,
+        link_checks,
+        external_links,
+        ..
+    } = links;
+    let mut compiled_body = String::new();
+    let title = push_body(&mut compiled_body, events.into_iter(), &math);
+    problems.extend(citations.problems());
+    if let Some(bibliography) = citations.bibliography() {
+        compiled_body.push_str(&bibliography);
+    }
+    let modification_time = push_signature(&mut compiled_body, config, &markdown_path);
     let title = if title.is_empty() {
-        ArticleTitle::FromFileName(file_name.base_name())
+        ArticleTitle::FromFileName(base_name)
     } else {
         ArticleTitle::FromFirstHeading(title)
     };
     let compiled_body = ArticleTemplate {
         body: compiled_body,
         title: title.clone_contents(),
+        theme_style: config.theme_style(),
+        canonical_url: config.base_url.absolute(&slug),
+        linked_from: &others.linked_from,
+        base_url: &config.base_url,
     }
     .render()
     .unwrap();
     Ok(CompiledArticleInfo {
         title,
         file_name,
+        slug,
+        aliases: front_matter
+            .aliases
+            .iter()
+            .map(|alias| alias.trim_matches('/').into())
+            .collect(),
+        dependencies,
+        links_to,
+        missing_wiki_links,
+        problems,
+        link_checks,
+        anchors: anchors_of(&compiled_body),
+        external_links,
         body: compiled_body,
         modification_time,
     })
@@ -139,17 +975,33 @@ where
     author_name: &'index_template AuthorName,
     background_color_code: &'index_template str,
     title_color_code: &'index_template str,
+    theme_style: &'index_template str,
+    base_url: &'index_template BaseUrl,
+    canonical_url: Option<String>,
 }
 
-pub fn compile_index_variants(articles_list: &[IndexArticleInfo], config: &Config) -> Vec<String> {
-    let mut index_variants = Vec::with_capacity(config.index_page_colors.len());
-    for color in &config.index_page_colors[..] {
+pub fn compile_index_variants(
+    articles_list: &[IndexArticleInfo],
+    config: &SiteConfig,
+) -> Vec<String> {
+    let default_colors = [PageColors::default()];
+    let colors = if config.index_page_colors.is_empty() {
+        &default_colors[..]
+    } else {
+        &config.index_page_colors[..]
+    };
+    let mut index_variants = Vec::with_capacity(colors.len());
+    let theme_style = config.theme_style();
+    for color in colors {
         index_variants.push(
             IndexTemplate {
                 articles_list,
                 author_name: &config.author_name,
                 background_color_code: color.background(),
                 title_color_code: color.title(),
+                theme_style: &theme_style,
+                base_url: &config.base_url,
+                canonical_url: config.base_url.absolute(""),
             }
             .render()
             .unwrap(),
@@ -157,3 +1009,362 @@ pub fn compile_index_variants(articles_list: &[IndexArticleInfo], config: &Confi
     }
     index_variants
 }
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorTemplate<'error_template> {
+    status_code: u16,
+    reason: &'error_template str,
+    explanation: &'error_template str,
+    suggestions: &'error_template [IndexArticleInfo],
+    author_name: &'error_template str,
+    background_color_code: &'error_template str,
+    title_color_code: &'error_template str,
+    theme_style: &'error_template str,
+    base_url: &'error_template BaseUrl,
+}
+
+fn random_index_colors(config: &SiteConfig) -> PageColors {
+    config
+        .index_page_colors
+        .choose(&mut rand::thread_rng())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Error pages are compiled on every request, because they depend on the request. They take a
+/// random color pair of the index page
+pub fn compile_error_page(
+    status_code: u16,
+    reason: &str,
+    explanation: &str,
+    suggestions: &[IndexArticleInfo],
+    config: &SiteConfig,
+) -> String {
+    let colors = random_index_colors(config);
+    ErrorTemplate {
+        status_code,
+        reason,
+        explanation,
+        suggestions,
+        author_name: &config.author_name,
+        background_color_code: colors.background(),
+        title_color_code: colors.title(),
+        theme_style: &config.theme_style(),
+        base_url: &config.base_url,
+    }
+    .render()
+    .unwrap()
+}
+
+#[derive(Template)]
+#[template(path = "directory.html")]
+struct DirectoryTemplate<'directory_template> {
+    path: &'directory_template str,
+    /// Links and names
+    entries: Vec<(String, &'directory_template str)>,
+    index_link: String,
+    author_name: &'directory_template str,
+    background_color_code: &'directory_template str,
+    title_color_code: &'directory_template str,
+    theme_style: &'directory_template str,
+}
+
+/// Lists the `entries` of the directory at `path` (relative to the files directory). The parent
+/// directory is listed too, unless it's the root
+pub fn compile_directory_listing(path: &str, entries: &[String], config: &SiteConfig) -> String {
+    let path = path.trim_matches('/');
+    let directory_path = if path.is_empty() {
+        String::new()
+    } else {
+        format!("{path}/")
+    };
+    let mut listed_entries = Vec::with_capacity(entries.len() + 1);
+    if let Some((parent, _name)) = path.rsplit_once('/') {
+        listed_entries.push((config.base_url.link(&format!("{parent}/")), "../"));
+    } else if !path.is_empty() {
+        listed_entries.push((config.base_url.link(""), "../"));
+    }
+    for name in entries {
+        listed_entries.push((
+            config.base_url.link(&format!("{directory_path}{name}")),
+            name,
+        ));
+    }
+    let colors = random_index_colors(config);
+    DirectoryTemplate {
+        path: &format!("/{directory_path}"),
+        entries: listed_entries,
+        index_link: config.base_url.link(""),
+        author_name: &config.author_name,
+        background_color_code: colors.background(),
+        title_color_code: colors.title(),
+        theme_style: &config.theme_style(),
+    }
+    .render()
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::Path,
+        sync::{mpsc, Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::{article_slug, slug_from_base_name, ExtractBaseName};
+    use crate::{config::SiteConfig, front_matter::FrontMatter, website::Website};
+
+    /// Writes the files (relative to a new directory with `articles` and `files`). The images are
+    /// not resized
+    fn site(files: &[(&str, &[u8])]) -> (tempfile::TempDir, SiteConfig) {
+        let root = tempfile::tempdir().unwrap();
+        for directory in ["articles", "files"] {
+            fs::create_dir(root.path().join(directory)).unwrap();
+        }
+        for (path, contents) in files {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let mut config = SiteConfig::for_tests(root.path());
+        config.image_widths = Vec::new();
+        (root, config)
+    }
+
+    fn website(files: &[(&str, &[u8])]) -> (tempfile::TempDir, Website) {
+        let (root, config) = site(files);
+        let website = Website::new(Arc::new(Mutex::new(config)), None);
+        (root, website)
+    }
+
+    fn article(website: &Website, slug: &str) -> String {
+        website.get_article(slug).expect("The article is not there")
+    }
+
+    fn hash_of(path: &Path) -> String {
+        let mut assets = crate::assets::Assets::new(crate::images::ImageProcessor::new(None));
+        assets.hash_of(path).unwrap()
+    }
+
+    #[test]
+    fn derives_slugs_from_file_names() {
+        for (base_name, slug) in [
+            ("My First Post", "my-first-post"),
+            ("  C++ & Rust: 2024!  ", "c-rust-2024"),
+            ("Ünïcödé Straße", "ünïcödé-straße"),
+            ("---", "---"),
+        ] {
+            assert_eq!(&*slug_from_base_name(&base_name.into()), slug);
+        }
+        let file_name: Arc<str> = "My First Post.md".into();
+        let slug = |front_matter: &str| {
+            let (front_matter, _body, _error) = FrontMatter::extract(front_matter);
+            article_slug(&file_name, &file_name.base_name(), &front_matter)
+        };
+        assert_eq!(&*slug(""), "my-first-post");
+        assert_eq!(&*slug("---\nslug: /hello/\n---\n"), "hello");
+        // Slugs with slashes are ignored
+        assert_eq!(&*slug("---\nslug: a/b\n---\n"), "my-first-post");
+        assert_eq!(&*slug("---\nslug: /\n---\n"), "my-first-post");
+    }
+
+    #[test]
+    fn bundles_take_slugs_from_their_directories() {
+        let (_root, website) = website(&[
+            ("articles/My Trip/index.md", b"# Somewhere else"),
+            ("articles/Other Post.md", b"---\nslug: elsewhere\n---\n"),
+        ]);
+        assert_eq!(website.slug_of("My Trip").as_deref(), Some("my-trip"));
+        assert_eq!(
+            website.slug_of("Other Post.md").as_deref(),
+            Some("elsewhere")
+        );
+        assert!(website.get_article("other-post").is_none());
+    }
+
+    #[test]
+    fn fingerprints_percent_encoded_links() {
+        let (root, website) = website(&[
+            ("articles/post.md", b"[photo](/my%20photo.png)"),
+            ("files/my photo.png", b"not really a photo"),
+        ]);
+        let hash = hash_of(&root.path().join("files/my photo.png"));
+        let article = article(&website, "post");
+        assert!(
+            article.contains(&format!(r#"href="/files/my%20photo.{hash}.png""#)),
+            "{article}"
+        );
+    }
+
+    #[test]
+    fn finds_percent_encoded_bundle_files() {
+        let (_root, website) = website(&[
+            ("articles/trip/index.md", b"[map](./my%20map.txt)"),
+            ("articles/trip/my map.txt", b"not really a map"),
+        ]);
+        let article = article(&website, "trip");
+        assert!(
+            article.contains(r#"href="/trip/my%20map.txt""#),
+            "{}",
+            article
+        );
+    }
+
+    #[test]
+    fn resizes_percent_encoded_images() {
+        let (root, mut config) = site(&[("articles/post.md", b"![photo](/my%20photo.png)")]);
+        let image_path = root.path().join("files/my photo.png");
+        image::RgbImage::new(100, 50).save(&image_path).unwrap();
+        config.image_widths = vec![40];
+        let (finished_images, finished_images_receiver) = mpsc::channel();
+        let mut website = Website::new(Arc::new(Mutex::new(config)), Some(finished_images));
+        let finished = finished_images_receiver
+            .recv_timeout(Duration::from_mins(1))
+            .unwrap();
+        assert_eq!(finished, image_path);
+        website.dependency_changed(&[finished]);
+        let article = article(&website, "post");
+        assert!(article.contains("<picture>"), "{}", article);
+    }
+
+    #[test]
+    fn links_percent_encoded_markdown_files() {
+        let (_root, website) = website(&[
+            ("articles/post.md", b"[other](my%20other%20post.md)"),
+            ("articles/my other post.md", b"# Other"),
+        ]);
+        let slug = website.slug_of("my other post.md").unwrap();
+        let article = article(&website, "post");
+        assert!(article.contains(&format!(r#"href="/{slug}""#)), "{article}");
+        assert!(website.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn titles_show_labels_instead_of_html() {
+        let (_root, website) = website(&[
+            (
+                "articles/post.md",
+                b"# Notes on [[other|the other one]] & <em>more</em>",
+            ),
+            ("articles/other.md", b"# Other"),
+        ]);
+        let post = article(&website, "post");
+        assert!(
+            post.contains("<title>Notes on the other one &amp; more</title>"),
+            "{}",
+            post
+        );
+        let other = article(&website, "other");
+        assert!(
+            other.contains(">Notes on the other one &amp; more</a></li>"),
+            "{}",
+            other
+        );
+    }
+
+    #[test]
+    fn extensions_are_off_by_default() {
+        let source = "\"hi\" -- ok... $x$ {{< download src=\"a.pdf\" >}} ~~no~~";
+        let (_root, website) = website(&[
+            ("articles/old.md", source.as_bytes()),
+            (
+                "articles/new.md",
+                format!(
+                    "---\nmarkdown: {{ smart_punctuation: true, math: true, shortcodes: true, \
+                     strikethrough: false }}\n---\n{source}"
+                )
+                .as_bytes(),
+            ),
+        ]);
+        let old = article(&website, "old");
+        assert!(
+            old.contains(
+                "<p>&quot;hi&quot; -- ok... $x$ {{&lt; download src=&quot;a.pdf&quot; &gt;}} \
+                 <del>no</del></p>"
+            ),
+            "{old}"
+        );
+        let new = article(&website, "new");
+        assert!(new.contains("<p>“hi” – ok… <math"), "{new}");
+        assert!(new.contains(r#"<a class="download-button""#), "{new}");
+        assert!(new.contains("~~no~~</p>"), "{new}");
+    }
+
+    #[test]
+    fn formulas_stay_out_of_code_and_links() {
+        let (_root, website) = website(&[(
+            "articles/post.md",
+            "---\nmarkdown: { math: true }\n---\n$x$\n\n    let a = $a$;\n    let b = $b$;\n\n\
+             ```\n\u{E000}0\u{E001}\n```\n\n[q](https://x/?a=$1$b) <https://x/?a=$1$b>\n"
+                .as_bytes(),
+        )]);
+        let post = article(&website, "post");
+        assert_eq!(post.matches("<math").count(), 1, "{post}");
+        assert!(
+            post.contains("<pre><code>let a = $a$;\nlet b = $b$;\n</code></pre>"),
+            "{post}"
+        );
+        assert!(
+            post.contains("<pre><code>\u{E000}0\u{E001}\n</code></pre>"),
+            "{post}"
+        );
+        assert!(
+            post.contains(r#"<a href="https://x/?a=$1$b">q</a>"#),
+            "{post}"
+        );
+        assert!(
+            post.contains(r#"<a href="https://x/?a=$1$b">https://x/?a=$1$b</a>"#),
+            "{post}"
+        );
+    }
+
+    #[test]
+    fn included_files_are_not_articles() {
+        let (root, mut website) = website(&[
+            (
+                "articles/post.md",
+                b"---\nmarkdown: { includes: true }\n---\n\
+                  {{< include src=\"snippet.rs\" >}}\n\n{{< include src=\"notes.txt\" >}}",
+            ),
+            ("articles/snippet.rs", b"fn main() {}"),
+            ("articles/notes.txt", b"Some *notes*"),
+        ]);
+        let post = article(&website, "post");
+        assert!(post.contains("fn main() {}"), "{post}");
+        assert!(post.contains("Some *notes*"), "{post}");
+        let snippet_path = root.path().join("articles/snippet.rs");
+        fs::write(&snippet_path, "fn start() {}").unwrap();
+        website.article_path_changed(&snippet_path);
+        assert!(article(&website, "post").contains("fn start() {}"));
+        for slug in ["snippet", "snippet-rs", "notes", "notes-txt"] {
+            assert!(website.get_article(slug).is_none(), "{slug}");
+        }
+        assert_eq!(website.articles_count(), 1);
+        assert!(website.diagnostics().is_empty());
+    }
+
+    #[test]
+    fn finds_data_files_beside_flat_articles() {
+        let (_root, website) = website(&[
+            (
+                "articles/post.md",
+                b"---\nmarkdown: { shortcodes: true }\n---\n\
+                  {{< table src=\"results.csv\" sort=\"time\" >}}",
+            ),
+            ("articles/results.csv", b"name,time\nslow,20\nfast,9\n"),
+        ]);
+        let post = article(&website, "post");
+        assert!(
+            post.contains(r#"<td>fast</td><td style="text-align: right">9</td>"#),
+            "{post}"
+        );
+        assert!(post.find("fast") < post.find("slow"), "{post}");
+        // The data file isn't an article
+        assert!(website.get_article("results").is_none());
+        assert_eq!(website.articles_count(), 1);
+        assert!(website.diagnostics().is_empty());
+    }
+}
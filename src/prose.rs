@@ -0,0 +1,183 @@
+/// Length of the run of `character` at the start of `text`
+pub fn run_length(text: &str, character: char) -> usize {
+    text.chars().take_while(|&other| other == character).count()
+}
+
+/// The opening fence of a code block (the character and the length of the run)
+fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    ['`', '~'].into_iter().find_map(|character| {
+        let length = run_length(trimmed, character);
+        (length >= 3).then_some((character, length))
+    })
+}
+
+fn is_closing_fence(line: &str, (character, length): (char, usize)) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    line.len() - trimmed.len() <= 3
+        && run_length(trimmed, character) >= length
+        && trimmed.trim_start_matches(character).trim().is_empty()
+}
+
+/// `[label]: destination "title"`, whose destination is left as it is (like the ones of the links).
+/// The footnotes (`[^1]: text`) are prose
+fn is_reference_definition(line: &str) -> bool {
+    let trimmed = line.trim_start_matches(' ');
+    line.len() - trimmed.len() <= 3
+        && trimmed.starts_with('[')
+        && !trimmed.starts_with("[^")
+        && trimmed
+            .find("]:")
+            .is_some_and(|end| end > 1 && !trimmed[1..end].contains(['[', ']']))
+}
+
+/// Length of the autolink (`<https://example.com>` or `<me@example.com>`) at the start of `text`
+fn autolink_length(text: &str) -> Option<usize> {
+    let inner = &text[1..text.find('>')?];
+    let is_uri = inner.split_once(':').is_some_and(|(scheme, _rest)| {
+        (2..=32).contains(&scheme.len())
+            && scheme.starts_with(|character: char| character.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || "+.-".contains(character))
+    });
+    let is_link = (is_uri || inner.contains('@'))
+        && !inner.contains(|character: char| character.is_whitespace() || character == '<');
+    is_link.then_some(inner.len() + 2)
+}
+
+/// Length of the destination and the title of an inline link (`(url "title")`) at the start of
+/// `text`, which is right after the `]` of the link text
+fn link_destination_length(text: &str) -> Option<usize> {
+    if !text.starts_with('(') {
+        return None;
+    }
+    let mut depth = 0;
+    let mut index = 1;
+    while index < text.len() {
+        let rest = &text[index..];
+        let character = rest.chars().next().unwrap();
+        match character {
+            '\\' => index += 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            '(' => {
+                depth += 1;
+                index += 1;
+            }
+            ')' if depth == 0 => return Some(index + 1),
+            ')' => {
+                depth -= 1;
+                index += 1;
+            }
+            character => index += character.len_utf8(),
+        }
+    }
+    None
+}
+
+/// Index of the first unescaped `delimiter` in `text`
+pub fn find_unescaped(text: &str, delimiter: &str) -> Option<usize> {
+    let mut index = 0;
+    while index < text.len() {
+        let rest = &text[index..];
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            index += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with(delimiter) {
+            return Some(index);
+        } else {
+            index += rest.chars().next().unwrap().len_utf8();
+        }
+    }
+    None
+}
+
+/// Calls `replace` with the rest of the paragraph at every position outside of the code blocks,
+/// the code spans, the escapes, the autolinks and the link destinations. It returns the length of
+/// the replaced source and the replacement, or `None` to leave the source as it is
+pub fn replace(markdown: &str, mut replace: impl FnMut(&str) -> Option<(usize, String)>) -> String {
+    let mut output = String::with_capacity(markdown.len());
+    let mut text = String::new();
+    let mut fence = None;
+    let mut is_previous_line_blank = true;
+    let mut is_in_indented_block = false;
+    for line in markdown.split_inclusive('\n') {
+        let is_code = if let Some(opened_fence) = fence {
+            if is_closing_fence(line, opened_fence) {
+                fence = None;
+            }
+            true
+        } else if let Some(opened_fence) = opening_fence(line) {
+            fence = Some(opened_fence);
+            is_in_indented_block = false;
+            true
+        } else {
+            let is_blank = line.trim().is_empty();
+            let is_indented = !is_blank && (line.starts_with("    ") || line.starts_with('\t'));
+            // An indented code block starts after a blank line, and goes on (through the blank
+            // lines) until a line with less indentation
+            is_in_indented_block = if is_in_indented_block {
+                is_indented || is_blank
+            } else {
+                is_indented && is_previous_line_blank
+            };
+            is_in_indented_block
+        };
+        let is_verbatim = is_code || (text.is_empty() && is_reference_definition(line));
+        if is_verbatim || line.trim().is_empty() {
+            replace_inline(&text, &mut output, &mut replace);
+            text.clear();
+            output.push_str(line);
+        } else {
+            text.push_str(line);
+        }
+        is_previous_line_blank = line.trim().is_empty() && !is_code;
+    }
+    replace_inline(&text, &mut output, &mut replace);
+    output
+}
+
+/// Replacements in a paragraph, skipping the code spans, the escaped characters, the autolinks and
+/// the link destinations
+fn replace_inline(
+    text: &str,
+    output: &mut String,
+    replace: &mut impl FnMut(&str) -> Option<(usize, String)>,
+) {
+    let mut index = 0;
+    while index < text.len() {
+        let rest = &text[index..];
+        if let Some((length, replacement)) = replace(rest) {
+            output.push_str(&replacement);
+            index += length;
+            continue;
+        }
+        let character = rest.chars().next().unwrap();
+        let consumed = match character {
+            '\\' => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            '`' => {
+                let length = run_length(rest, '`');
+                let mut end = length;
+                // The code span ends with a run of the same length
+                loop {
+                    match rest[end..].find('`') {
+                        Some(start) => {
+                            let closing_length = run_length(&rest[end + start..], '`');
+                            end += start + closing_length;
+                            if closing_length == length {
+                                break end;
+                            }
+                        }
+                        None => break length,
+                    }
+                }
+            }
+            '<' => autolink_length(rest).unwrap_or(1),
+            ']' => 1 + link_destination_length(&rest[1..]).unwrap_or(0),
+            character => character.len_utf8(),
+        };
+        output.push_str(&rest[..consumed]);
+        index += consumed;
+    }
+}
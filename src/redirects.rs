@@ -0,0 +1,306 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::{error, info};
+
+/// Old paths of a site and where they lead now. Renamed and deleted articles are recorded here
+/// automatically, and redirects can be added by hand too. Kept in
+/// `<articles directory>.redirects.json`, as a JSON map:
+/// `{ "old-slug": "new-slug", "elsewhere": "https://example.com/", "deleted-slug": null }`
+pub struct Redirects {
+    path: PathBuf,
+    /// `None` means that the page was deleted on purpose
+    map: BTreeMap<String, Option<String>>,
+    /// To notice the manual edits of the file
+    modification_time: Option<SystemTime>,
+    /// The changes that couldn't be written yet
+    is_changed: bool,
+}
+
+pub enum Resolution {
+    /// Site-relative path or an absolute URL
+    Target(String),
+    /// The redirects lead to each other. The paths of the loop are listed
+    Cycle(Vec<String>),
+    /// The page was deleted
+    Gone,
+    NotFound,
+}
+
+/// `a/b/`, `/a/b` and `a/b` are the same path for the redirects
+fn normalize(path: &str) -> &str {
+    path.trim_matches('/')
+}
+
+fn is_absolute_url(target: &str) -> bool {
+    target.contains("://")
+}
+
+impl Redirects {
+    /// Path of the redirects file of an articles directory
+    pub fn path_for(articles_directory: &Path) -> PathBuf {
+        let mut file_name = articles_directory
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        file_name.push(".redirects.json");
+        articles_directory.with_file_name(file_name)
+    }
+
+    /// Reads the redirects of an articles directory. A missing file means there are no redirects
+    pub fn load(articles_directory: &Path) -> Self {
+        let mut instance = Self {
+            path: Self::path_for(articles_directory),
+            map: BTreeMap::new(),
+            modification_time: None,
+            is_changed: false,
+        };
+        instance.reload();
+        instance
+    }
+
+    fn reload(&mut self) {
+        self.is_changed = false;
+        self.modification_time = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        self.map = match fs::read_to_string(&self.path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|error| {
+                error!(
+                    "Redirects file `{:?}` cannot be parsed, ignoring it! Details: {}",
+                    self.path, error
+                );
+                BTreeMap::new()
+            }),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => {
+                error!(
+                    "Redirects file `{:?}` cannot be read, ignoring it! Details: {}",
+                    self.path, error
+                );
+                BTreeMap::new()
+            }
+        };
+    }
+
+    /// Re-reads the file if it was edited since the last read
+    pub fn refresh(&mut self) {
+        let modification_time = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if modification_time != self.modification_time {
+            self.reload();
+        }
+    }
+
+    /// # Errors
+    /// Returns an error if the file cannot be written.
+    pub fn save(&mut self) -> io::Result<()> {
+        fs::write(
+            &self.path,
+            serde_json::to_string_pretty(&self.map).unwrap() + "\n",
+        )?;
+        self.modification_time = fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        self.is_changed = false;
+        Ok(())
+    }
+
+    /// Writes the changes that failed to be written before (on shutdown, for example)
+    pub fn flush(&mut self) {
+        if self.is_changed {
+            self.save_or_complain();
+        }
+    }
+
+    /// Remembers that `from` is now `to`, and saves the file
+    pub fn record(&mut self, from: &str, to: &str) {
+        let (from, to) = (normalize(from), normalize(to));
+        if from == to {
+            return;
+        }
+        self.refresh();
+        info!("Redirecting `{}` to `{}` from now on", from, to);
+        self.map.insert(from.to_owned(), Some(to.to_owned()));
+        // The target is a live page again, so it shouldn't lead anywhere else
+        self.map.remove(to);
+        self.save_or_complain();
+    }
+
+    /// Remembers that the page at `path` was deleted, and saves the file
+    pub fn record_removal(&mut self, path: &str) {
+        let path = normalize(path);
+        self.refresh();
+        if !self.map.contains_key(path) {
+            info!("`{}` is remembered as deleted", path);
+            self.map.insert(path.to_owned(), None);
+            self.save_or_complain();
+        }
+    }
+
+    /// Forgets that the page at `path` was deleted (because it is back)
+    pub fn forget_removal(&mut self, path: &str) {
+        let path = normalize(path);
+        if matches!(self.map.get(path), Some(None)) {
+            self.map.remove(path);
+            self.save_or_complain();
+        }
+    }
+
+    fn save_or_complain(&mut self) {
+        self.is_changed = true;
+        self.save().unwrap_or_else(|error| {
+            error!(
+                "Redirects file `{:?}` cannot be written! Details: {}",
+                self.path, error
+            );
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Option<String>)> {
+        self.map.iter()
+    }
+
+    pub fn remove(&mut self, from: &str) {
+        self.map.remove(from);
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Follows the chain of redirects from `from` until a path that `is_live` or is not redirected
+    /// anywhere, or until an absolute URL
+    pub fn resolve(&self, from: &str, is_live: impl Fn(&str) -> bool) -> Resolution {
+        let mut current = normalize(from);
+        let mut visited = HashSet::new();
+        let mut chain = Vec::new();
+        loop {
+            let Some(target) = self.map.get(current) else {
+                return if chain.is_empty() {
+                    Resolution::NotFound
+                } else {
+                    Resolution::Target(current.to_owned())
+                };
+            };
+            if !visited.insert(current) {
+                return Resolution::Cycle(chain);
+            }
+            chain.push(current.to_owned());
+            let Some(target) = target else {
+                return Resolution::Gone;
+            };
+            if is_absolute_url(target) {
+                return Resolution::Target(target.clone());
+            }
+            current = normalize(target);
+            if is_live(current) {
+                return Resolution::Target(current.to_owned());
+            }
+        }
+    }
+}
+
+/// What happens to a request that matches a redirect
+pub enum Status {
+    /// Leads to a page (or to another site)
+    Working(String),
+    /// There's a page at the old path again, so the redirect is never used
+    Shadowed,
+    /// Leads to itself through the other redirects
+    Cycle(Vec<String>),
+    /// Leads to a page that doesn't exist
+    Dead(String),
+    /// Tells that the page was deleted
+    Gone,
+}
+
+impl Status {
+    pub const fn is_working(&self) -> bool {
+        matches!(self, Self::Working(_) | Self::Gone)
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Working(target) => write!(f, "leads to `{target}`"),
+            Self::Shadowed => write!(f, "shadowed by a page at the same path"),
+            Self::Cycle(chain) => write!(f, "part of a cycle: {}", chain.join(" -> ")),
+            Self::Dead(target) => write!(f, "leads to `{target}`, which doesn't exist"),
+            Self::Gone => write!(f, "deleted"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{Redirects, Resolution};
+
+    /// Redirects of `articles` in a new directory, with the file written as given
+    fn redirects(json: &str) -> (tempfile::TempDir, Redirects) {
+        let root = tempfile::tempdir().unwrap();
+        let articles_directory = root.path().join("articles");
+        fs::write(Redirects::path_for(&articles_directory), json).unwrap();
+        let redirects = Redirects::load(&articles_directory);
+        (root, redirects)
+    }
+
+    fn resolve(redirects: &Redirects, from: &str, live: &[&str]) -> String {
+        match redirects.resolve(from, |path| live.contains(&path)) {
+            Resolution::Target(target) => format!("-> {target}"),
+            Resolution::Cycle(chain) => format!("cycle {}", chain.join(" ")),
+            Resolution::Gone => "gone".to_owned(),
+            Resolution::NotFound => "not found".to_owned(),
+        }
+    }
+
+    #[test]
+    fn follows_chains() {
+        let (_root, redirects) = redirects(
+            r#"{ "a": "b", "b": "/c/", "c": "d", "far": "https://example.com/", "old": null,
+                "to-old": "old" }"#,
+        );
+        assert_eq!(resolve(&redirects, "/a/", &["d"]), "-> d");
+        // A live page stops the chain
+        assert_eq!(resolve(&redirects, "a", &["c"]), "-> c");
+        // The end of the chain is where the request goes, even if nothing is there
+        assert_eq!(resolve(&redirects, "a", &[]), "-> d");
+        assert_eq!(resolve(&redirects, "far", &[]), "-> https://example.com/");
+        assert_eq!(resolve(&redirects, "old", &[]), "gone");
+        assert_eq!(resolve(&redirects, "to-old", &[]), "gone");
+        assert_eq!(resolve(&redirects, "d", &[]), "not found");
+    }
+
+    #[test]
+    fn finds_cycles() {
+        let (_root, redirects) = redirects(r#"{ "a": "b", "b": "c", "c": "a", "d": "d" }"#);
+        assert_eq!(resolve(&redirects, "b", &[]), "cycle b c a");
+        assert_eq!(resolve(&redirects, "d", &[]), "cycle d");
+        assert_eq!(resolve(&redirects, "a", &["c"]), "-> c");
+    }
+
+    #[test]
+    fn records_and_saves() {
+        let (root, mut redirects) = redirects(r#"{ "new": "elsewhere" }"#);
+        redirects.record("/old/", "new");
+        redirects.record("same", "same");
+        redirects.record_removal("gone");
+        let saved = fs::read_to_string(redirects.path()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&saved).unwrap(),
+            serde_json::json!({ "old": "new", "gone": null })
+        );
+        redirects.forget_removal("gone");
+        let reloaded = Redirects::load(&root.path().join("articles"));
+        assert_eq!(resolve(&reloaded, "old", &[]), "-> new");
+        assert_eq!(resolve(&reloaded, "gone", &[]), "not found");
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Mutex;
+
+use actix_web::{
+    dev::ServiceResponse,
+    http::{header, StatusCode},
+    middleware::ErrorHandlerResponse,
+    web, HttpResponse,
+};
+
+use crate::{page_compilers::compile_error_page, sites::Sites, website::Website};
+
+/// How many similar articles are suggested on the "not found" page
+const SUGGESTIONS_COUNT: usize = 3;
+
+/// How long to wait before retrying the request that came while the articles were compiling
+const WARMING_UP_RETRY_AFTER_IN_SECONDS: u32 = 5;
+
+pub enum ErrorPage {
+    NotFound,
+    /// The article was deleted
+    Gone,
+    InternalError,
+    /// The articles are still being compiled
+    WarmingUp,
+}
+
+impl ErrorPage {
+    const fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Gone => StatusCode::GONE,
+            Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::WarmingUp => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    const fn explanation(&self) -> &'static str {
+        match self {
+            Self::NotFound => "Sorry, the file you requested isn't found!",
+            Self::Gone => "Sorry, the article you requested was deleted!",
+            Self::InternalError => "Sorry, something went wrong on our side!",
+            Self::WarmingUp => "The blog is starting up, please try again in a few seconds!",
+        }
+    }
+
+    /// Renders the page with the colors and the theme of the website. `path` is the requested
+    /// path relative to the site's root, which is used to suggest the similar articles
+    pub fn render(&self, website: &Website, path: &str) -> String {
+        let suggestions = if matches!(self, Self::NotFound) {
+            website.suggestions(path, SUGGESTIONS_COUNT)
+        } else {
+            Vec::new()
+        };
+        let status_code = self.status_code();
+        compile_error_page(
+            status_code.as_u16(),
+            status_code.canonical_reason().unwrap_or_default(),
+            self.explanation(),
+            &suggestions,
+            &website.config().lock().unwrap(),
+        )
+    }
+
+    pub fn respond(&self, website: &Website, path: &str) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        response.content_type("text/html; charset=utf-8");
+        if matches!(self, Self::WarmingUp) {
+            response.insert_header((header::RETRY_AFTER, WARMING_UP_RETRY_AFTER_IN_SECONDS));
+        }
+        response.body(self.render(website, path))
+    }
+}
+
+/// Replaces the bodies of the internal errors that come from actix with the themed page
+#[allow(clippy::unnecessary_wraps)] // That's the signature of the error handlers
+pub fn internal_error<B>(
+    response: ServiceResponse<B>,
+) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    let website = response
+        .request()
+        .app_data::<web::Data<Mutex<Sites>>>()
+        .and_then(|sites| sites.lock().ok())
+        .map(|sites| sites.website_for(response.request()));
+    let Some(website) = website else {
+        return Ok(ErrorHandlerResponse::Response(
+            response.map_into_left_body(),
+        ));
+    };
+    let body = ErrorPage::InternalError.render(&website.lock().unwrap(), "");
+    let (request, response) = response.into_parts();
+    let mut response = response.set_body(body).map_into_boxed_body();
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("text/html; charset=utf-8"),
+    );
+    Ok(ErrorHandlerResponse::Response(
+        ServiceResponse::new(request, response).map_into_right_body(),
+    ))
+}
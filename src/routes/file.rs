@@ -1,14 +1,25 @@
-use std::sync::{Arc, Mutex};
+use std::sync::Mutex;
 
 use actix_files::NamedFile;
-use actix_web::{body::BoxBody, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{body::BoxBody, http::header, HttpRequest, HttpResponse, Responder};
 
-use crate::website::Website;
+use super::ErrorPage;
+use crate::{
+    assets::{fingerprinted_path, parse_fingerprinted_path},
+    base_url::BaseUrl,
+    files::Lookup,
+    images::{cache_directory_for, parse_resized_path},
+    page_compilers::compile_directory_listing,
+    redirects::Resolution,
+    website::Website,
+};
 
 #[allow(clippy::module_name_repetitions)]
 #[allow(clippy::large_enum_variant)]
 pub enum FileOrText {
     File(NamedFile),
+    /// A fingerprinted file, its contents never change
+    ImmutableFile(NamedFile),
     Text(HttpResponse),
 }
 
@@ -18,35 +29,133 @@ impl Responder for FileOrText {
     fn respond_to(self, req: &HttpRequest) -> HttpResponse<Self::Body> {
         match self {
             Self::File(file) => file.into_response(req),
+            Self::ImmutableFile(file) => {
+                let mut response = file.into_response(req);
+                response.headers_mut().insert(
+                    header::CACHE_CONTROL,
+                    header::HeaderValue::from_static("public, max-age=31536000, immutable"),
+                );
+                response
+            }
             Self::Text(text) => text,
         }
     }
 }
 
-#[allow(clippy::unused_async)]
-pub async fn file(
-    website: web::Data<Mutex<Website>>,
-    path_arguments: web::Path<String>,
-) -> FileOrText {
+/// `files/images/cat.3f9a1c0b2d.png` is served if the hash is the current one, and redirected to
+/// the current fingerprinted path otherwise (the page that links to it is probably cached)
+fn fingerprinted_file(website: &mut Website, name: &str, base_url: &BaseUrl) -> Option<FileOrText> {
+    let (relative_path, hash) = parse_fingerprinted_path(name)?;
+    let lookup = {
+        let config = website.config().clone();
+        let config = config.lock().unwrap();
+        if !config.fingerprint_files {
+            return None;
+        }
+        config.files_policy().lookup(&relative_path)
+    };
+    let Lookup::File(path) = lookup else {
+        return None;
+    };
+    let current_hash = website.asset_hash(&path)?;
+    if current_hash == hash {
+        NamedFile::open(path).ok().map(FileOrText::ImmutableFile)
+    } else {
+        Some(FileOrText::Text(
+            HttpResponse::Found()
+                .insert_header((
+                    header::LOCATION,
+                    base_url.link(&fingerprinted_path(&relative_path, &current_hash)),
+                ))
+                .finish(),
+        ))
+    }
+}
+
+/// `resized/3f9a1c0b2d.480.webp` is served from the cache directory of the resized images
+fn resized_image(website: &Website, name: &str) -> Option<FileOrText> {
+    let file_name = parse_resized_path(name)?;
+    let path =
+        cache_directory_for(&website.config().lock().unwrap().files_directory).join(file_name);
+    NamedFile::open(path).ok().map(FileOrText::ImmutableFile)
+}
+
+/// Articles are looked up by their slugs first, then the fingerprinted files, the resized images
+/// and the files of the page bundles, then the files directory, then the article file names
+/// (which are redirected to the slugs), and then the aliases and the redirects file. The redirects
+/// lead to `base_url`, which is the forwarded prefix if the proxy sent one
+// The guards are used until the end (by the listing and the error pages), which the lint misses
+#[allow(clippy::significant_drop_tightening)]
+pub fn file(website: &Mutex<Website>, name: &str, base_url: &BaseUrl) -> FileOrText {
     use FileOrText::{File, Text};
-    let file_name: Arc<str> = path_arguments.into_inner().into();
-    let article = website.lock().unwrap().get_article(&file_name);
-    match article {
-        Some(article) => Text(HttpResponse::Ok().body(article)),
-        None => match NamedFile::open(
-            website
-                .lock()
-                .unwrap()
-                .config()
-                .lock()
-                .unwrap()
-                .files_directory
-                .join(&file_name[..]),
-        ) {
-            Ok(file) => File(file),
-            Err(_) => {
-                Text(HttpResponse::NotFound().body("Sorry, the file you requested isn't found!"))
+    let mut website = website.lock().unwrap();
+    if let Some(article) = website.get_article(name) {
+        return Text(HttpResponse::Ok().body(article));
+    }
+    if let Some(file) = fingerprinted_file(&mut website, name, base_url) {
+        return file;
+    }
+    if let Some(image) = resized_image(&website, name) {
+        return image;
+    }
+    if let Some((slug, relative_path)) = name.split_once('/') {
+        if let Lookup::File(path) = website.bundle_file(slug, relative_path) {
+            if let Ok(file) = NamedFile::open(path) {
+                return File(file);
             }
+        }
+    }
+    let config = website.config().clone();
+    {
+        let config = config.lock().unwrap();
+        match config.files_policy().lookup(name) {
+            Lookup::File(path) => {
+                if let Ok(file) = NamedFile::open(path) {
+                    return File(file);
+                }
+            }
+            Lookup::Directory(path) if config.directory_listing => {
+                let entries = config.files_policy().list(&path, name);
+                return Text(
+                    HttpResponse::Ok()
+                        .content_type("text/html; charset=utf-8")
+                        .body(compile_directory_listing(name, &entries, &config)),
+                );
+            }
+            Lookup::Directory(_) | Lookup::NotFound => (),
+        }
+    }
+    let resolution = website.slug_of(name).map_or_else(
+        || match (website.resolve_redirect(name), name.split_once('/')) {
+            // A file of a page bundle that was renamed
+            (Resolution::NotFound, Some((old_slug, relative_path))) => {
+                match website.resolve_redirect(old_slug) {
+                    Resolution::Target(target) if !target.contains("://") => {
+                        Resolution::Target(format!("{target}/{relative_path}"))
+                    }
+                    _ => Resolution::NotFound,
+                }
+            }
+            (resolution, _) => resolution,
         },
+        |slug| Resolution::Target(slug.to_string()),
+    );
+    match resolution {
+        Resolution::Target(target) => {
+            let location = if target.contains("://") {
+                target
+            } else {
+                base_url.link(&target)
+            };
+            Text(
+                HttpResponse::MovedPermanently()
+                    .insert_header((header::LOCATION, location))
+                    .finish(),
+            )
+        }
+        Resolution::Gone => Text(ErrorPage::Gone.respond(&website, name)),
+        Resolution::NotFound | Resolution::Cycle(_) => {
+            Text(ErrorPage::NotFound.respond(&website, name))
+        }
     }
 }
@@ -1,10 +1,10 @@
 use std::sync::Mutex;
 
-use actix_web::{web, HttpResponse};
+use actix_web::HttpResponse;
 
 use crate::website::Website;
 
-#[allow(clippy::unused_async)]
-pub async fn index(website: web::Data<Mutex<Website>>) -> HttpResponse {
-    HttpResponse::Ok().body(website.lock().unwrap().get_index_page().clone())
+pub fn index(website: &Mutex<Website>) -> HttpResponse {
+    let index_page = website.lock().unwrap().get_index_page().clone();
+    HttpResponse::Ok().body(index_page)
 }
@@ -1,4 +1,232 @@
+use std::sync::Mutex;
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+
+use crate::sites::Sites;
+
 mod index;
 pub use index::index;
 mod file;
-pub use file::file;
+pub use file::{file, FileOrText};
+mod error;
+pub use error::{internal_error, ErrorPage};
+
+/// Set by proxies that serve the site under a subpath and strip that subpath from the requests
+const FORWARDED_PREFIX_HEADER: &str = "X-Forwarded-Prefix";
+
+/// Every request comes here first, so the path of the site's `base_url` is stripped in one place
+#[allow(clippy::unused_async, clippy::future_not_send)] // Actix keeps the handlers on their threads
+pub async fn page(sites: web::Data<Mutex<Sites>>, request: HttpRequest) -> FileOrText {
+    let website = sites.lock().unwrap().website_for(&request);
+    // Decoded by actix
+    let request_path = format!("/{}", request.match_info().query("path"));
+    let (site_path, base_url) = {
+        let config = {
+            let website = website.lock().unwrap();
+            if website.is_warming_up() {
+                return FileOrText::Text(ErrorPage::WarmingUp.respond(&website, ""));
+            }
+            website.config().clone()
+        };
+        let config = config.lock().unwrap();
+        let forwarded_prefix = request
+            .headers()
+            .get(FORWARDED_PREFIX_HEADER)
+            .filter(|_| config.trust_forwarded_prefix);
+        if let Some(forwarded_prefix) = forwarded_prefix {
+            let base_url = forwarded_prefix
+                .to_str()
+                .map_err(|error| error.to_string())
+                .and_then(|prefix| config.base_url.with_forwarded_prefix(prefix));
+            match base_url {
+                Ok(base_url) => (Some(request_path[1..].to_owned()), base_url),
+                Err(error) => {
+                    return FileOrText::Text(HttpResponse::BadRequest().body(format!(
+                        "Invalid `{FORWARDED_PREFIX_HEADER}` header: {error}"
+                    )))
+                }
+            }
+        } else if format!("{request_path}/") == config.base_url.path_prefix() {
+            // `/blog` instead of `/blog/`: relative links wouldn't work without the slash
+            return FileOrText::Text(
+                HttpResponse::MovedPermanently()
+                    .insert_header((header::LOCATION, config.base_url.link("")))
+                    .finish(),
+            );
+        } else {
+            let site_path = config
+                .base_url
+                .strip_prefix(&request_path)
+                .map(ToOwned::to_owned);
+            (site_path, config.base_url.clone())
+        }
+    };
+    match site_path.as_deref() {
+        Some("") => FileOrText::Text(index(&website)),
+        Some(file_name) => file(&website, file_name, &base_url),
+        None => {
+            FileOrText::Text(ErrorPage::NotFound.respond(&website.lock().unwrap(), &request_path))
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fs,
+        os::unix::fs::symlink,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use actix_web::{
+        http::{header, StatusCode},
+        test, web, App,
+    };
+
+    use crate::{config::SiteConfig, sites::Sites};
+
+    /// A site with the articles, `files/cat.txt` and `files/.env`, and a symbolic link to
+    /// `outside/secret.txt`. Waits until it warms up
+    fn sites(root: &tempfile::TempDir, articles: &[(&str, &str)]) -> Arc<Mutex<Sites>> {
+        let files = root.path().join("files");
+        let outside = root.path().join("outside");
+        fs::create_dir_all(root.path().join("articles")).unwrap();
+        for (file_name, contents) in articles {
+            fs::write(root.path().join("articles").join(file_name), contents).unwrap();
+        }
+        fs::create_dir_all(&files).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(files.join("cat.txt"), "cat").unwrap();
+        fs::write(files.join(".env"), "hidden").unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        symlink(outside.join("secret.txt"), files.join("link.txt")).unwrap();
+        let config = Arc::new(Mutex::new(SiteConfig::for_tests(root.path())));
+        let mut sites = Sites::empty();
+        sites.add("main".to_owned(), config.clone()).unwrap();
+        sites.reindex_hosts(&BTreeMap::from([("main".to_owned(), config)]), "main");
+        let (_name, website) = sites.websites().next().unwrap();
+        while website.lock().unwrap().is_warming_up() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        Arc::new(Mutex::new(sites))
+    }
+
+    #[actix_web::test]
+    async fn page_doesnt_leave_files_directory() {
+        let root = tempfile::tempdir().unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(sites(&root, &[])))
+                .route("/{path:.*}", web::get().to(super::page)),
+        )
+        .await;
+        let request = test::TestRequest::get().uri("/cat.txt").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(test::read_body(response).await, "cat");
+        for uri in [
+            "/../outside/secret.txt",
+            "/%2e%2e/outside/secret.txt",
+            "/%2E%2E/outside/secret.txt",
+            "/..%2Foutside%2Fsecret.txt",
+            "/%2e%2e%2foutside%2fsecret.txt",
+            "/..%5Coutside%5Csecret.txt",
+            "/%5C..%5Coutside%5Csecret.txt",
+            "/cat.txt%00",
+            "/cat.txt%00.png",
+            "/.env",
+            "/%2eenv",
+            "/link.txt",
+        ] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{uri}");
+            let body = test::read_body(response).await;
+            assert!(body != "secret" && body != "hidden", "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn deleted_articles_are_gone_and_missing_ones_get_suggestions() {
+        let root = tempfile::tempdir().unwrap();
+        let sites = sites(
+            &root,
+            &[("My Trip.md", "# My trip"), ("Old Post.md", "# Old post")],
+        );
+        let old_post = root.path().join("articles/Old Post.md");
+        fs::remove_file(&old_post).unwrap();
+        for (_name, website) in sites.lock().unwrap().websites() {
+            website.lock().unwrap().article_path_changed(&old_post);
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(sites))
+                .route("/{path:.*}", web::get().to(super::page)),
+        )
+        .await;
+        let get = |uri: &str| test::TestRequest::get().uri(uri).to_request();
+        let response = test::call_service(&app, get("/old-post")).await;
+        assert_eq!(response.status(), StatusCode::GONE);
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body.contains("was deleted"), "{body}");
+        assert!(!body.contains("Maybe you were looking for"), "{body}");
+        let response = test::call_service(&app, get("/my-trp")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body.contains("Maybe you were looking for"), "{body}");
+        assert!(
+            body.contains(r#"<li><a href="/my-trip">My trip</a></li>"#),
+            "{body}"
+        );
+        let response = test::call_service(&app, get("/zzz")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(!body.contains("Maybe you were looking for"), "{body}");
+    }
+
+    #[actix_web::test]
+    async fn redirects_lead_to_the_forwarded_prefix() {
+        let root = tempfile::tempdir().unwrap();
+        let sites = sites(&root, &[("My Trip.md", "# My trip")]);
+        for (_name, website) in sites.lock().unwrap().websites() {
+            let config = website.lock().unwrap().config().clone();
+            config.lock().unwrap().trust_forwarded_prefix = true;
+        }
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::from(sites))
+                .route("/{path:.*}", web::get().to(super::page)),
+        )
+        .await;
+        for (prefix, status, location) in [
+            (None, StatusCode::MOVED_PERMANENTLY, Some("/my-trip")),
+            (Some(""), StatusCode::MOVED_PERMANENTLY, Some("/my-trip")),
+            (
+                Some("/proxied"),
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/proxied/my-trip"),
+            ),
+            (
+                Some("proxied//blog/"),
+                StatusCode::MOVED_PERMANENTLY,
+                Some("/proxied/blog/my-trip"),
+            ),
+            (Some("/proxied/../admin"), StatusCode::BAD_REQUEST, None),
+        ] {
+            let mut request = test::TestRequest::get().uri("/My%20Trip.md");
+            if let Some(prefix) = prefix {
+                request = request.insert_header((super::FORWARDED_PREFIX_HEADER, prefix));
+            }
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), status, "{prefix:?}");
+            let actual_location = response
+                .headers()
+                .get(header::LOCATION)
+                .map(|location| location.to_str().unwrap());
+            assert_eq!(actual_location, location, "{prefix:?}");
+        }
+    }
+}
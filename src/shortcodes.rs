@@ -0,0 +1,654 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{data_tables, diagnostics::Problem, prose};
+
+/// The shortcodes are replaced with `\u{E002}<index>\u{E003}` before the Markdown is parsed (like
+/// the formulas), so their arguments aren't touched by the emphasis and the smart punctuation
+const PLACEHOLDER_START: char = '\u{E002}';
+const PLACEHOLDER_END: char = '\u{E003}';
+
+/// Directory of the theme with the templates of the user-defined shortcodes
+const TEMPLATES_DIRECTORY: &str = "shortcodes";
+
+pub type Arguments = BTreeMap<String, String>;
+
+/// What the shortcodes need from the article they are in
+pub trait Context {
+    fn theme_directory(&self) -> Option<&Path>;
+    /// The link as it is served (fingerprinted, leading to the page bundle and so on)
+    fn link(&mut self, link: &str) -> String;
+    /// `<picture>` of the resized copies of an image, if there are any
+    fn picture(&mut self, link: &str, alt: &str, title: &str) -> Option<String>;
+    /// A file from the page bundle (or from the directory of the Markdown file) or from the files
+    /// directory
+    fn data_file(&mut self, path: &str) -> Option<PathBuf>;
+    /// The article is compiled again when the file changes
+    fn depend_on(&mut self, path: PathBuf);
+    fn report(&mut self, problem: Problem);
+}
+
+pub struct Call {
+    /// As it was written, for the error messages
+    pub source: String,
+    /// The name and the arguments, or why they cannot be parsed
+    pub parsed: Result<(String, Arguments), String>,
+}
+
+struct BuiltIn {
+    name: &'static str,
+    required: &'static [&'static str],
+    optional: &'static [&'static str],
+    render: fn(&Arguments, &mut dyn Context) -> Result<String, String>,
+}
+
+const BUILT_INS: &[BuiltIn] = &[
+    BuiltIn {
+        name: "figure",
+        required: &["src"],
+        optional: &["alt", "caption", "title", "link"],
+        render: figure,
+    },
+    BuiltIn {
+        name: "youtube",
+        required: &["id"],
+        optional: &["title", "start"],
+        render: youtube,
+    },
+    BuiltIn {
+        name: "video",
+        required: &["src"],
+        optional: &["poster", "title"],
+        render: video,
+    },
+    BuiltIn {
+        name: "table",
+        required: &["src"],
+        optional: &["columns", "sort", "order", "number_alignment", "caption"],
+        render: data_tables::render,
+    },
+    BuiltIn {
+        name: "download",
+        required: &["src"],
+        optional: &["label"],
+        render: download,
+    },
+];
+
+fn attribute(value: &str) -> String {
+    html_escape::encode_double_quoted_attribute(value).into_owned()
+}
+
+fn argument<'arguments>(arguments: &'arguments Arguments, name: &str) -> &'arguments str {
+    arguments.get(name).map_or("", String::as_str)
+}
+
+/// `{{< figure src="photo.jpg" alt="..." caption="..." title="..." link="..." >}}`
+#[allow(clippy::unnecessary_wraps)] // Same signature as the other built-in shortcodes
+fn figure(arguments: &Arguments, context: &mut dyn Context) -> Result<String, String> {
+    let (src, alt, title) = (
+        argument(arguments, "src"),
+        argument(arguments, "alt"),
+        argument(arguments, "title"),
+    );
+    let mut image = context.picture(src, alt, title).unwrap_or_else(|| {
+        let title = if title.is_empty() {
+            String::new()
+        } else {
+            format!(r#" title="{}""#, attribute(title))
+        };
+        format!(
+            r#"<img src="{}" alt="{}"{title}>"#,
+            attribute(&context.link(src)),
+            attribute(alt),
+        )
+    });
+    if let Some(link) = arguments.get("link") {
+        image = format!(
+            r#"<a href="{}">{image}</a>"#,
+            attribute(&context.link(link))
+        );
+    }
+    let caption = arguments
+        .get("caption")
+        .map_or_else(String::new, |caption| {
+            format!(
+                "<figcaption>{}</figcaption>",
+                html_escape::encode_text(caption)
+            )
+        });
+    Ok(format!("<figure>{image}{caption}</figure>"))
+}
+
+/// `{{< youtube id="dQw4w9WgXcQ" title="..." start="42" >}}`
+fn youtube(arguments: &Arguments, _context: &mut dyn Context) -> Result<String, String> {
+    let id = argument(arguments, "id");
+    if !id
+        .chars()
+        .all(|character| character.is_ascii_alphanumeric() || "_-".contains(character))
+    {
+        return Err(format!("`{id}` is not a YouTube video ID"));
+    }
+    let start = match arguments.get("start") {
+        Some(start) => {
+            let seconds: u32 = start
+                .parse()
+                .map_err(|_error| format!("`start` must be a number of seconds, not `{start}`"))?;
+            format!("?start={seconds}")
+        }
+        None => String::new(),
+    };
+    let title = arguments
+        .get("title")
+        .map_or("YouTube video", String::as_str);
+    Ok(format!(
+        r#"<div class="video"><iframe src="https://www.youtube-nocookie.com/embed/{id}{start}" title="{}" loading="lazy" allow="encrypted-media; picture-in-picture" allowfullscreen></iframe></div>"#,
+        attribute(title)
+    ))
+}
+
+/// `{{< video src="clip.mp4" poster="clip.jpg" title="..." >}}`
+#[allow(clippy::unnecessary_wraps)] // Same signature as the other built-in shortcodes
+fn video(arguments: &Arguments, context: &mut dyn Context) -> Result<String, String> {
+    let src = context.link(argument(arguments, "src"));
+    let poster = arguments.get("poster").map_or_else(String::new, |poster| {
+        format!(r#" poster="{}""#, attribute(&context.link(poster)))
+    });
+    let title = arguments.get("title").map_or_else(String::new, |title| {
+        format!(r#" aria-label="{}""#, attribute(title))
+    });
+    let src = attribute(&src);
+    Ok(format!(
+        r#"<video class="video" src="{src}"{poster}{title} controls preload="metadata"><a href="{src}">Download the video</a></video>"#
+    ))
+}
+
+/// `{{< download src="slides.pdf" label="Slides" >}}`
+#[allow(clippy::unnecessary_wraps)] // Same signature as the other built-in shortcodes
+fn download(arguments: &Arguments, context: &mut dyn Context) -> Result<String, String> {
+    let src = argument(arguments, "src");
+    let label = arguments
+        .get("label")
+        .map_or_else(|| src.rsplit('/').next().unwrap_or(src), String::as_str);
+    Ok(format!(
+        r#"<a class="download-button" href="{}" download>{}</a>"#,
+        attribute(&context.link(src)),
+        html_escape::encode_text(label)
+    ))
+}
+
+fn is_name_character(character: char) -> bool {
+    character.is_alphanumeric() || "_-".contains(character)
+}
+
+/// `name key="value" other=value` (inside of `{{<` and `>}}`)
+fn parse(inner: &str) -> Result<(String, Arguments), String> {
+    let inner = inner.trim();
+    let name_length = inner
+        .find(|character| !is_name_character(character))
+        .unwrap_or(inner.len());
+    if name_length == 0 {
+        return Err("the shortcode has no name".to_owned());
+    }
+    let (name, mut rest) = inner.split_at(name_length);
+    let mut arguments = Arguments::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok((name.to_owned(), arguments));
+        }
+        let key_length = rest
+            .find(|character| !is_name_character(character))
+            .unwrap_or(rest.len());
+        let (key, after_key) = rest.split_at(key_length);
+        let Some(after_equals) = after_key.strip_prefix('=').filter(|_| !key.is_empty()) else {
+            return Err(format!(
+                "expected `name=\"value\"`, found `{}`",
+                rest.split_whitespace().next().unwrap_or_default()
+            ));
+        };
+        let value;
+        if let Some(quoted) = after_equals.strip_prefix('"') {
+            let end = prose::find_unescaped(quoted, "\"")
+                .ok_or_else(|| format!("the value of `{key}` has no closing quote"))?;
+            value = quoted[..end].replace("\\\"", "\"").replace("\\\\", "\\");
+            rest = &quoted[end + 1..];
+        } else {
+            let end = after_equals
+                .find(char::is_whitespace)
+                .unwrap_or(after_equals.len());
+            value = after_equals[..end].to_owned();
+            rest = &after_equals[end..];
+        }
+        if arguments.insert(key.to_owned(), value).is_some() {
+            return Err(format!("`{key}` is given more than once"));
+        }
+    }
+}
+
+/// Length of the shortcode at the start of `text`, if it is one
+pub fn shortcode_at(text: &str) -> Option<(usize, Call)> {
+    let inner = text.strip_prefix("{{<")?;
+    // `>}}` in the quoted values doesn't end the shortcode
+    let mut is_in_quotes = false;
+    let mut index = 0;
+    while index < inner.len() {
+        let rest = &inner[index..];
+        if is_in_quotes && rest.starts_with('\\') {
+            index += 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if rest.starts_with('"') {
+            is_in_quotes = !is_in_quotes;
+        } else if !is_in_quotes && rest.starts_with(">}}") {
+            let length = 3 + index + 3;
+            return Some((
+                length,
+                Call {
+                    source: text[..length].to_owned(),
+                    parsed: parse(&inner[..index]),
+                },
+            ));
+        }
+        index += rest.chars().next().unwrap().len_utf8();
+    }
+    Some((
+        3,
+        Call {
+            source: "{{<".to_owned(),
+            parsed: Err("the shortcode is not closed with `>}}`".to_owned()),
+        },
+    ))
+}
+
+/// Checks that the required arguments are given, and that there are no unknown ones
+pub fn validate(arguments: &Arguments, required: &[&str], optional: &[&str]) -> Result<(), String> {
+    if let Some(missing) = required.iter().find(|name| !arguments.contains_key(**name)) {
+        return Err(format!("argument `{missing}` is required"));
+    }
+    if let Some(unknown) = arguments
+        .keys()
+        .find(|name| !required.contains(&name.as_str()) && !optional.contains(&name.as_str()))
+    {
+        let mut known = required.iter().chain(optional).copied().collect::<Vec<_>>();
+        known.sort_unstable();
+        return Err(format!(
+            "argument `{}` is unknown (the known ones are: {})",
+            unknown,
+            known.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// `{{ name }}`, `{{ name? }}` (can be omitted) or `{{ name | link }}` (resolved like the links of
+/// the article) in a template
+enum Segment<'template> {
+    Literal(&'template str),
+    Argument {
+        name: &'template str,
+        is_optional: bool,
+        is_link: bool,
+    },
+}
+
+fn parse_template(template: &str) -> Result<Vec<Segment<'_>>, String> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        segments.push(Segment::Literal(&rest[..start]));
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or("the template has `{{` without `}}`")?;
+        let (name, filter) = after[..end].split_once('|').unwrap_or((&after[..end], ""));
+        let name = name.trim();
+        let (name, is_optional) = name
+            .strip_suffix('?')
+            .map_or((name, false), |name| (name.trim(), true));
+        if name.is_empty() || !name.chars().all(is_name_character) {
+            return Err(format!("the template has an invalid argument `{name}`"));
+        }
+        let is_link = match filter.trim() {
+            "" => false,
+            "link" => true,
+            filter => return Err(format!("the template has an unknown filter `{filter}`")),
+        };
+        segments.push(Segment::Argument {
+            name,
+            is_optional,
+            is_link,
+        });
+        rest = &after[end + 2..];
+    }
+    segments.push(Segment::Literal(rest));
+    Ok(segments)
+}
+
+fn render_template(
+    template: &str,
+    arguments: &Arguments,
+    context: &mut dyn Context,
+) -> Result<String, String> {
+    let segments = parse_template(template)?;
+    let mut required = Vec::new();
+    let mut optional = Vec::new();
+    for segment in &segments {
+        if let Segment::Argument {
+            name, is_optional, ..
+        } = *segment
+        {
+            if is_optional {
+                optional.push(name);
+            } else {
+                required.push(name);
+            }
+        }
+    }
+    validate(arguments, &required, &optional)?;
+    let mut html = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Literal(literal) => html.push_str(literal),
+            Segment::Argument { name, is_link, .. } => {
+                let value = argument(arguments, name);
+                if is_link && !value.is_empty() {
+                    html.push_str(&attribute(&context.link(value)));
+                } else {
+                    html.push_str(&attribute(value));
+                }
+            }
+        }
+    }
+    Ok(html)
+}
+
+pub enum Piece<'text> {
+    Text(&'text str),
+    Html(String),
+}
+
+/// Shortcodes of an article: `{{< name key="value" >}}`. The built-in ones are `figure`,
+/// `youtube`, `video`, `table` and `download`, and the theme can add more (or replace these) with
+/// `shortcodes/<name>.html` templates
+#[derive(Default)]
+pub struct Shortcodes {
+    calls: Vec<Call>,
+}
+
+impl Shortcodes {
+    /// Replaces the shortcodes outside of the code with placeholders
+    pub fn extract(markdown: &str) -> (String, Self) {
+        let mut shortcodes = Self::default();
+        let output = prose::replace(markdown, |text| {
+            let (length, call) = shortcode_at(text)?;
+            let placeholder = format!(
+                "{}{}{}",
+                PLACEHOLDER_START,
+                shortcodes.calls.len(),
+                PLACEHOLDER_END
+            );
+            shortcodes.calls.push(call);
+            Some((length, placeholder))
+        });
+        (output, shortcodes)
+    }
+
+    fn render(call: &Call, context: &mut dyn Context) -> Result<String, String> {
+        let (name, arguments) = call.parsed.as_ref().map_err(Clone::clone)?;
+        if let Some(theme_directory) = context.theme_directory() {
+            let path = theme_directory
+                .join(TEMPLATES_DIRECTORY)
+                .join(format!("{name}.html"));
+            context.depend_on(path.clone());
+            match fs::read_to_string(&path) {
+                Ok(template) => return render_template(&template, arguments, context),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+                Err(error) => {
+                    return Err(format!(
+                        "template `{}` cannot be read: {error}",
+                        path.display()
+                    ))
+                }
+            }
+        }
+        let built_in = BUILT_INS
+            .iter()
+            .find(|built_in| built_in.name == name)
+            .ok_or_else(|| format!("there's no shortcode `{name}`"))?;
+        validate(arguments, built_in.required, built_in.optional)?;
+        (built_in.render)(arguments, context)
+    }
+
+    /// HTML of the shortcode with the index. The errors are reported and shown as the shortcode
+    /// was written
+    fn html(&self, index: usize, context: &mut dyn Context) -> String {
+        let call = &self.calls[index];
+        Self::render(call, context).unwrap_or_else(|details| {
+            context.report(Problem::InvalidShortcode {
+                shortcode: call.source.clone(),
+                details: details.clone(),
+            });
+            format!(
+                r#"<span class="shortcode-error" title="{}">{}</span>"#,
+                attribute(&details),
+                html_escape::encode_text(&call.source)
+            )
+        })
+    }
+
+    /// Splits the text at the placeholders, turning them into HTML
+    pub fn expand<'text>(&self, text: &'text str, context: &mut dyn Context) -> Vec<Piece<'text>> {
+        let mut pieces = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(PLACEHOLDER_START) {
+            let contents = &rest[start + PLACEHOLDER_START.len_utf8()..];
+            let Some((index, after)) = contents.split_once(PLACEHOLDER_END) else {
+                break;
+            };
+            let Some(index) = index.parse().ok().filter(|&index| index < self.calls.len()) else {
+                break;
+            };
+            if start > 0 {
+                pieces.push(Piece::Text(&rest[..start]));
+            }
+            pieces.push(Piece::Html(self.html(index, context)));
+            rest = after;
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Text(rest));
+        }
+        pieces
+    }
+
+    /// HTML of the text if it is a single shortcode (which then doesn't need a paragraph)
+    pub fn alone(&self, text: &str, context: &mut dyn Context) -> Option<String> {
+        let index = text
+            .trim()
+            .strip_prefix(PLACEHOLDER_START)?
+            .strip_suffix(PLACEHOLDER_END)?
+            .parse()
+            .ok()
+            .filter(|&index| index < self.calls.len())?;
+        Some(self.html(index, context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use super::{parse, render_template, shortcode_at, Arguments, Context, Piece, Shortcodes};
+    use crate::diagnostics::Problem;
+
+    /// Links get a `/linked/` prefix, and the problems are collected
+    #[derive(Default)]
+    struct StandIn {
+        theme_directory: Option<PathBuf>,
+        problems: Vec<Problem>,
+    }
+
+    impl Context for StandIn {
+        fn theme_directory(&self) -> Option<&Path> {
+            self.theme_directory.as_deref()
+        }
+
+        fn link(&mut self, link: &str) -> String {
+            format!("/linked/{link}")
+        }
+
+        fn picture(&mut self, _link: &str, _alt: &str, _title: &str) -> Option<String> {
+            None
+        }
+
+        fn data_file(&mut self, _path: &str) -> Option<PathBuf> {
+            None
+        }
+
+        fn depend_on(&mut self, _path: PathBuf) {}
+
+        fn report(&mut self, problem: Problem) {
+            self.problems.push(problem);
+        }
+    }
+
+    fn arguments(pairs: &[(&str, &str)]) -> Arguments {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+            .collect()
+    }
+
+    /// The HTML of the shortcodes in the text, and the problems
+    fn expand(markdown: &str, context: &mut StandIn) -> String {
+        let (text, shortcodes) = Shortcodes::extract(markdown);
+        shortcodes
+            .expand(&text, context)
+            .into_iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.to_owned(),
+                Piece::Html(html) => html,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_arguments() {
+        assert_eq!(
+            parse(r#" figure src="a b.png" alt=plain caption="say \"hi\" \\ bye" "#),
+            Ok((
+                "figure".to_owned(),
+                arguments(&[
+                    ("src", "a b.png"),
+                    ("alt", "plain"),
+                    ("caption", r#"say "hi" \ bye"#)
+                ])
+            ))
+        );
+        assert!(parse("").is_err());
+        assert!(parse(r#"figure src="unclosed"#).is_err());
+        assert!(parse("figure src").is_err());
+        assert!(parse("figure src=a src=b").is_err());
+    }
+
+    #[test]
+    fn quoted_ends_dont_end_shortcodes() {
+        let text = r#"{{< figure caption="a >}} b" >}} after"#;
+        let (length, call) = shortcode_at(text).unwrap();
+        assert_eq!(&text[length..], " after");
+        assert_eq!(
+            call.parsed,
+            Ok(("figure".to_owned(), arguments(&[("caption", "a >}} b")])))
+        );
+        assert!(shortcode_at("{ {< figure >}}").is_none());
+    }
+
+    #[test]
+    fn unclosed_shortcodes_are_reported() {
+        let (length, call) = shortcode_at(r#"{{< figure src="a.png" >}"#).unwrap();
+        assert_eq!(length, 3);
+        assert!(call.parsed.is_err());
+        let mut context = StandIn::default();
+        let html = expand(r#"{{< figure src="a.png" >}"#, &mut context);
+        assert!(
+            html.starts_with(r#"<span class="shortcode-error""#),
+            "{}",
+            html
+        );
+        assert_eq!(context.problems.len(), 1);
+    }
+
+    #[test]
+    fn unknown_arguments_are_reported() {
+        let mut context = StandIn::default();
+        let html = expand(r#"{{< download src="a.pdf" size="big" >}}"#, &mut context);
+        assert!(html.contains("shortcode-error"), "{}", html);
+        assert_eq!(
+            context.problems[0].to_string(),
+            "shortcode `{{< download src=\"a.pdf\" size=\"big\" >}}` cannot be expanded. \
+             Details: argument `size` is unknown (the known ones are: label, src)"
+        );
+        let html = expand(r#"{{< download src="a.pdf" >}}"#, &mut context);
+        assert_eq!(
+            html,
+            r#"<a class="download-button" href="/linked/a.pdf" download>a.pdf</a>"#
+        );
+        assert_eq!(context.problems.len(), 1);
+    }
+
+    #[test]
+    fn link_destinations_are_left_alone() {
+        let markdown = r#"[a]({{< download src="a.pdf" >}}) ![b](<{{< figure src="b.png" >}}>)"#;
+        let (text, shortcodes) = Shortcodes::extract(markdown);
+        assert_eq!(text, markdown);
+        assert!(shortcodes.calls.is_empty());
+        let mut context = StandIn::default();
+        assert_eq!(
+            expand(r#"[{{< download src="a.pdf" >}}](/b)"#, &mut context),
+            r#"[<a class="download-button" href="/linked/a.pdf" download>a.pdf</a>](/b)"#
+        );
+    }
+
+    #[test]
+    fn renders_templates() {
+        let mut context = StandIn::default();
+        let template = r#"<a href="{{ href | link }}" title="{{ title? }}">{{ label }}</a>"#;
+        assert_eq!(
+            render_template(
+                template,
+                &arguments(&[("href", "a.pdf"), ("label", "<A & B>")]),
+                &mut context
+            ),
+            Ok(r#"<a href="/linked/a.pdf" title="">&lt;A &amp; B&gt;</a>"#.to_owned())
+        );
+        assert!(render_template(template, &arguments(&[("href", "a")]), &mut context).is_err());
+        assert!(render_template("{{ a | upper }}", &Arguments::new(), &mut context).is_err());
+        assert!(render_template("{{ a", &Arguments::new(), &mut context).is_err());
+    }
+
+    #[test]
+    fn theme_templates_replace_built_ins() {
+        let theme = tempfile::tempdir().unwrap();
+        fs::create_dir(theme.path().join("shortcodes")).unwrap();
+        fs::write(
+            theme.path().join("shortcodes/download.html"),
+            r#"<a href="{{ src | link }}">Get it</a>"#,
+        )
+        .unwrap();
+        let mut context = StandIn {
+            theme_directory: Some(theme.path().to_owned()),
+            ..StandIn::default()
+        };
+        assert_eq!(
+            expand(r#"Here: {{< download src="a.pdf" >}}."#, &mut context),
+            r#"Here: <a href="/linked/a.pdf">Get it</a>."#
+        );
+        assert!(context.problems.is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+use std::io;
+
+/// What the process was asked to do from the outside
+pub enum Signal {
+    /// `SIGHUP`: re-read the configuration and recompile everything
+    Reload,
+    /// `SIGUSR1`: write the current state of the server to the log
+    DumpStatus,
+    /// `SIGTERM` or `SIGINT`: stop gracefully
+    Shutdown,
+}
+
+/// Spawns a thread that calls the `handler` on every signal received.
+///
+/// # Errors
+/// Returns an error if the signal handlers couldn't be registered.
+#[cfg(unix)]
+pub fn listen(mut handler: impl FnMut(Signal) + Send + 'static) -> io::Result<()> {
+    use signal_hook::{
+        consts::{SIGHUP, SIGINT, SIGTERM, SIGUSR1},
+        iterator::Signals,
+    };
+
+    let mut signals = Signals::new([SIGHUP, SIGUSR1, SIGTERM, SIGINT])?;
+    std::thread::spawn(move || {
+        for signal in signals.forever() {
+            handler(match signal {
+                SIGHUP => Signal::Reload,
+                SIGUSR1 => Signal::DumpStatus,
+                _ => Signal::Shutdown,
+            });
+        }
+    });
+    Ok(())
+}
+
+/// There are no such signals outside of Unix, and Ctrl+C is handled by the server itself there.
+///
+/// # Errors
+/// Never returns an error; the signature matches the Unix version.
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+pub fn listen(_handler: impl FnMut(Signal) + Send + 'static) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,587 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use actix_web::HttpRequest;
+use log::{error, info};
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode};
+
+use crate::{
+    begin_watching,
+    config::{Config, SiteConfig},
+    file_watcher_delay, watch_articles, watch_files, watch_theme,
+    website::Website,
+    ArticlesWatcher, CompareWithAbsolutePath, FilesWatcher, StopFlag, ThemeWatcher, WatchContext,
+    WatchResult,
+};
+
+/// One blog with its own articles and the watchers of its articles and files directories
+struct Site {
+    website: Arc<Mutex<Website>>,
+    watch_context: Arc<Mutex<WatchContext<ArticlesWatcher>>>,
+    stop_flag: StopFlag,
+    watcher_thread: JoinHandle<()>,
+    /// There's nothing to watch if the files directory doesn't exist
+    files_watching: Option<DependencyWatching<FilesWatcher>>,
+    /// The templates of the shortcodes are in the theme directory
+    theme_watching: Option<DependencyWatching<ThemeWatcher>>,
+    /// The included files can be anywhere
+    outside_watching: OutsideWatching,
+}
+
+/// The articles are recompiled when the files they depend on (from the files directory or from
+/// the theme directory) are changed
+struct DependencyWatching<Watcher> {
+    watch_context: Arc<Mutex<WatchContext<Watcher>>>,
+    stop_flag: StopFlag,
+    watcher_thread: JoinHandle<()>,
+}
+
+struct ArticlesDirectory {
+    config: Arc<Mutex<SiteConfig>>,
+}
+
+impl CompareWithAbsolutePath for ArticlesDirectory {
+    fn compare(&self, absolute_path: &Path) -> bool {
+        self.config.lock().unwrap().articles_directory.as_ref() == absolute_path
+    }
+}
+
+/// The files directory or the theme directory of a site
+struct DependencyDirectory {
+    config: Arc<Mutex<SiteConfig>>,
+    directory_of: fn(&SiteConfig) -> Option<&Path>,
+}
+
+impl CompareWithAbsolutePath for DependencyDirectory {
+    fn compare(&self, absolute_path: &Path) -> bool {
+        (self.directory_of)(&self.config.lock().unwrap()) == Some(absolute_path)
+    }
+}
+
+#[allow(clippy::unnecessary_wraps)] // The same signature as `theme_directory_of`
+fn files_directory_of(config: &SiteConfig) -> Option<&Path> {
+    Some(&config.files_directory)
+}
+
+fn theme_directory_of(config: &SiteConfig) -> Option<&Path> {
+    config.theme_directory.as_deref()
+}
+
+impl<Watcher: 'static + Send> DependencyWatching<Watcher> {
+    fn start(
+        config: Arc<Mutex<SiteConfig>>,
+        website: Arc<Mutex<Website>>,
+        directory_name: &'static str,
+        directory_of: fn(&SiteConfig) -> Option<&Path>,
+        watch_context_maker: fn(&SiteConfig) -> WatchResult<Watcher>,
+    ) -> Option<Self> {
+        let watch_context_result = {
+            let config = config.lock().unwrap();
+            // A site without a theme has nothing to watch there
+            directory_of(&config)?;
+            watch_context_maker(&config)
+        };
+        let watch_context = match watch_context_result {
+            Ok(watch_context) => Arc::new(Mutex::new(watch_context)),
+            Err(error) => {
+                info!(
+                    "{} is not watched, so the articles won't notice the changes of its files. \
+                    Details: {}",
+                    directory_name, error
+                );
+                return None;
+            }
+        };
+        let stop_flag = StopFlag::default();
+        let watcher_thread = begin_watching(
+            watch_context.clone(),
+            config.clone(),
+            stop_flag.clone(),
+            directory_name,
+            DependencyDirectory {
+                config,
+                directory_of,
+            },
+            watch_context_maker,
+            {
+                let website = website.clone();
+                move |event| match event {
+                    DebouncedEvent::Remove(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Create(path) => {
+                        website.lock().unwrap().dependency_changed(&[path]);
+                    }
+                    DebouncedEvent::Rename(from, to) => {
+                        website.lock().unwrap().dependency_changed(&[from, to]);
+                    }
+                    _ => (),
+                }
+            },
+            move || website.lock().unwrap().reload_articles_and_index(),
+        );
+        Some(Self {
+            watch_context,
+            stop_flag,
+            watcher_thread,
+        })
+    }
+}
+
+impl DependencyWatching<FilesWatcher> {
+    fn files(config: Arc<Mutex<SiteConfig>>, website: Arc<Mutex<Website>>) -> Option<Self> {
+        Self::start(
+            config,
+            website,
+            "Files directory",
+            files_directory_of,
+            watch_files,
+        )
+    }
+}
+
+impl DependencyWatching<ThemeWatcher> {
+    fn theme(config: Arc<Mutex<SiteConfig>>, website: Arc<Mutex<Website>>) -> Option<Self> {
+        Self::start(
+            config,
+            website,
+            "Theme directory",
+            theme_directory_of,
+            watch_theme,
+        )
+    }
+}
+
+/// Watches the directories of the files that the articles include from outside of the watched
+/// directories. The set of the directories changes with the articles, so it's checked every second
+struct OutsideWatching {
+    stop_flag: StopFlag,
+    watcher_thread: JoinHandle<()>,
+}
+
+impl OutsideWatching {
+    fn start(website: Arc<Mutex<Website>>) -> Self {
+        let stop_flag = StopFlag::default();
+        let watcher_thread = thread::spawn({
+            let stop_flag = stop_flag.clone();
+            move || {
+                let (event_sender, event_receiver) = mpsc::channel();
+                let mut watcher: RecommendedWatcher =
+                    notify::Watcher::new(event_sender, file_watcher_delay()).unwrap();
+                let mut watched_directories = BTreeSet::new();
+                while !stop_flag.is_raised() {
+                    let directories = website.lock().unwrap().outside_directories();
+                    for directory in watched_directories.difference(&directories) {
+                        let _ = notify::Watcher::unwatch(&mut watcher, directory);
+                    }
+                    watched_directories.retain(|directory| directories.contains(directory));
+                    for directory in directories {
+                        // A directory that doesn't exist yet is tried again later
+                        if !watched_directories.contains(&directory)
+                            && notify::Watcher::watch(
+                                &mut watcher,
+                                &directory,
+                                RecursiveMode::NonRecursive,
+                            )
+                            .is_ok()
+                        {
+                            watched_directories.insert(directory);
+                        }
+                    }
+                    while let Ok(event) = event_receiver.try_recv() {
+                        match event {
+                            DebouncedEvent::Remove(path)
+                            | DebouncedEvent::Write(path)
+                            | DebouncedEvent::Create(path) => {
+                                website.lock().unwrap().dependency_changed(&[path]);
+                            }
+                            DebouncedEvent::Rename(from, to) => {
+                                website.lock().unwrap().dependency_changed(&[from, to]);
+                            }
+                            _ => (),
+                        }
+                    }
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        });
+        Self {
+            stop_flag,
+            watcher_thread,
+        }
+    }
+}
+
+impl Site {
+    fn raise_stop_flags(&self) {
+        self.stop_flag.raise();
+        if let Some(files_watching) = &self.files_watching {
+            files_watching.stop_flag.raise();
+        }
+        if let Some(theme_watching) = &self.theme_watching {
+            theme_watching.stop_flag.raise();
+        }
+        self.outside_watching.stop_flag.raise();
+    }
+
+    fn start(config: Arc<Mutex<SiteConfig>>) -> Result<Self, notify::Error> {
+        let watch_context = Arc::new(Mutex::new(watch_articles(&config.lock().unwrap())?));
+        // The server answers with "warming up" pages until the articles are compiled
+        let website = Arc::new(Mutex::new(Website::warming_up(config.clone())));
+        let (finished_images, finished_images_receiver) = mpsc::channel();
+        thread::spawn({
+            let website = website.clone();
+            let config = config.clone();
+            move || {
+                let compiled_website = Website::new(config, Some(finished_images));
+                website.lock().unwrap().finish_warming_up(compiled_website);
+            }
+        });
+        // The articles show the resized copies of the images as soon as they are ready. The thread
+        // finishes when the website is dropped (with the sender)
+        thread::spawn({
+            let website = Arc::downgrade(&website);
+            move || {
+                for source in finished_images_receiver {
+                    let Some(website) = website.upgrade() else {
+                        break;
+                    };
+                    website.lock().unwrap().dependency_changed(&[source]);
+                }
+            }
+        });
+        let stop_flag = StopFlag::default();
+        let watcher_thread = begin_watching(
+            watch_context.clone(),
+            config.clone(),
+            stop_flag.clone(),
+            "Articles directory",
+            ArticlesDirectory {
+                config: config.clone(),
+            },
+            watch_articles,
+            {
+                let website = website.clone();
+                move |event| match event {
+                    DebouncedEvent::Remove(path)
+                    | DebouncedEvent::Write(path)
+                    | DebouncedEvent::Create(path) => {
+                        website.lock().unwrap().article_path_changed(&path);
+                    }
+                    DebouncedEvent::Rename(from, to) => {
+                        website.lock().unwrap().article_path_renamed(&from, &to);
+                    }
+                    _ => (),
+                }
+            },
+            {
+                let website = website.clone();
+                move || website.lock().unwrap().reload_articles_and_index()
+            },
+        );
+        Ok(Self {
+            files_watching: DependencyWatching::files(config.clone(), website.clone()),
+            theme_watching: DependencyWatching::theme(config, website.clone()),
+            outside_watching: OutsideWatching::start(website.clone()),
+            website,
+            watch_context,
+            stop_flag,
+            watcher_thread,
+        })
+    }
+}
+
+/// All the blogs that are served by this process
+pub struct Sites {
+    /// Site name -> site
+    by_name: BTreeMap<String, Site>,
+    /// Host name -> site name
+    hosts: HashMap<String, String>,
+    default_site: String,
+}
+
+impl Sites {
+    /// A placeholder that serves nothing
+    pub fn empty() -> Self {
+        Self {
+            by_name: BTreeMap::new(),
+            hosts: HashMap::new(),
+            default_site: String::new(),
+        }
+    }
+
+    /// Compiles every site and starts watching their articles.
+    ///
+    /// # Errors
+    /// Returns the name of the site whose articles directory cannot be watched, and the reason.
+    pub fn start(config: &Config) -> Result<Self, (String, notify::Error)> {
+        let mut instance = Self {
+            by_name: BTreeMap::new(),
+            hosts: HashMap::new(),
+            default_site: config.default_site.clone(),
+        };
+        for (name, site_config) in &config.sites {
+            instance
+                .add(name.clone(), site_config.clone())
+                .map_err(|error| (name.clone(), error))?;
+        }
+        instance.reindex_hosts(&config.sites, &config.default_site);
+        Ok(instance)
+    }
+
+    /// # Errors
+    /// Returns an error if the articles directory of the site cannot be watched.
+    pub fn add(
+        &mut self,
+        name: String,
+        config: Arc<Mutex<SiteConfig>>,
+    ) -> Result<(), notify::Error> {
+        let site = Site::start(config)?;
+        if let Some(old_site) = self.by_name.insert(name, site) {
+            old_site.raise_stop_flags();
+        }
+        Ok(())
+    }
+
+    /// Starts watching the new files directory of the site
+    pub fn rewatch_files(&mut self, name: &str) {
+        let site = self.by_name.get_mut(name).unwrap();
+        if let Some(files_watching) = site.files_watching.take() {
+            files_watching.stop_flag.raise();
+        }
+        let config = site.website.lock().unwrap().config().clone();
+        site.files_watching = DependencyWatching::files(config, site.website.clone());
+    }
+
+    /// Starts watching the new theme directory of the site
+    pub fn rewatch_theme(&mut self, name: &str) {
+        let site = self.by_name.get_mut(name).unwrap();
+        if let Some(theme_watching) = site.theme_watching.take() {
+            theme_watching.stop_flag.raise();
+        }
+        let config = site.website.lock().unwrap().config().clone();
+        site.theme_watching = DependencyWatching::theme(config, site.website.clone());
+    }
+
+    /// Stops watching the site's articles and forgets about it. The watcher thread finishes by
+    /// itself shortly after.
+    pub fn remove(&mut self, name: &str) {
+        if let Some(site) = self.by_name.remove(name) {
+            site.raise_stop_flags();
+        }
+    }
+
+    /// # Panics
+    /// Panics if there's no site with this name.
+    pub fn parts_of(
+        &self,
+        name: &str,
+    ) -> (
+        Arc<Mutex<Website>>,
+        Arc<Mutex<WatchContext<ArticlesWatcher>>>,
+    ) {
+        let site = &self.by_name[name];
+        (site.website.clone(), site.watch_context.clone())
+    }
+
+    pub fn reindex_hosts(
+        &mut self,
+        site_configs: &BTreeMap<String, Arc<Mutex<SiteConfig>>>,
+        default_site: &str,
+    ) {
+        self.hosts.clear();
+        for (name, site_config) in site_configs {
+            for host_name in &site_config.lock().unwrap().host_names {
+                if let Some(other_name) = self.hosts.insert(host_name.to_lowercase(), name.clone())
+                {
+                    error!(
+                        "Host `{}` belongs to both `{}` and `{}` sites! Using `{}`",
+                        host_name, other_name, name, name
+                    );
+                }
+            }
+        }
+        default_site.clone_into(&mut self.default_site);
+    }
+
+    /// Recreates the watchers of every site (after the watcher delay was changed)
+    pub fn rewatch(&self) {
+        for (name, site) in &self.by_name {
+            let config = site.website.lock().unwrap().config().clone();
+            let new_context = watch_articles(&config.lock().unwrap());
+            match new_context {
+                Ok(new_context) => *site.watch_context.lock().unwrap() = new_context,
+                Err(error) => error!(
+                    "An error occured while changing the articles watcher delay of site `{}`: {}",
+                    name, error
+                ),
+            }
+            if let Some(files_watching) = &site.files_watching {
+                let new_context = watch_files(&config.lock().unwrap());
+                match new_context {
+                    Ok(new_context) => *files_watching.watch_context.lock().unwrap() = new_context,
+                    Err(error) => error!(
+                        "An error occured while changing the files watcher delay of site `{}`: {}",
+                        name, error
+                    ),
+                }
+            }
+            if let Some(theme_watching) = &site.theme_watching {
+                let new_context = watch_theme(&config.lock().unwrap());
+                match new_context {
+                    Ok(new_context) => *theme_watching.watch_context.lock().unwrap() = new_context,
+                    Err(error) => error!(
+                        "An error occured while changing the theme watcher delay of site `{}`: {}",
+                        name, error
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Picks the site by the `Host` header of the request, falling back to the default site
+    pub fn website_for(&self, request: &HttpRequest) -> Arc<Mutex<Website>> {
+        let connection_info = request.connection_info();
+        let host = connection_info.host();
+        // Stripping the port, but not breaking IPv6 addresses like `[::1]`
+        let host = match host.rfind(':') {
+            Some(colon_index) if !host[colon_index..].contains(']') => &host[..colon_index],
+            _ => host,
+        };
+        let site_name = self
+            .hosts
+            .get(&host.to_lowercase())
+            .unwrap_or(&self.default_site);
+        // The default site can be missing for a moment while the configuration is being updated
+        self.by_name
+            .get(site_name)
+            .or_else(|| self.by_name.values().next())
+            .expect("There are no sites to serve!")
+            .website
+            .clone()
+    }
+
+    pub fn websites(&self) -> impl Iterator<Item = (&String, &Arc<Mutex<Website>>)> {
+        self.by_name
+            .iter()
+            .map(|(name, site)| (name, &site.website))
+    }
+
+    /// Compiles the articles and the index pages of every site again, one site at a time. The list
+    /// of the sites isn't locked meanwhile, so the other sites are still served, but the requests
+    /// to a site wait until it's compiled
+    pub fn reload_all(sites: &Mutex<Self>) {
+        let websites: Vec<_> = sites
+            .lock()
+            .unwrap()
+            .websites()
+            .map(|(_name, website)| website.clone())
+            .collect();
+        for website in websites {
+            website.lock().unwrap().reload_articles_and_index();
+        }
+    }
+
+    /// Stops every watcher and waits for them to finish, then saves what the sites haven't saved
+    pub fn stop(self) {
+        for site in self.by_name.values() {
+            site.raise_stop_flags();
+        }
+        for site in self.by_name.into_values() {
+            site.watcher_thread.join().unwrap();
+            if let Some(files_watching) = site.files_watching {
+                files_watching.watcher_thread.join().unwrap();
+            }
+            if let Some(theme_watching) = site.theme_watching {
+                theme_watching.watcher_thread.join().unwrap();
+            }
+            site.outside_watching.watcher_thread.join().unwrap();
+            site.website.lock().unwrap().flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    use actix_web::test::TestRequest;
+
+    use super::Sites;
+    use crate::config::SiteConfig;
+
+    /// Sites with the host names, each in its own directory, with `main` by default
+    fn sites(root: &tempfile::TempDir, host_names: &[(&str, &[&str])]) -> Sites {
+        let mut sites = Sites::empty();
+        let mut configs = BTreeMap::new();
+        for (name, site_host_names) in host_names {
+            let directory = root.path().join(name);
+            fs::create_dir_all(directory.join("articles")).unwrap();
+            let mut config = SiteConfig::for_tests(&directory);
+            config.host_names = site_host_names
+                .iter()
+                .map(|host_name| (*host_name).to_owned())
+                .collect();
+            let config = Arc::new(Mutex::new(config));
+            sites.add((*name).to_owned(), config.clone()).unwrap();
+            configs.insert((*name).to_owned(), config);
+        }
+        sites.reindex_hosts(&configs, "main");
+        sites
+    }
+
+    /// Name of the site that serves the request with the `Host` header
+    fn site_for(sites: &Sites, host: &str) -> String {
+        let request = TestRequest::default()
+            .insert_header(("Host", host))
+            .to_http_request();
+        let website = sites.website_for(&request);
+        sites
+            .websites()
+            .find(|(_name, other)| Arc::ptr_eq(other, &website))
+            .map(|(name, _website)| name.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn picks_sites_by_hosts() {
+        let root = tempfile::tempdir().unwrap();
+        let sites = sites(
+            &root,
+            &[
+                ("main", &["example.com"]),
+                ("notes", &["Notes.Example.com", "[::1]"]),
+            ],
+        );
+        assert_eq!(site_for(&sites, "notes.example.com"), "notes");
+        assert_eq!(site_for(&sites, "NOTES.example.COM:8080"), "notes");
+        assert_eq!(site_for(&sites, "[::1]:8080"), "notes");
+        assert_eq!(site_for(&sites, "[::1]"), "notes");
+        assert_eq!(site_for(&sites, "example.com"), "main");
+        assert_eq!(site_for(&sites, "unknown.example.com"), "main");
+        assert_eq!(site_for(&sites, "127.0.0.1:8080"), "main");
+    }
+
+    #[test]
+    fn the_last_site_wins_a_shared_host() {
+        let root = tempfile::tempdir().unwrap();
+        let sites = sites(
+            &root,
+            &[
+                ("main", &[]),
+                ("b", &["shared.example.com"]),
+                ("a", &["Shared.Example.com"]),
+            ],
+        );
+        // The sites are indexed in the order of their names, and the error message names the winner
+        assert_eq!(site_for(&sites, "shared.example.com"), "b");
+    }
+}
@@ -11,17 +11,16 @@ impl FileNameShortcut for Path {
 }
 
 pub fn set_global_log_level(log_level_name: impl AsRef<str>) -> Result<(), String> {
-    if let Ok(log_level_filter) = log::LevelFilter::from_str(log_level_name.as_ref()) {
-        log::set_max_level(log_level_filter);
-        Ok(())
-    } else {
-        Err(format!(
+    let log_level_filter = log::LevelFilter::from_str(log_level_name.as_ref()).map_err(|_| {
+        format!(
             "Log level not in [{}]!",
             itertools::Itertools::intersperse(
                 log::LevelFilter::iter().map(|variant| variant.as_str()),
                 ", "
             )
             .collect::<String>()
-        ))
-    }
+        )
+    })?;
+    log::set_max_level(log_level_filter);
+    Ok(())
 }
@@ -1,25 +1,46 @@
 use std::{
-    collections::{BTreeMap, HashMap},
-    sync::{Arc, Mutex, MutexGuard},
+    borrow::Borrow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::Hash,
+    io,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex, MutexGuard},
 };
 
 use chrono::{DateTime, Local};
-use log::error;
+use log::{error, warn};
 use rand::prelude::SliceRandom;
 
 use crate::{
-    config::Config,
+    assets::{parse_fingerprinted_path, Assets},
+    config::SiteConfig,
+    diagnostics::{Diagnostic, LinkCheck, Problem},
+    files::{decode_path, Lookup},
+    images::{cache_directory_for, parse_resized_path, ImageProcessor},
     page_compilers::{
-        compile_article, compile_index_variants, CompiledArticleInfo, ExtractBaseName,
+        compile_article, compile_index_variants, is_article, normalize_wiki_name,
+        CompiledArticleInfo, ExtractBaseName, OtherArticles, BUNDLE_INDEX,
     },
+    redirects::{Redirects, Resolution, Status},
 };
 
 pub type FileTime = DateTime<Local>;
 pub type ModificationTime = FileTime;
 pub type ArticleFileName = str;
+pub type Slug = str;
 struct MinimalArticleInfo {
     compiled_body: String,
     modification_time: Arc<FileTime>,
+    slug: Arc<Slug>,
+    aliases: Vec<Arc<str>>,
+    dependencies: Vec<PathBuf>,
+    title: Arc<str>,
+    links_to: BTreeSet<Arc<ArticleFileName>>,
+    missing_wiki_links: BTreeSet<String>,
+    problems: Vec<Problem>,
+    link_checks: Vec<LinkCheck>,
+    anchors: HashSet<String>,
+    external_links: BTreeSet<String>,
 }
 pub enum ArticleTitle {
     FromFileName(Arc<str>),
@@ -37,148 +58,980 @@ impl ArticleTitle {
 pub struct Website {
     compiled_articles: HashMap<Arc<ArticleFileName>, MinimalArticleInfo>,
     articles_list: BTreeMap<Arc<ModificationTime>, HashMap<Arc<ArticleFileName>, ArticleTitle>>,
+    /// Several articles can claim the same slug. That is reported, and the first file name (in
+    /// the alphabetical order) gets served
+    slugs: HashMap<Arc<Slug>, BTreeSet<Arc<ArticleFileName>>>,
+    /// Paths from the `aliases` of the articles
+    aliases: HashMap<Arc<str>, Arc<ArticleFileName>>,
+    redirects: Redirects,
+    /// Files that the articles depend on -> the articles
+    dependencies: HashMap<PathBuf, BTreeSet<Arc<ArticleFileName>>>,
+    /// Names that the wiki links find the articles by (see [`normalize_wiki_name`])
+    wiki_names: HashMap<String, BTreeSet<Arc<ArticleFileName>>>,
+    /// Article -> the articles that link to it
+    backlinks: HashMap<Arc<ArticleFileName>, BTreeSet<Arc<ArticleFileName>>>,
+    /// Targets of the wiki links that lead nowhere -> the articles with such links
+    wanted: HashMap<String, BTreeSet<Arc<ArticleFileName>>>,
+    assets: Assets,
     index_variants: Vec<String>,
-    config: Arc<Mutex<Config>>,
+    is_warming_up: bool,
+    /// What changed while the placeholder was served, see [`Self::finish_warming_up`]
+    pending_changes: Vec<PendingChange>,
+    config: Arc<Mutex<SiteConfig>>,
 }
+/// A change of the files that the warming-up placeholder can't react to
+enum PendingChange {
+    ArticlePath(PathBuf),
+    ArticleRenamed(PathBuf, PathBuf),
+    Dependencies(Vec<PathBuf>),
+    Everything,
+}
+
 pub struct IndexArticleInfo {
-    pub file_name: Arc<ArticleFileName>,
+    pub slug: Arc<Slug>,
     pub title: Arc<str>,
 }
 
+/// How similar (from 0 to 1) a title or a slug should be to a missing path to be suggested
+const SUGGESTION_THRESHOLD: f64 = 0.75;
+
+/// How far the changes of the links spread: an article changes, the articles that link to it
+/// change, and the articles that those link to change their "Linked from" sections
+const LINK_UPDATE_DEPTH: usize = 2;
+
+/// What the other articles show about an article: its slug and title (in their links and in their
+/// "Linked from" sections), and the articles that it links to
+type LinkState = (
+    Option<(Arc<Slug>, Arc<str>)>,
+    BTreeSet<Arc<ArticleFileName>>,
+);
+
+fn wiki_names_of(file_name: &Arc<ArticleFileName>, title: &str) -> [String; 3] {
+    [
+        normalize_wiki_name(title),
+        normalize_wiki_name(file_name),
+        normalize_wiki_name(&file_name.base_name()),
+    ]
+}
+
+/// Removes the article from the set of the key, and the key if the set becomes empty
+fn forget_article<Key: Eq + Hash + Borrow<Query>, Query: Eq + Hash + ?Sized>(
+    map: &mut HashMap<Key, BTreeSet<Arc<ArticleFileName>>>,
+    key: &Query,
+    file_name: &ArticleFileName,
+) {
+    if let Some(file_names) = map.get_mut(key) {
+        file_names.remove(file_name);
+        if file_names.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
 impl Website {
-    pub fn new(config: Arc<Mutex<Config>>) -> Self {
-        let mut instance = Self {
+    fn empty(
+        config: Arc<Mutex<SiteConfig>>,
+        is_warming_up: bool,
+        finished_images: Option<Sender<PathBuf>>,
+    ) -> Self {
+        let redirects = Redirects::load(config.lock().unwrap().articles_directory.as_ref());
+        Self {
             compiled_articles: HashMap::new(),
             articles_list: BTreeMap::new(),
+            slugs: HashMap::new(),
+            aliases: HashMap::new(),
+            redirects,
+            dependencies: HashMap::new(),
+            wiki_names: HashMap::new(),
+            backlinks: HashMap::new(),
+            wanted: HashMap::new(),
+            assets: Assets::new(ImageProcessor::new(finished_images)),
             index_variants: Vec::new(),
+            is_warming_up,
+            pending_changes: Vec::new(),
             config,
-        };
+        }
+    }
+
+    /// The paths of the images whose resized copies are ready are sent to `finished_images`, and
+    /// should be passed to [`Self::dependency_changed`]. Without it, the images are not resized
+    pub fn new(config: Arc<Mutex<SiteConfig>>, finished_images: Option<Sender<PathBuf>>) -> Self {
+        let mut instance = Self::empty(config, false, finished_images);
         instance.reload_articles_and_index();
         instance
     }
 
+    /// A placeholder that is served while the articles are being compiled for the first time
+    pub fn warming_up(config: Arc<Mutex<SiteConfig>>) -> Self {
+        Self::empty(config, true, None)
+    }
+
+    pub const fn is_warming_up(&self) -> bool {
+        self.is_warming_up
+    }
+
+    /// Replaces the placeholder with the compiled website, and applies the changes that happened
+    /// during the compilation (it could have read the files before they changed)
+    pub fn finish_warming_up(&mut self, compiled: Self) {
+        let pending_changes = std::mem::take(&mut self.pending_changes);
+        *self = compiled;
+        if pending_changes
+            .iter()
+            .any(|change| matches!(change, PendingChange::Everything))
+        {
+            self.reload_articles_and_index();
+            return;
+        }
+        for change in pending_changes {
+            match change {
+                PendingChange::ArticlePath(path) => self.article_path_changed(&path),
+                PendingChange::ArticleRenamed(from, to) => self.article_path_renamed(&from, &to),
+                PendingChange::Dependencies(paths) => self.dependency_changed(&paths),
+                PendingChange::Everything => unreachable!(),
+            }
+        }
+    }
+
+    /// Articles that can be reached, the newest first. The articles that lost their slugs to
+    /// others are not reachable
+    fn served_articles(&self) -> impl Iterator<Item = IndexArticleInfo> + '_ {
+        self.articles_list
+            .values()
+            .rev()
+            .flat_map(|articles_map| {
+                articles_map.iter().map(|(file_name, title)| {
+                    (file_name, &self.compiled_articles[file_name].slug, title)
+                })
+            })
+            .filter(|(file_name, _slug, _title)| self.is_served(file_name))
+            .map(|(_file_name, slug, title)| IndexArticleInfo {
+                slug: slug.clone(),
+                title: title.clone_contents(),
+            })
+    }
+
     pub fn reload_index_variants(&mut self) {
         let index_variants = compile_index_variants(
-            &self
-                .articles_list
-                .values()
-                .rev()
-                .flat_map(|articles_map| {
-                    articles_map
-                        .iter()
-                        .map(|(file_name, title)| IndexArticleInfo {
-                            file_name: file_name.clone(),
-                            title: title.clone_contents(),
-                        })
-                })
-                .collect::<Vec<_>>(),
+            &self.served_articles().collect::<Vec<_>>(),
             &self.lock_config(),
         );
         self.index_variants = index_variants;
     }
 
-    pub fn get_article(&self, file_name: &Arc<ArticleFileName>) -> Option<String> {
+    /// Articles whose slugs or titles look like the missing `path`, the closest first
+    pub fn suggestions(&self, path: &str, limit: usize) -> Vec<IndexArticleInfo> {
+        let path = path.trim_matches('/').to_lowercase();
+        let mut suggestions: Vec<(f64, IndexArticleInfo)> = self
+            .served_articles()
+            .map(|article| {
+                let similarity = strsim::jaro_winkler(&path, &article.slug)
+                    .max(strsim::jaro_winkler(&path, &article.title.to_lowercase()));
+                (similarity, article)
+            })
+            .filter(|(similarity, _article)| *similarity >= SUGGESTION_THRESHOLD)
+            .collect();
+        suggestions.sort_by(|(first, _), (second, _)| second.total_cmp(first));
+        suggestions
+            .into_iter()
+            .take(limit)
+            .map(|(_similarity, article)| article)
+            .collect()
+    }
+
+    pub fn get_article(&self, slug: &Slug) -> Option<String> {
+        self.slugs
+            .get(slug)
+            .and_then(|file_names| file_names.iter().next())
+            .map(|file_name| self.compiled_articles[file_name].compiled_body.clone())
+    }
+
+    /// Used to redirect the links from the times when the articles were served by their file names
+    pub fn slug_of(&self, file_name: &ArticleFileName) -> Option<Arc<Slug>> {
         self.compiled_articles
             .get(file_name)
-            .map(|minimal_article_info| minimal_article_info.compiled_body.clone())
+            .map(|article_info| article_info.slug.clone())
     }
 
+    /// There is a variant even without `index_page_colors` (in the default colors)
     pub fn get_index_page(&self) -> &String {
         self.index_variants.choose(&mut rand::thread_rng()).unwrap()
     }
 
-    pub fn remove_article(&mut self, file_name: &Arc<ArticleFileName>) {
-        let article_info = self.compiled_articles.remove(file_name).unwrap();
+    /// Forgets the article, returning whether it was there
+    fn remove_without_index_reload(&mut self, file_name: &Arc<ArticleFileName>) -> bool {
+        let Some(article_info) = self.compiled_articles.remove(file_name) else {
+            return false;
+        };
         let articles_map = self
             .articles_list
             .get_mut(&article_info.modification_time)
             .unwrap();
-        articles_map.remove(file_name).unwrap();
+        articles_map.remove(file_name);
         if articles_map.is_empty() {
             self.articles_list.remove(&article_info.modification_time);
         }
+        let file_names = self.slugs.get_mut(&article_info.slug).unwrap();
+        file_names.remove(file_name);
+        if file_names.is_empty() {
+            self.slugs.remove(&article_info.slug);
+        }
+        for alias in &article_info.aliases {
+            if self.aliases.get(alias) == Some(file_name) {
+                self.aliases.remove(alias);
+            }
+        }
+        for dependency in &article_info.dependencies {
+            forget_article(&mut self.dependencies, dependency, file_name);
+        }
+        for name in wiki_names_of(file_name, &article_info.title) {
+            forget_article(&mut self.wiki_names, &name, file_name);
+        }
+        for target in &article_info.links_to {
+            forget_article(&mut self.backlinks, target, file_name);
+        }
+        for target in &article_info.missing_wiki_links {
+            forget_article(&mut self.wanted, target, file_name);
+        }
+        true
+    }
+
+    fn link_state(&self, file_name: &ArticleFileName) -> LinkState {
+        self.compiled_articles.get(file_name).map_or_else(
+            || (None, BTreeSet::new()),
+            |article_info| {
+                (
+                    Some((article_info.slug.clone(), article_info.title.clone())),
+                    article_info.links_to.clone(),
+                )
+            },
+        )
+    }
+
+    /// Compiles again the articles whose links or "Linked from" sections are changed by the
+    /// change of the article (which was `before`), and then follows their changes
+    fn propagate_link_changes(
+        &mut self,
+        file_name: &Arc<ArticleFileName>,
+        before: LinkState,
+        depth: usize,
+    ) {
+        let after = self.link_state(file_name);
+        if before == after || depth == 0 {
+            return;
+        }
+        let ((before_identity, before_targets), (after_identity, after_targets)) = (before, after);
+        let mut affected: BTreeSet<Arc<ArticleFileName>> = before_targets
+            .symmetric_difference(&after_targets)
+            .cloned()
+            .collect();
+        if before_identity != after_identity {
+            affected.extend(before_targets.iter().chain(&after_targets).cloned());
+            affected.extend(self.backlinks.get(file_name).into_iter().flatten().cloned());
+            for (_slug, title) in before_identity.iter().chain(&after_identity) {
+                for name in wiki_names_of(file_name, title) {
+                    affected.extend(self.wanted.get(&name).into_iter().flatten().cloned());
+                }
+            }
+        }
+        affected.remove(file_name);
+        for other_file_name in affected {
+            let before = self.link_state(&other_file_name);
+            // Links to the articles that are gone
+            if before.0.is_none() {
+                continue;
+            }
+            self.update_without_index_reload(&other_file_name);
+            self.propagate_link_changes(&other_file_name, before, depth - 1);
+        }
+    }
+
+    /// Compiles the article again along with the articles that its links affect
+    fn update_with_links(&mut self, file_name: &Arc<ArticleFileName>) {
+        let before = self.link_state(file_name);
+        self.update_without_index_reload(file_name);
+        self.propagate_link_changes(file_name, before, LINK_UPDATE_DEPTH);
+    }
+
+    /// Whether the article is served at its slug (it can lose the slug to another article)
+    fn is_served(&self, file_name: &ArticleFileName) -> bool {
+        self.compiled_articles
+            .get(file_name)
+            .and_then(|article_info| self.slugs.get(&article_info.slug))
+            .and_then(|file_names| file_names.iter().next())
+            .is_some_and(|first| &**first == file_name)
+    }
+
+    /// The article that the alias leads to. The alias of an article that lost its slug leads
+    /// nowhere, so it doesn't send the readers to another article
+    fn article_of_alias(&self, alias: &str) -> Option<&Arc<ArticleFileName>> {
+        self.aliases
+            .get(alias)
+            .filter(|file_name| self.is_served(file_name))
+    }
+
+    /// Why the alias of the article doesn't lead to it, if it doesn't
+    fn alias_conflict(&self, alias: &str, file_name: &ArticleFileName) -> Option<String> {
+        let served_at = |path: &str| {
+            self.slugs
+                .get(path)
+                .and_then(|file_names| file_names.iter().next())
+                .filter(|other| &***other != file_name)
+        };
+        if let Some(other) = served_at(alias) {
+            return Some(format!("`{other}` is served at the same path"));
+        }
+        if let Some(other) = self
+            .aliases
+            .get(alias)
+            .filter(|other| &***other != file_name)
+        {
+            return Some(format!("`{other}` has the same alias"));
+        }
+        let slug = &self.compiled_articles.get(file_name)?.slug;
+        served_at(slug).map(|other| format!("`{other}` is served at slug `{slug}` instead"))
+    }
+
+    /// The article that is served at the path
+    fn article_at(&self, path: &str) -> Option<&Arc<ArticleFileName>> {
+        self.slugs
+            .get(path)
+            .and_then(|file_names| file_names.iter().next())
+            .or_else(|| self.article_of_alias(path))
+    }
+
+    /// Whether a link to the path leads somewhere: to an article, a file (maybe fingerprinted or
+    /// resized), a file of a page bundle, or through a redirect
+    fn path_exists(&self, path: &str) -> bool {
+        // As the server would decode the request
+        let Some(path) = decode_path(path) else {
+            return false;
+        };
+        let path = path.trim_matches('/');
+        if self.is_live(path) {
+            return true;
+        }
+        let config = self.lock_config();
+        if let Some((original_path, _hash)) = parse_fingerprinted_path(path) {
+            return matches!(
+                config.files_policy().lookup(&original_path),
+                Lookup::File(_)
+            );
+        }
+        if let Some(file_name) = parse_resized_path(path) {
+            return cache_directory_for(&config.files_directory)
+                .join(file_name)
+                .is_file();
+        }
+        drop(config);
+        if let Some((slug, relative_path)) = path.split_once('/') {
+            if !matches!(self.bundle_file(slug, relative_path), Lookup::NotFound) {
+                return true;
+            }
+        }
+        match self.redirects.resolve(path, |path| self.is_live(path)) {
+            Resolution::Target(target) => target.contains("://") || self.is_live(&target),
+            Resolution::Cycle(_) | Resolution::Gone | Resolution::NotFound => false,
+        }
+    }
+
+    /// Problems of the article: broken links, duplicate titles and so on
+    fn diagnostics_of(&self, file_name: &Arc<ArticleFileName>) -> Vec<Diagnostic> {
+        let Some(article_info) = self.compiled_articles.get(file_name) else {
+            return Vec::new();
+        };
+        let mut problems = article_info.problems.clone();
+        for check in &article_info.link_checks {
+            match check {
+                LinkCheck::Anchor {
+                    link,
+                    article,
+                    anchor,
+                } => {
+                    // A link to an article that is gone is reported as a broken link
+                    let target = self
+                        .compiled_articles
+                        .get(article.as_ref().unwrap_or(file_name));
+                    if target.is_some_and(|target| !target.anchors.contains(anchor)) {
+                        problems.push(Problem::MissingAnchor(link.clone()));
+                    }
+                }
+                LinkCheck::Path { link, path, anchor } => {
+                    if !self.path_exists(path) {
+                        problems.push(Problem::BrokenLink(link.clone()));
+                    } else if !anchor.is_empty() {
+                        let target = self
+                            .article_at(path.trim_matches('/'))
+                            .and_then(|target| self.compiled_articles.get(target));
+                        if target.is_some_and(|target| !target.anchors.contains(anchor)) {
+                            problems.push(Problem::MissingAnchor(link.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        for alias in &article_info.aliases {
+            if let Some(details) = self.alias_conflict(alias, file_name) {
+                problems.push(Problem::ConflictingAlias {
+                    alias: alias.clone(),
+                    details,
+                });
+            }
+        }
+        problems.extend(
+            article_info
+                .missing_wiki_links
+                .iter()
+                .cloned()
+                .map(Problem::MissingWikiLink),
+        );
+        let mut others: Vec<Arc<ArticleFileName>> = self
+            .compiled_articles
+            .iter()
+            .filter(|(other_file_name, other_info)| {
+                other_info.title == article_info.title && *other_file_name != file_name
+            })
+            .map(|(other_file_name, _other_info)| other_file_name.clone())
+            .collect();
+        if !others.is_empty() {
+            others.sort();
+            problems.push(Problem::DuplicateTitle {
+                title: article_info.title.clone(),
+                others,
+            });
+        }
+        problems
+            .into_iter()
+            .map(|problem| Diagnostic {
+                file_name: file_name.clone(),
+                problem,
+            })
+            .collect()
+    }
+
+    /// Problems of every article, by the file names
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut file_names: Vec<_> = self.compiled_articles.keys().collect();
+        file_names.sort();
+        file_names
+            .into_iter()
+            .flat_map(|file_name| self.diagnostics_of(file_name))
+            .collect()
+    }
+
+    /// Links to the other sites -> the articles with them
+    pub fn external_links(&self) -> BTreeMap<String, BTreeSet<Arc<ArticleFileName>>> {
+        let mut external_links: BTreeMap<String, BTreeSet<Arc<ArticleFileName>>> = BTreeMap::new();
+        for (file_name, article_info) in &self.compiled_articles {
+            for link in &article_info.external_links {
+                external_links
+                    .entry(link.clone())
+                    .or_default()
+                    .insert(file_name.clone());
+            }
+        }
+        external_links
+    }
+
+    fn report_diagnostics(&self, file_name: &Arc<ArticleFileName>) {
+        for diagnostic in self.diagnostics_of(file_name) {
+            warn!("{}", diagnostic);
+        }
+    }
+
+    /// Articles that link to the article, by their titles
+    fn linked_from(&self, file_name: &ArticleFileName) -> Vec<IndexArticleInfo> {
+        let mut linked_from: Vec<IndexArticleInfo> = self
+            .backlinks
+            .get(file_name)
+            .into_iter()
+            .flatten()
+            .filter(|source| &***source != file_name)
+            .filter_map(|source| self.compiled_articles.get(source))
+            .map(|article_info| IndexArticleInfo {
+                slug: article_info.slug.clone(),
+                title: article_info.title.clone(),
+            })
+            .collect();
+        linked_from.sort_by(|first, second| first.title.cmp(&second.title));
+        linked_from
+    }
+
+    /// Compiles again the articles that depend on the changed files
+    pub fn dependency_changed(&mut self, paths: &[PathBuf]) {
+        if self.is_warming_up {
+            self.pending_changes
+                .push(PendingChange::Dependencies(paths.to_vec()));
+            return;
+        }
+        let mut dependent_articles = BTreeSet::new();
+        for path in paths {
+            self.assets.forget(path);
+            if let Some(file_names) = self.dependencies.get(path) {
+                dependent_articles.extend(file_names.iter().cloned());
+            }
+        }
+        if dependent_articles.is_empty() {
+            return;
+        }
+        for file_name in &dependent_articles {
+            self.update_with_links(file_name);
+        }
         self.reload_index_variants();
     }
 
+    /// Directories of the files that the articles depend on (the included ones), which are
+    /// outside of the articles, the files and the theme directories, so nothing else watches them
+    pub fn outside_directories(&self) -> BTreeSet<PathBuf> {
+        let watched_directories = {
+            let config = self.lock_config();
+            [
+                Some(Path::to_path_buf(config.articles_directory.as_ref())),
+                Some(config.files_directory.clone()),
+                config.theme_directory.clone(),
+            ]
+        };
+        self.dependencies
+            .keys()
+            .filter(|path| {
+                !watched_directories
+                    .iter()
+                    .flatten()
+                    .any(|directory| path.starts_with(directory))
+            })
+            .filter_map(|path| path.parent().map(Path::to_path_buf))
+            .collect()
+    }
+
+    /// Current hash of a file from the files directory
+    pub fn asset_hash(&mut self, path: &Path) -> Option<String> {
+        self.assets.hash_of(path)
+    }
+
+    /// A file of the page bundle of the article with the slug. The article itself is not served
+    pub fn bundle_file(&self, slug: &Slug, relative_path: &str) -> Lookup {
+        let Some(file_name) = self
+            .slugs
+            .get(slug)
+            .and_then(|file_names| file_names.iter().next())
+        else {
+            return Lookup::NotFound;
+        };
+        let config = self.lock_config();
+        let bundle_directory = config.articles_directory.as_ref().join(&file_name[..]);
+        if !bundle_directory.is_dir() || relative_path.trim_matches('/') == BUNDLE_INDEX {
+            return Lookup::NotFound;
+        }
+        config
+            .bundle_policy(&bundle_directory)
+            .lookup(relative_path)
+    }
+
+    /// Name of the article that the path belongs to: the article file itself or the page bundle
+    /// that contains the path
+    fn article_file_name_of(&self, path: &Path) -> Option<Arc<ArticleFileName>> {
+        let relative_path = path
+            .strip_prefix(self.lock_config().articles_directory.as_ref())
+            .ok()?;
+        let first_component = relative_path.components().next()?;
+        Some(first_component.as_os_str().to_str()?.into())
+    }
+
+    /// Reacts to a change of an article file or of a file of a page bundle
+    pub fn article_path_changed(&mut self, path: &Path) {
+        if self.is_warming_up {
+            self.pending_changes
+                .push(PendingChange::ArticlePath(path.to_owned()));
+            return;
+        }
+        let Some(file_name) = self.article_file_name_of(path) else {
+            return;
+        };
+        let article_path = self
+            .lock_config()
+            .articles_directory
+            .as_ref()
+            .join(&file_name[..]);
+        // The other files (the data files and the included ones) only affect the articles that
+        // use them, and a directory without `index.md` stops being an article
+        if is_article(&article_path) {
+            self.update_article(&file_name);
+        } else {
+            self.remove_article(&file_name);
+        }
+        self.relink(&file_name);
+        // The other articles can include the files of the page bundles
+        if path != article_path && path != article_path.join(BUNDLE_INDEX) {
+            self.dependency_changed(&[path.to_owned()]);
+        }
+    }
+
+    /// Compiles again the articles that link to the article's Markdown file, because its slug may
+    /// have changed (or the article may be gone)
+    fn relink(&mut self, file_name: &ArticleFileName) {
+        let article_path = self
+            .lock_config()
+            .articles_directory
+            .as_ref()
+            .join(file_name);
+        self.dependency_changed(&[article_path.join(BUNDLE_INDEX), article_path]);
+    }
+
+    /// Renaming an article file or a page bundle renames the article, and moving the files between
+    /// the bundles changes both of them
+    pub fn article_path_renamed(&mut self, from: &Path, to: &Path) {
+        if self.is_warming_up {
+            self.pending_changes.push(PendingChange::ArticleRenamed(
+                from.to_owned(),
+                to.to_owned(),
+            ));
+            return;
+        }
+        let is_top_level = |website: &Self, path: &Path| {
+            path.parent() == Some(website.lock_config().articles_directory.as_ref())
+        };
+        if let (true, true, true, Some(from_file_name), Some(to_file_name)) = (
+            is_top_level(self, from),
+            is_top_level(self, to),
+            // Renaming `notes.md` to `notes.txt` unpublishes the article
+            is_article(to),
+            self.article_file_name_of(from),
+            self.article_file_name_of(to),
+        ) {
+            self.rename_article(&from_file_name, &to_file_name);
+            self.relink(&from_file_name);
+            self.relink(&to_file_name);
+        } else {
+            self.article_path_changed(from);
+            self.article_path_changed(to);
+        }
+    }
+
+    /// Forgets the article, remembering its slug as deleted (unless another article has it)
+    pub fn remove_article(&mut self, file_name: &Arc<ArticleFileName>) {
+        let slug = self.slug_of(file_name);
+        let before = self.link_state(file_name);
+        if self.remove_without_index_reload(file_name) {
+            self.propagate_link_changes(file_name, before, LINK_UPDATE_DEPTH);
+            if let Some(slug) = slug {
+                if !self.slugs.contains_key(&slug) {
+                    self.redirects.record_removal(&slug);
+                }
+            }
+            self.reload_index_variants();
+        }
+    }
+
+    /// The article is compiled again, because its slug (and the title, if there's no heading)
+    /// depend on the file name. If the slug changes, the old one is redirected to the new one
     pub fn rename_article(
         &mut self,
         old_file_name: &Arc<ArticleFileName>,
-        new_file_name: Arc<ArticleFileName>,
+        new_file_name: &Arc<ArticleFileName>,
     ) {
-        let article_info = self.compiled_articles.remove(old_file_name).unwrap();
-        {
-            let articles_map = self
-                .articles_list
-                .get_mut(&article_info.modification_time)
-                .unwrap();
-            let mut article_title = articles_map.remove(old_file_name).unwrap();
-            if matches!(article_title, ArticleTitle::FromFileName(..)) {
-                article_title = ArticleTitle::FromFileName(new_file_name.base_name());
+        let old_slug = self.slug_of(old_file_name);
+        let before = self.link_state(old_file_name);
+        self.remove_without_index_reload(old_file_name);
+        self.propagate_link_changes(old_file_name, before, LINK_UPDATE_DEPTH);
+        self.update_with_links(new_file_name);
+        if let (Some(old_slug), Some(new_slug)) = (old_slug, self.slug_of(new_file_name)) {
+            if !self.slugs.contains_key(&old_slug) {
+                self.redirects.record(&old_slug, &new_slug);
             }
-            articles_map.insert(new_file_name.clone(), article_title);
         }
-        self.compiled_articles.insert(new_file_name, article_info);
         self.reload_index_variants();
     }
 
-    fn update_without_index_reload(&mut self, file_name: &Arc<ArticleFileName>) {
-        if let Some(article_info) = self.compiled_articles.get_mut(file_name) {
-            self.articles_list
-                .get_mut(&article_info.modification_time)
-                .unwrap()
-                .remove(file_name);
+    /// Whether there's an article or a file at the path
+    fn is_live(&self, path: &str) -> bool {
+        self.slugs.contains_key(path)
+            || self.article_of_alias(path).is_some()
+            || matches!(
+                self.lock_config().files_policy().lookup(path),
+                Lookup::File(_) | Lookup::Directory(_)
+            )
+    }
+
+    /// Where a request for a missing page should be redirected: a path relative to the site's root
+    /// or an absolute URL (or whether the page was deleted). Aliases of the articles come first,
+    /// then the redirects file. Cycles are reported and treated as missing redirects
+    pub fn resolve_redirect(&mut self, path: &str) -> Resolution {
+        let path = path.trim_matches('/');
+        if let Some(file_name) = self.article_of_alias(path) {
+            return Resolution::Target(self.compiled_articles[file_name].slug.to_string());
         }
+        self.redirects.refresh();
+        match self.redirects.resolve(path, |path| self.is_live(path)) {
+            Resolution::Cycle(chain) => {
+                error!(
+                    "Redirects from `{}` form a cycle ({})! Check `{:?}`",
+                    path,
+                    chain.join(" -> "),
+                    self.redirects.path()
+                );
+                Resolution::NotFound
+            }
+            resolution => resolution,
+        }
+    }
+
+    /// Every redirect from the redirects file (`None` marks the deleted pages), and whether it
+    /// works
+    pub fn redirect_statuses(&mut self) -> Vec<(String, Option<String>, Status)> {
+        self.redirects.refresh();
+        self.redirects
+            .iter()
+            .map(|(from, to)| {
+                let status = if self.is_live(from) {
+                    Status::Shadowed
+                } else {
+                    match self.redirects.resolve(from, |path| self.is_live(path)) {
+                        Resolution::Cycle(chain) => Status::Cycle(chain),
+                        Resolution::Gone => Status::Gone,
+                        Resolution::Target(target)
+                            if target.contains("://") || self.is_live(&target) =>
+                        {
+                            Status::Working(target)
+                        }
+                        Resolution::Target(target) => Status::Dead(target),
+                        Resolution::NotFound => unreachable!(),
+                    }
+                };
+                (from.clone(), to.clone(), status)
+            })
+            .collect()
+    }
+
+    /// Removes the redirects that don't work, returning them
+    ///
+    /// # Errors
+    /// Returns an error if the redirects file cannot be written.
+    pub fn prune_redirects(&mut self) -> io::Result<Vec<(String, Option<String>, Status)>> {
+        let broken: Vec<_> = self
+            .redirect_statuses()
+            .into_iter()
+            .filter(|(_from, _to, status)| !status.is_working())
+            .collect();
+        if !broken.is_empty() {
+            for (from, _to, _status) in &broken {
+                self.redirects.remove(from);
+            }
+            self.redirects.save()?;
+        }
+        Ok(broken)
+    }
+
+    pub fn redirects_path(&self) -> &Path {
+        self.redirects.path()
+    }
+
+    fn update_without_index_reload(&mut self, file_name: &Arc<ArticleFileName>) {
+        self.remove_without_index_reload(file_name);
         let full_path = self
             .lock_config()
             .articles_directory
             .as_ref()
             .join(&file_name[..]);
-        let compiled_article_info = compile_article(&full_path, &self.lock_config());
+        let linked_from = self.linked_from(file_name);
+        let (wiki_names, compiled_articles) = (&self.wiki_names, &self.compiled_articles);
+        let resolve_wiki_link = |target: &str| {
+            let file_name = wiki_names
+                .get(&normalize_wiki_name(target))?
+                .iter()
+                .next()?;
+            Some((file_name.clone(), compiled_articles[file_name].slug.clone()))
+        };
+        let compiled_article_info = compile_article(
+            &full_path,
+            &self.config.lock().unwrap(),
+            &mut self.assets,
+            &OtherArticles {
+                resolve_wiki_link: &resolve_wiki_link,
+                linked_from,
+            },
+        );
         if let Ok(CompiledArticleInfo {
             body,
             file_name,
+            slug,
+            aliases,
+            dependencies,
+            links_to,
+            missing_wiki_links,
+            problems,
+            link_checks,
+            anchors,
+            external_links,
             modification_time,
             title,
         }) = compiled_article_info
         {
+            self.check_slug(&slug, &file_name);
+            for dependency in dependencies.iter().cloned() {
+                self.dependencies
+                    .entry(dependency)
+                    .or_default()
+                    .insert(file_name.clone());
+            }
+            self.redirects.forget_removal(&slug);
+            self.record_aliases(&aliases, &file_name);
+            self.slugs
+                .entry(slug.clone())
+                .or_default()
+                .insert(file_name.clone());
+            for name in wiki_names_of(&file_name, &title.clone_contents()) {
+                self.wiki_names
+                    .entry(name)
+                    .or_default()
+                    .insert(file_name.clone());
+            }
+            let links_to: BTreeSet<_> = links_to.into_iter().collect();
+            for target in &links_to {
+                self.backlinks
+                    .entry(target.clone())
+                    .or_default()
+                    .insert(file_name.clone());
+            }
+            let missing_wiki_links: BTreeSet<_> = missing_wiki_links.into_iter().collect();
+            for target in &missing_wiki_links {
+                self.wanted
+                    .entry(target.clone())
+                    .or_default()
+                    .insert(file_name.clone());
+            }
             let modification_time = Arc::new(modification_time);
             self.compiled_articles.insert(
                 file_name.clone(),
                 MinimalArticleInfo {
                     compiled_body: body,
                     modification_time: modification_time.clone(),
+                    slug,
+                    aliases,
+                    dependencies,
+                    title: title.clone_contents(),
+                    links_to,
+                    missing_wiki_links,
+                    problems,
+                    link_checks,
+                    anchors: anchors.into_iter().collect(),
+                    external_links: external_links.into_iter().collect(),
                 },
             );
             self.articles_list
                 .entry(modification_time)
-                .or_insert_with(HashMap::new)
+                .or_default()
                 .insert(file_name, title);
         }
     }
 
+    /// Serves the article at its aliases, reporting the aliases that other articles list too
+    fn record_aliases(&mut self, aliases: &[Arc<str>], file_name: &Arc<ArticleFileName>) {
+        for alias in aliases {
+            if let Some(other_file_name) = self.aliases.insert(alias.clone(), file_name.clone()) {
+                if other_file_name != *file_name {
+                    error!(
+                        "Alias `{}` is listed by both `{}` and `{}`! Using `{}`",
+                        alias, other_file_name, file_name, file_name
+                    );
+                }
+            }
+        }
+    }
+
+    /// Reports the collisions of a new slug with the other articles and with the files
+    fn check_slug(&self, slug: &Slug, file_name: &ArticleFileName) {
+        if let Some(file_names) = self.slugs.get(slug) {
+            error!(
+                "Slug `{}` of article `{}` is already taken by {}! Only `{}` will be served",
+                slug,
+                file_name,
+                itertools::Itertools::intersperse(
+                    file_names.iter().map(|file_name| format!("`{file_name}`")),
+                    ", ".to_owned()
+                )
+                .collect::<String>(),
+                file_names
+                    .iter()
+                    .next()
+                    .map_or(file_name, |first| std::cmp::min(&**first, file_name))
+            );
+        }
+        let file_path = self.lock_config().files_directory.join(slug);
+        if file_path.exists() {
+            error!(
+                "Slug `{}` of article `{}` is the same as the name of `{:?}` from the files \
+                directory! The article will be served instead of the file",
+                slug, file_name, file_path
+            );
+        }
+    }
+
     pub fn update_article(&mut self, file_name: &Arc<ArticleFileName>) {
-        self.update_without_index_reload(file_name);
+        self.update_with_links(file_name);
+        self.report_diagnostics(file_name);
         self.reload_index_variants();
     }
 
-    fn lock_config(&self) -> MutexGuard<Config> {
+    fn lock_config(&self) -> MutexGuard<'_, SiteConfig> {
         self.config.lock().unwrap()
     }
 
-    pub const fn config(&self) -> &Arc<Mutex<Config>> {
+    pub const fn config(&self) -> &Arc<Mutex<SiteConfig>> {
         &self.config
     }
 
+    pub fn articles_count(&self) -> usize {
+        self.compiled_articles.len()
+    }
+
+    /// Saves what isn't saved yet (the redirects that couldn't be written)
+    pub fn flush(&mut self) {
+        self.redirects.flush();
+    }
+
     pub fn reload_articles(&mut self) {
+        if self.is_warming_up {
+            self.pending_changes.push(PendingChange::Everything);
+            return;
+        }
+        // The redirects are read again below
+        self.redirects.flush();
         self.articles_list = BTreeMap::new();
         self.compiled_articles = HashMap::new();
+        self.slugs = HashMap::new();
+        self.aliases = HashMap::new();
+        self.dependencies = HashMap::new();
+        self.wiki_names = HashMap::new();
+        self.backlinks = HashMap::new();
+        self.wanted = HashMap::new();
+        let redirects = Redirects::load(self.lock_config().articles_directory.as_ref());
+        self.redirects = redirects;
         let articles_directory_contents = self.lock_config().articles_directory.as_ref().read_dir();
         if let Ok(article_file_names) = articles_directory_contents {
             for entry in article_file_names {
-                let file_name: Arc<ArticleFileName> =
-                    entry.unwrap().file_name().to_str().unwrap().into();
+                let entry = entry.unwrap();
+                if !is_article(&entry.path()) {
+                    continue;
+                }
+                let file_name: Arc<ArticleFileName> = entry.file_name().to_str().unwrap().into();
                 self.update_without_index_reload(&file_name);
             }
+            // The wiki links to the articles that were compiled later, and the "Linked from"
+            // sections
+            let incomplete: BTreeSet<Arc<ArticleFileName>> = self
+                .wanted
+                .values()
+                .flatten()
+                .chain(self.backlinks.keys())
+                .cloned()
+                .collect();
+            for file_name in &incomplete {
+                if self.compiled_articles.contains_key(file_name) {
+                    self.update_with_links(file_name);
+                }
+            }
+            for diagnostic in self.diagnostics() {
+                warn!("{}", diagnostic);
+            }
         } else {
             error!(
                 "Articles directory `{:?}` was not found! Cannot reload the articles, \
@@ -193,3 +1046,197 @@ impl Website {
         self.reload_index_variants();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    use super::Website;
+    use crate::{config::SiteConfig, diagnostics::Problem, redirects::Resolution};
+
+    /// A website with the articles (relative to the articles directory). The images are not
+    /// resized, and the files are not fingerprinted
+    fn website(articles: &[(&str, &str)]) -> (tempfile::TempDir, Website) {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("articles")).unwrap();
+        fs::create_dir(root.path().join("files")).unwrap();
+        for (path, contents) in articles {
+            let path = root.path().join("articles").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        let mut config = SiteConfig::for_tests(root.path());
+        config.fingerprint_files = false;
+        config.image_widths = Vec::new();
+        let website = Website::new(Arc::new(Mutex::new(config)), None);
+        (root, website)
+    }
+
+    fn article(website: &Website, slug: &str) -> String {
+        website.get_article(slug).expect("The article is not there")
+    }
+
+    /// Titles of the articles on the index page, in its order
+    fn served_titles(website: &Website) -> Vec<String> {
+        website
+            .served_articles()
+            .map(|article| article.title.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn the_first_file_name_wins_the_slug() {
+        let (root, mut website) = website(&[
+            ("beta.md", "---\nslug: same\n---\n# Beta"),
+            ("alpha.md", "---\nslug: same\n---\n# Alpha"),
+            ("my-post.md", "# Lower"),
+            ("My Post.md", "# Upper"),
+        ]);
+        assert!(article(&website, "same").contains("<h1>Alpha</h1>"));
+        // Both make `my-post`, and the upper case comes first
+        assert_eq!(website.slug_of("my-post.md").as_deref(), Some("my-post"));
+        assert!(article(&website, "my-post").contains("<h1>Upper</h1>"));
+        let mut titles = served_titles(&website);
+        titles.sort();
+        assert_eq!(titles, ["Alpha", "Upper"]);
+        let alpha_path = root.path().join("articles/alpha.md");
+        fs::remove_file(&alpha_path).unwrap();
+        website.article_path_changed(&alpha_path);
+        assert!(article(&website, "same").contains("<h1>Beta</h1>"));
+        fs::write(&alpha_path, "---\nslug: same\n---\n# Alpha again").unwrap();
+        website.article_path_changed(&alpha_path);
+        assert!(article(&website, "same").contains("<h1>Alpha again</h1>"));
+        assert!(!served_titles(&website).contains(&"Beta".to_owned()));
+    }
+
+    fn redirect(website: &mut Website, path: &str) -> Option<String> {
+        match website.resolve_redirect(path) {
+            Resolution::Target(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn renaming_records_a_redirect() {
+        let (root, mut website) = website(&[("Old Name.md", "# Post"), ("Kept.md", "# Kept")]);
+        let articles = root.path().join("articles");
+        fs::rename(articles.join("Old Name.md"), articles.join("New Name.md")).unwrap();
+        website.article_path_renamed(&articles.join("Old Name.md"), &articles.join("New Name.md"));
+        assert!(website.get_article("old-name").is_none());
+        assert!(website.get_article("new-name").is_some());
+        assert_eq!(
+            redirect(&mut website, "/old-name/").as_deref(),
+            Some("new-name")
+        );
+        // The slug is the same, so there's nothing to redirect
+        fs::write(articles.join("kept.md"), "# Kept").unwrap();
+        fs::remove_file(articles.join("Kept.md")).unwrap();
+        website.article_path_renamed(&articles.join("Kept.md"), &articles.join("kept.md"));
+        assert_eq!(redirect(&mut website, "kept"), None);
+        let saved = fs::read_to_string(root.path().join("articles.redirects.json")).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&saved).unwrap(),
+            serde_json::json!({ "old-name": "new-name" })
+        );
+    }
+
+    #[test]
+    fn aliases_dont_lead_to_other_articles() {
+        let (_root, mut website) = website(&[
+            ("a.md", "---\nslug: same\naliases: [old-a]\n---\n# A"),
+            ("b.md", "---\nslug: same\naliases: [old-b, c]\n---\n# B"),
+            ("c.md", "# C"),
+        ]);
+        assert_eq!(redirect(&mut website, "old-a").as_deref(), Some("same"));
+        // `b.md` lost `same` to `a.md`
+        assert_eq!(redirect(&mut website, "old-b"), None);
+        let conflicts: Vec<_> = website
+            .diagnostics()
+            .into_iter()
+            .filter(|diagnostic| matches!(diagnostic.problem, Problem::ConflictingAlias { .. }))
+            .map(|diagnostic| diagnostic.to_string())
+            .collect();
+        assert!(conflicts.contains(
+            &"`b.md`: alias `old-b` doesn't lead to the article. Details: `a.md` is served at \
+              slug `same` instead"
+                .to_owned()
+        ));
+        assert!(conflicts.contains(
+            &"`b.md`: alias `c` doesn't lead to the article. Details: `c.md` is served at the \
+              same path"
+                .to_owned()
+        ));
+        assert_eq!(conflicts.len(), 2, "{conflicts:?}");
+    }
+
+    /// The titles in the "Linked from" section of the article
+    fn linked_from(website: &Website, slug: &str) -> Vec<String> {
+        let article = article(website, slug);
+        article
+            .split_once("<h2>Linked from</h2>")
+            .map(|(_body, section)| {
+                section
+                    .split("\">")
+                    .skip(1)
+                    .filter_map(|item| item.split_once("</a>"))
+                    .map(|(title, _rest)| title.to_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn editing_an_article_updates_the_backlinks() {
+        let (root, mut website) = website(&[
+            ("a.md", "# A\n\nSee [[B]]"),
+            ("b.md", "# B"),
+            ("c.md", "# C"),
+        ]);
+        assert_eq!(linked_from(&website, "b"), ["A"]);
+        assert!(linked_from(&website, "c").is_empty());
+        let a_path = root.path().join("articles/a.md");
+        let edit_a = |website: &mut Website, contents: &str| {
+            fs::write(&a_path, contents).unwrap();
+            website.article_path_changed(&a_path);
+        };
+        edit_a(&mut website, "# A\n\nSee [C](c.md) instead");
+        assert!(linked_from(&website, "b").is_empty());
+        assert_eq!(linked_from(&website, "c"), ["A"]);
+        edit_a(&mut website, "# Renamed A\n\nSee [[B]] and [[C]]");
+        assert_eq!(linked_from(&website, "b"), ["Renamed A"]);
+        assert_eq!(linked_from(&website, "c"), ["Renamed A"]);
+        fs::remove_file(&a_path).unwrap();
+        website.article_path_changed(&a_path);
+        assert!(linked_from(&website, "b").is_empty());
+        assert!(linked_from(&website, "c").is_empty());
+    }
+
+    #[test]
+    fn percent_encoded_links_are_not_broken() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("articles")).unwrap();
+        fs::create_dir(root.path().join("files")).unwrap();
+        fs::write(
+            root.path().join("articles/post.md"),
+            "[photo](/my%20photo.png) [missing](/my%20missing%20photo.png)",
+        )
+        .unwrap();
+        fs::write(root.path().join("files/my photo.png"), "not really a photo").unwrap();
+        let mut config = SiteConfig::for_tests(root.path());
+        config.fingerprint_files = false;
+        config.image_widths = Vec::new();
+        let website = Website::new(Arc::new(Mutex::new(config)), None);
+        let broken_links: Vec<_> = website
+            .diagnostics()
+            .into_iter()
+            .filter_map(|diagnostic| match diagnostic.problem {
+                Problem::BrokenLink(link) => Some(link),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(broken_links, ["/my%20missing%20photo.png"]);
+    }
+}